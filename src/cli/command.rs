@@ -1,5 +1,16 @@
+use std::ops::RangeInclusive;
+
 use clap::{command, Args, Parser, Subcommand};
 
+use crate::{
+    dry_run::{
+        hardfork::Hardfork,
+        row_usage::{MAX_DEGREE, MIN_DEGREE},
+        rpc::{DEFAULT_CHAIN_ID, DEFAULT_GAS_CAP},
+    },
+    run::{parse_block_range, DEFAULT_TRACE_CACHE, EXPECTED_TRACE_VERSION},
+};
+
 #[derive(Parser)]
 pub struct Cli {
     #[command(subcommand)]
@@ -10,6 +21,18 @@ pub struct Cli {
 pub enum Commands {
     Run(RunArgs),
     DryRun(DryRunArgs),
+    Fuzz(FuzzArgs),
+    RowUsage(RowUsageArgs),
+    Replay(ReplayArgs),
+    ServeRpc(ServeRpcArgs),
+    Selectors(SelectorsArgs),
+    ValidateTrace(ValidateTraceArgs),
+    TraceStats(TraceStatsArgs),
+    AssembleTrace(AssembleTraceArgs),
+    ConvertTrace(ConvertTraceArgs),
+    FetchTraces(FetchTracesArgs),
+    MinimizeTrace(MinimizeTraceArgs),
+    DiffTrace(DiffTraceArgs),
 }
 
 #[derive(Args)]
@@ -23,6 +46,27 @@ pub struct RunArgs {
     #[arg(long)]
     pub batch_dir: Option<String>,
 
+    /// Glob patterns of batch files to leave out, relative to the batch dir.
+    #[arg(long, requires = "batch_dir")]
+    pub exclude: Vec<String>,
+
+    /// File listing the batch files to prove, one per line in block order,
+    /// instead of every trace sorted by block number.
+    #[arg(long, requires = "batch_dir")]
+    pub order: Option<String>,
+
+    /// Scroll JSON-RPC endpoint to fetch the `--blocks` from.
+    #[arg(long, conflicts_with = "batch_dir", requires = "blocks")]
+    pub rpc: Option<String>,
+
+    /// Blocks to prove, `<from>..<to>` both included.
+    #[arg(long, requires = "rpc", value_parser = parse_block_range)]
+    pub blocks: Option<RangeInclusive<u64>>,
+
+    /// Cache directory of fetched traces.
+    #[arg(long, default_value = DEFAULT_TRACE_CACHE)]
+    pub cache_dir: String,
+
     #[arg(long, default_value = "output")]
     pub output_dir: String,
 
@@ -41,6 +85,86 @@ pub struct RunArgs {
 
 #[derive(Args)]
 pub struct DryRunArgs {
+    #[arg(short, long)]
+    pub calldata: Option<String>,
+    #[arg(short, long)]
+    pub bytecode: Option<String>,
+    #[arg(short = 'd', long)]
+    pub hardcode: Option<String>,
+    /// Case file(s) to run, one run per file. `.json` files are structured
+    /// inputs or Foundry/Hardhat artifacts, run with `--calldata`.
+    #[arg(short, long)]
+    pub file: Vec<String>,
+    /// Hardfork rules to execute with, defaults to the one the prover supports.
+    #[arg(long, value_enum, default_value_t)]
+    pub hardfork: Hardfork,
+    /// Find the minimal gas limit the call succeeds with instead of running it once.
+    #[arg(long)]
+    pub estimate_gas: bool,
+    /// Gas limit capping `--estimate-gas`, on top of the gas limit of a case.
+    #[arg(long, requires = "estimate_gas", default_value_t = DEFAULT_GAS_CAP)]
+    pub gas_cap: u64,
+    /// Also execute through a standard revm EVM and report discrepancies.
+    #[arg(long)]
+    pub differential: bool,
+    /// Directory of JSON/YAML test vectors to run as a conformance suite.
+    #[arg(long)]
+    pub suite: Option<String>,
+    /// Where to write the JUnit XML report of `--suite`.
+    #[arg(long, requires = "suite")]
+    pub junit: Option<String>,
+    /// EIP-2930 access list item `<address>[:<slot>,<slot>...]`, repeatable.
+    #[arg(long)]
+    pub access_list: Vec<String>,
+    /// Memory in bytes all call frames of a run may use together.
+    #[cfg(feature = "memory_limit")]
+    #[arg(long, default_value_t = crate::dry_run::bytecode_run::DEFAULT_MEMORY_LIMIT)]
+    pub memory_limit: u64,
+    /// JSON/YAML list of steps to execute against one shared state.
+    #[arg(long)]
+    pub script: Option<String>,
+    /// Session state to start the script from.
+    #[arg(long, requires = "script")]
+    pub state: Option<String>,
+    /// Where to write the session state once the script is done.
+    #[arg(long, requires = "script")]
+    pub export_state: Option<String>,
+
+    /// Record executed program counters across all runs.
+    #[arg(long)]
+    pub coverage: bool,
+    /// solc `srcmap`/`srcmap-runtime` matching the executed bytecode.
+    #[arg(long, requires = "coverage")]
+    pub srcmap: Option<String>,
+    /// Solidity sources, in solc source index order.
+    #[arg(long, requires = "srcmap")]
+    pub source: Vec<String>,
+    /// Where to write the lcov report when a source map is given.
+    #[arg(long, default_value = "lcov.info")]
+    pub lcov: String,
+}
+
+#[derive(Args)]
+pub struct FuzzArgs {
+    /// Number of random cases to run.
+    #[arg(long, default_value_t = 10_000)]
+    pub cases: u32,
+    /// Gas limit of each run.
+    #[arg(long, default_value_t = 10_000_000)]
+    pub gas_limit: u64,
+    /// Hardfork rules both EVMs execute with, defaults to the one the prover supports.
+    #[arg(long, value_enum, default_value_t)]
+    pub hardfork: Hardfork,
+    /// A run taking longer than this is reported as a hang.
+    #[arg(long, default_value_t = 5_000)]
+    pub timeout_ms: u64,
+}
+
+#[derive(Args)]
+pub struct RowUsageArgs {
+    /// Block traces to measure exactly, instead of estimating a dry run.
+    #[arg(long)]
+    pub trace_path: Vec<String>,
     #[arg(short, long)]
     pub calldata: Option<String>,
     #[arg(short, long)]
@@ -49,4 +173,171 @@ pub struct DryRunArgs {
     pub hardcode: Option<String>,
     #[arg(short, long)]
     pub file: Option<String>,
+    #[arg(long, value_enum, default_value_t)]
+    pub hardfork: Hardfork,
+    /// Degree of the chunk circuit to compare against.
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(MIN_DEGREE as i64..=MAX_DEGREE as i64))]
+    pub degree: u32,
+}
+
+#[derive(Args)]
+pub struct ReplayArgs {
+    /// Block traces whose transactions to re-execute.
+    #[arg(long, env = "TRACE_PATH", default_value = "./traces/calculation.json")]
+    pub trace_path: Vec<String>,
+    #[arg(long, value_enum, default_value_t)]
+    pub hardfork: Hardfork,
+}
+
+#[derive(Args)]
+pub struct ValidateTraceArgs {
+    /// Block traces to check.
+    #[arg(long, env = "TRACE_PATH", default_value = "./traces/calculation.json")]
+    pub trace_path: Vec<String>,
+    /// Trace version prefix the prover reads.
+    #[arg(long, default_value = EXPECTED_TRACE_VERSION)]
+    pub trace_version: String,
+    /// Chain id the traces must be for.
+    #[arg(long)]
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Args)]
+pub struct TraceStatsArgs {
+    /// Block traces to report on, side by side.
+    #[arg(long, env = "TRACE_PATH", default_value = "./traces/calculation.json")]
+    pub trace_path: Vec<String>,
+    /// Report on every trace under this directory, as a whole.
+    #[arg(long)]
+    pub batch_dir: Option<String>,
+    /// Number of most executed opcodes to list.
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+}
+
+#[derive(Args)]
+pub struct AssembleTraceArgs {
+    /// `eth_getBlockByNumber` result with full transactions.
+    #[arg(long)]
+    pub block: String,
+    /// `debug_traceBlockByNumber` result, or the struct logger results of
+    /// the block's transactions.
+    #[arg(long)]
+    pub traces: String,
+    /// `prestateTracer` results of the block's transactions, in diff mode
+    /// for an exact post-state.
+    #[arg(long)]
+    pub prestate: String,
+    /// Chain id, the one of the transactions by default.
+    #[arg(long)]
+    pub chain_id: Option<u64>,
+    /// File to write the block trace to, stdout by default.
+    #[arg(short, long)]
+    pub output: Option<String>,
+}
+
+#[derive(Args)]
+pub struct MinimizeTraceArgs {
+    /// Block trace failing mock proving, JSON or compact.
+    #[arg(long)]
+    pub trace_path: String,
+    /// File to write the minimized block trace to.
+    #[arg(short, long, default_value = "minimized.json")]
+    pub output: String,
+    /// Drop transactions from the trace as is instead of regenerating it
+    /// in the dry run, leaving calldata alone.
+    #[arg(long)]
+    pub no_regenerate: bool,
+    /// Keep reductions failing in any way, not only with the original
+    /// failure.
+    #[arg(long)]
+    pub any_failure: bool,
+    #[arg(long, value_enum, default_value_t)]
+    pub hardfork: Hardfork,
+}
+
+#[derive(Args)]
+pub struct DiffTraceArgs {
+    /// Block trace to compare, JSON or compact, such as the one l2geth
+    /// produced.
+    #[arg(long)]
+    pub left: String,
+    /// Block trace to compare it with, such as one regenerated from a dry
+    /// run.
+    #[arg(long)]
+    pub right: String,
+}
+
+#[derive(Args)]
+pub struct ConvertTraceArgs {
+    /// Block traces to convert, JSON or compact.
+    #[arg(long, required = true)]
+    pub trace_path: Vec<String>,
+    /// Directory to write the converted traces to, next to the inputs by
+    /// default.
+    #[arg(long)]
+    pub output_dir: Option<String>,
+    /// Convert compact traces back to JSON.
+    #[arg(long)]
+    pub to_json: bool,
+    /// zstd compression level of compact traces, 0 to store them
+    /// uncompressed.
+    #[arg(long, default_value_t = 3)]
+    pub level: i32,
+}
+
+#[derive(Args)]
+pub struct FetchTracesArgs {
+    /// Scroll JSON-RPC endpoint.
+    #[arg(long)]
+    pub rpc: String,
+    /// Blocks to fetch, `<from>..<to>` both included.
+    #[arg(long, value_parser = parse_block_range)]
+    pub blocks: RangeInclusive<u64>,
+    /// Cache directory of fetched traces, one `<block hash>.json` each.
+    #[arg(long, default_value = DEFAULT_TRACE_CACHE)]
+    pub cache_dir: String,
+    /// Retries of a request failing on the transport or with a 429 or 5xx
+    /// status.
+    #[arg(long, default_value_t = 3)]
+    pub retries: u32,
+    /// Fetch traces by number without looking the block hash up first.
+    /// Cached traces are then fetched again.
+    #[arg(long)]
+    pub no_block_lookup: bool,
+}
+
+#[derive(Args)]
+pub struct ServeRpcArgs {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8545")]
+    pub addr: String,
+    /// State to serve, as exported by `dry-run --script --export-state`.
+    #[arg(long)]
+    pub state: Option<String>,
+    #[arg(long, default_value_t = DEFAULT_CHAIN_ID)]
+    pub chain_id: u64,
+    #[arg(long, value_enum, default_value_t)]
+    pub hardfork: Hardfork,
+    /// Transaction gas a call may use at most, also given to calls without `gas`.
+    #[arg(long, default_value_t = DEFAULT_GAS_CAP)]
+    pub gas_cap: u64,
+    /// Memory in bytes all call frames of a run may use together.
+    #[cfg(feature = "memory_limit")]
+    #[arg(long, default_value_t = crate::dry_run::bytecode_run::DEFAULT_MEMORY_LIMIT)]
+    pub memory_limit: u64,
+}
+
+#[derive(Args)]
+pub struct SelectorsArgs {
+    /// Deployed bytecode in hex.
+    #[arg(short, long)]
+    pub bytecode: Option<String>,
+    /// Case file whose bytecode to analyze.
+    #[arg(short, long)]
+    pub file: Option<String>,
+    /// 4byte style signature file, `<selector> <signature>` or
+    /// `<signature>` per line, or a JSON map of selectors to signatures.
+    #[arg(long)]
+    pub signatures: Option<String>,
 }
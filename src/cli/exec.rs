@@ -2,56 +2,124 @@ use std::path::Path;
 
 use chrono::Utc;
 use log::info;
-use prover::{
-    utils::{get_block_trace_from_file, init_env_and_log},
-    BlockTrace,
-};
+use prover::{utils::init_env_and_log, BlockTrace};
 
-use crate::run::load_batch_traces;
+use crate::run::{
+    load_batch_traces, load_block_trace, load_trace_chain, BatchOptions, FetchOptions,
+};
 
 use super::command::{Cli, Commands, RunArgs};
 
+mod assemble_trace;
+mod convert_trace;
+mod diff_trace;
 mod dry_run;
+mod fetch_traces;
+mod fuzz;
+mod minimize_trace;
+mod replay;
+mod row_usage;
 mod run;
+mod selectors;
+mod serve_rpc;
+mod trace_stats;
+mod validate_trace;
 
 pub fn match_operation(cli: &Cli) {
     match &cli.command {
         Commands::DryRun(args) => {
-            dry_run::exec_dry_run(
-                args.calldata.as_deref(),
-                args.bytecode.as_deref(),
-                args.hardcode.as_deref(),
-                args.file.as_deref(),
-            );
+            dry_run::exec_dry_run(args);
+        }
+        Commands::Fuzz(args) => {
+            fuzz::exec_fuzz(args);
+        }
+        Commands::RowUsage(args) => {
+            row_usage::exec_row_usage(args);
+        }
+        Commands::Replay(args) => {
+            replay::exec_replay(args);
+        }
+        Commands::ServeRpc(args) => {
+            serve_rpc::exec_serve_rpc(args);
+        }
+        Commands::Selectors(args) => {
+            selectors::exec_selectors(args);
+        }
+        Commands::AssembleTrace(args) => {
+            assemble_trace::exec_assemble_trace(args);
+        }
+        Commands::ConvertTrace(args) => {
+            convert_trace::exec_convert_trace(args);
+        }
+        Commands::DiffTrace(args) => {
+            diff_trace::exec_diff_trace(args);
+        }
+        Commands::FetchTraces(args) => {
+            fetch_traces::exec_fetch_traces(args);
+        }
+        Commands::MinimizeTrace(args) => {
+            minimize_trace::exec_minimize_trace(args);
+        }
+        Commands::TraceStats(args) => {
+            trace_stats::exec_trace_stats(args);
+        }
+        Commands::ValidateTrace(args) => {
+            validate_trace::exec_validate_trace(args);
         }
         Commands::Run(args) => {
             let output_dir = args.init();
-            if args.mock {
-                run::run_mock_prove(args.get_block_traces());
-            } else {
-                run::run_chunk_prove_verify(
-                    args.get_block_traces(),
-                    &output_dir,
-                    &args.chunk_params_dir,
-                    &args.chunk_assets_dir,
-                    &args.chunk_vk_filename,
-                );
-            }
+            let (names, block_traces) = args.get_block_traces();
+            run::run_chunks(args, names, block_traces, &output_dir);
         }
     }
 }
 
 impl RunArgs {
-    pub fn get_block_traces(&self) -> Vec<BlockTrace> {
-        if let Some(batch_dir) = &self.batch_dir {
+    /// Trace file names and their traces, in block order.
+    pub fn get_block_traces(&self) -> (Vec<String>, Vec<BlockTrace>) {
+        if let (Some(rpc), Some(blocks)) = (&self.rpc, &self.blocks) {
+            let options = FetchOptions {
+                cache_dir: self.cache_dir.clone().into(),
+                ..Default::default()
+            };
+            let files = fetch_traces::fetch_traces(rpc, blocks.clone(), options);
+            match load_trace_chain(files, false) {
+                Ok(names_and_traces) => names_and_traces,
+                Err(e) => {
+                    log::error!("{e}");
+                    std::process::exit(1);
+                }
+            }
+        } else if let Some(batch_dir) = &self.batch_dir {
             info!("use batch chunk files under dir: {:?}", batch_dir);
-            load_batch_traces(batch_dir).1
+            let order = self.order.as_ref().map(|path| {
+                std::fs::read_to_string(path)
+                    .unwrap()
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(String::from)
+                    .collect()
+            });
+            let options = BatchOptions {
+                exclude: self.exclude.clone(),
+                order,
+            };
+            match load_batch_traces(batch_dir, &options) {
+                Ok(names_and_traces) => names_and_traces,
+                Err(e) => {
+                    log::error!("{e}");
+                    std::process::exit(1);
+                }
+            }
         } else {
             info!("use block traces files: {:?}", self.trace_path);
-            self.trace_path
+            let traces = self
+                .trace_path
                 .iter()
-                .map(get_block_trace_from_file)
-                .collect()
+                .map(|path| load_block_trace(path).unwrap())
+                .collect();
+            (self.trace_path.clone(), traces)
         }
     }
 
@@ -64,7 +132,7 @@ impl RunArgs {
     }
 
     pub fn mode(&self) -> &str {
-        if self.batch_dir.is_some() || self.trace_path.len() > 1 {
+        if self.batch_dir.is_some() || self.blocks.is_some() || self.trace_path.len() > 1 {
             "multi"
         } else {
             "single"
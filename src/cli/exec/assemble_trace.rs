@@ -0,0 +1,15 @@
+use crate::cli::command::AssembleTraceArgs;
+use crate::run::assemble_trace_files;
+
+pub(crate) fn exec_assemble_trace(args: &AssembleTraceArgs) {
+    let trace =
+        assemble_trace_files(&args.block, &args.traces, &args.prestate, args.chain_id).unwrap();
+    let json = serde_json::to_string_pretty(&trace).unwrap();
+    match &args.output {
+        Some(path) => {
+            std::fs::write(path, json).unwrap();
+            println!("Block trace written to {path}");
+        }
+        None => println!("{json}"),
+    }
+}
@@ -0,0 +1,28 @@
+use std::path::Path;
+
+use crate::cli::command::ConvertTraceArgs;
+use crate::run::{convert_trace, COMPACT_TRACE_EXTENSION};
+
+pub(crate) fn exec_convert_trace(args: &ConvertTraceArgs) {
+    let extension = if args.to_json {
+        "json"
+    } else {
+        COMPACT_TRACE_EXTENSION
+    };
+    let level = (args.level != 0).then_some(args.level);
+    for input in &args.trace_path {
+        let input_path = Path::new(input);
+        let file_name = input_path.with_extension(extension);
+        let output = match &args.output_dir {
+            Some(dir) => Path::new(dir).join(file_name.file_name().unwrap()),
+            None => file_name,
+        };
+        let output = output.to_str().unwrap();
+        convert_trace(input, output, level).unwrap();
+        println!(
+            "{input} ({} bytes) -> {output} ({} bytes)",
+            std::fs::metadata(input).unwrap().len(),
+            std::fs::metadata(output).unwrap().len()
+        );
+    }
+}
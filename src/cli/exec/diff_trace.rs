@@ -0,0 +1,15 @@
+use crate::cli::command::DiffTraceArgs;
+use crate::run::{diff_report, diff_traces, read_trace_json};
+
+pub(crate) fn exec_diff_trace(args: &DiffTraceArgs) {
+    let left = read_trace_json(&args.left).unwrap();
+    let right = read_trace_json(&args.right).unwrap();
+    let diff = diff_traces(&left, &right);
+    if diff.is_empty() {
+        println!("{} and {} match", args.left, args.right);
+        return;
+    }
+    println!("- {}\n+ {}", args.left, args.right);
+    print!("{}", diff_report(&diff));
+    std::process::exit(1);
+}
@@ -1,43 +1,244 @@
-use crate::dry_run::bytecode_run::bytecode_run;
+use crate::cli::command::DryRunArgs;
+use crate::dry_run::{
+    bytecode_run::{bytecode_run_with_config, bytecode_run_with_coverage, RunConfig},
+    coverage::{self, Coverage, Source},
+    differential::differential_run,
+    estimate::estimate_gas,
+    input,
+    session::{self, decimal_or_hex, ScriptStep, Session},
+    suite,
+};
+use revm_primitives::{B160, U256};
 use std::{
-    fs::File,
+    collections::HashMap,
+    fs::{self, File},
     io::{BufRead, BufReader},
+    str::FromStr,
 };
 
-pub(crate) fn exec_dry_run(
-    calldata: Option<&str>,
-    bytecode: Option<&str>,
-    hardcode: Option<&str>,
-    file: Option<&str>,
-) {
-    let (calldata, bytecode, hardcode) = if file.is_some() {
-        read_from_file(file.unwrap()).unwrap()
-    } else {
-        parse_from_args(
-            calldata.expect("should have calldata"),
-            bytecode.expect("should have bytecode"),
-            hardcode,
-        )
-        .unwrap()
+pub(crate) type Case = (Vec<u8>, Vec<u8>, Option<Vec<u8>>);
+
+pub(crate) fn exec_dry_run(args: &DryRunArgs) {
+    let config = RunConfig {
+        hardfork: args.hardfork,
+        access_list: args
+            .access_list
+            .iter()
+            .map(|item| parse_access_list_item(item))
+            .collect::<anyhow::Result<_>>()
+            .unwrap(),
+        #[cfg(feature = "memory_limit")]
+        memory_limit: args.memory_limit,
+        ..Default::default()
     };
+    if let Some(dir) = &args.suite {
+        exec_suite(dir, args.junit.as_deref(), &config);
+        return;
+    }
+    if let Some(script) = &args.script {
+        exec_script(
+            script,
+            args.state.as_deref(),
+            args.export_state.as_deref(),
+            config,
+        )
+        .unwrap();
+        return;
+    }
 
-    match bytecode_run(calldata, bytecode, hardcode) {
-        Ok(r) => {
-            println!(
-                "Bytecode exec successfully, result (in hex):\n{}",
-                hex::encode(r)
+    let cases: Vec<(Case, RunConfig)> = if !args.file.is_empty() {
+        args.file
+            .iter()
+            .map(|file| read_input(file, args.calldata.as_deref(), &config).unwrap())
+            .collect()
+    } else {
+        vec![(
+            parse_from_args(
+                args.calldata.as_deref().expect("should have calldata"),
+                args.bytecode.as_deref().expect("should have bytecode"),
+                args.hardcode.as_deref(),
             )
+            .unwrap(),
+            config.clone(),
+        )]
+    };
+
+    if args.differential {
+        exec_differential(cases);
+        return;
+    }
+    if args.estimate_gas {
+        for ((calldata, bytecode, hardcode), config) in cases {
+            let config = RunConfig {
+                gas_limit: args.gas_cap.min(config.gas_limit),
+                ..config
+            };
+            match estimate_gas(calldata, bytecode, hardcode, &config) {
+                Ok(gas) => println!("Estimated gas: {gas}"),
+                Err(e) => println!("Gas estimation failed, reason: {e}"),
+            }
         }
-        Err(e) => {
-            println!("Bytecode exec failed, reason: {}", e.to_string())
+        return;
+    }
+
+    let mut coverage = Coverage::default();
+    for ((calldata, bytecode, hardcode), config) in cases.iter().cloned() {
+        let result = if args.coverage {
+            bytecode_run_with_coverage(calldata, bytecode, hardcode, &config, &mut coverage)
+        } else {
+            bytecode_run_with_config(calldata, bytecode, hardcode, &config)
+        };
+        match result {
+            Ok(r) => {
+                println!(
+                    "Bytecode exec successfully, result (in hex):\n{}",
+                    hex::encode(r.return_data)
+                );
+                println!("Gas used: {} (execution: {})", r.gas_used, r.execution_gas);
+            }
+            Err(e) => {
+                println!("Bytecode exec failed, reason: {}", e.to_string())
+            }
+        };
+    }
+
+    if args.coverage {
+        print!("Coverage:\n{}", coverage.summary());
+        if let Some(srcmap) = &args.srcmap {
+            write_lcov(&coverage, &cases, srcmap, &args.source, &args.lcov).unwrap();
+            println!("lcov report written to {}", args.lcov);
         }
-    };
+    }
 }
-fn convert(
-    calldata: &str,
-    bytecode: &str,
-    hardcode: Option<&str>,
-) -> anyhow::Result<(Vec<u8>, Vec<u8>, Option<Vec<u8>>)> {
+
+fn exec_differential(cases: Vec<(Case, RunConfig)>) {
+    let mut diverged = false;
+    for ((calldata, bytecode, hardcode), config) in cases {
+        let report = differential_run(calldata, bytecode, hardcode, &config);
+        if report.discrepancies.is_empty() {
+            println!("Dry run agrees with the reference EVM");
+        } else {
+            diverged = true;
+            println!("Dry run diverges from the reference EVM:");
+            for discrepancy in &report.discrepancies {
+                println!("  {discrepancy}");
+            }
+        }
+    }
+    if diverged {
+        std::process::exit(1);
+    }
+}
+
+fn exec_suite(dir: &str, junit: Option<&str>, config: &RunConfig) {
+    let vectors = suite::load_suite(dir).unwrap();
+    let reports = suite::run_suite(&vectors, config);
+    print!("{}", suite::table(&reports));
+
+    if let Some(junit) = junit {
+        fs::write(junit, suite::junit_xml(&suite::suite_name(dir), &reports)).unwrap();
+        println!("JUnit report written to {junit}");
+    }
+    if reports.iter().any(|r| !r.passed()) {
+        std::process::exit(1);
+    }
+}
+
+fn exec_script(
+    script: &str,
+    state: Option<&str>,
+    export_state: Option<&str>,
+    config: RunConfig,
+) -> anyhow::Result<()> {
+    let mut session = Session::new(config);
+    if let Some(state) = state {
+        session.import_state(&fs::read_to_string(state)?)?;
+    }
+    let mut snapshots = HashMap::new();
+    for step in session::load_script(script)? {
+        match step {
+            ScriptStep::SetCode { address, code } => {
+                session.account_mut(B160::from_str(&address)?).code =
+                    hex::decode(code.trim_start_matches("0x"))?;
+            }
+            ScriptStep::SetBalance { address, balance } => {
+                session.account_mut(B160::from_str(&address)?).balance = decimal_or_hex(&balance)?;
+            }
+            ScriptStep::SetStorage {
+                address,
+                slot,
+                value,
+            } => {
+                session
+                    .account_mut(B160::from_str(&address)?)
+                    .storage
+                    .insert(decimal_or_hex(&slot)?, decimal_or_hex(&value)?);
+            }
+            ScriptStep::Call { from, to, calldata } => {
+                let from = from.as_deref().map_or(Ok(B160::zero()), B160::from_str)?;
+                let calldata = hex::decode(calldata.trim_start_matches("0x"))?;
+                match session.call(from, B160::from_str(&to)?, calldata) {
+                    Ok(r) => println!(
+                        "Call to {to} succeeded, result (in hex): {}, gas used: {}",
+                        hex::encode(r.return_data),
+                        r.gas_used
+                    ),
+                    Err(e) => println!("Call to {to} failed, reason: {e}"),
+                }
+            }
+            ScriptStep::Snapshot(name) => {
+                snapshots.insert(name, session.snapshot());
+            }
+            ScriptStep::Revert(name) => {
+                let id = snapshots
+                    .remove(&name)
+                    .ok_or_else(|| anyhow::anyhow!("unknown snapshot {name}"))?;
+                session.revert(id);
+                snapshots.retain(|_, later| *later < id);
+                println!("Reverted to snapshot {name}");
+            }
+        }
+    }
+
+    if let Some(export_state) = export_state {
+        fs::write(export_state, session.export_state()?)?;
+        println!("Session state written to {export_state}");
+    }
+    Ok(())
+}
+
+/// Write the lcov report of `coverage`, mapped through the source map of the
+/// bytecode all `cases` run.
+fn write_lcov(
+    coverage: &Coverage,
+    cases: &[(Case, RunConfig)],
+    srcmap: &str,
+    sources: &[String],
+    lcov: &str,
+) -> anyhow::Result<()> {
+    let bytecode = &cases[0].0 .1;
+    anyhow::ensure!(
+        cases.iter().all(|((_, code, _), _)| code == bytecode),
+        "a source map covers a single contract, but the cases run different bytecodes"
+    );
+    let srcmap = coverage::parse_source_map(&fs::read_to_string(srcmap)?)?;
+    let sources = sources
+        .iter()
+        .map(|path| {
+            Ok(Source {
+                path: path.clone(),
+                content: fs::read_to_string(path)?,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    fs::write(
+        lcov,
+        coverage::to_lcov(coverage, bytecode, &srcmap, &sources)?,
+    )?;
+    Ok(())
+}
+
+fn convert(calldata: &str, bytecode: &str, hardcode: Option<&str>) -> anyhow::Result<Case> {
     Ok((
         hex::decode(calldata.trim_start_matches("0x"))?,
         hex::decode(bytecode.trim_start_matches("0x"))?,
@@ -47,7 +248,22 @@ fn convert(
     ))
 }
 
-fn read_from_file(file: &str) -> anyhow::Result<(Vec<u8>, Vec<u8>, Option<Vec<u8>>)> {
+/// Read a case file, JSON inputs and artifacts giving their own config on top
+/// of `config`. `calldata` is used when the file has none.
+fn read_input(
+    file: &str,
+    calldata: Option<&str>,
+    config: &RunConfig,
+) -> anyhow::Result<(Case, RunConfig)> {
+    if !file.ends_with(".json") {
+        return Ok((read_from_file(file)?, config.clone()));
+    }
+    let input = input::load_input(file, calldata)?;
+    let config = input.config(config);
+    Ok(((input.calldata, input.bytecode, input.hardcode), config))
+}
+
+pub(crate) fn read_from_file(file: &str) -> anyhow::Result<Case> {
     let file = File::open(file)?;
     let mut reader = BufReader::new(file);
 
@@ -68,10 +284,22 @@ fn read_from_file(file: &str) -> anyhow::Result<(Vec<u8>, Vec<u8>, Option<Vec<u8
     )
 }
 
-fn parse_from_args(
+pub(crate) fn parse_from_args(
     calldata: &str,
     bytecode: &str,
     hardcode: Option<&str>,
-) -> anyhow::Result<(Vec<u8>, Vec<u8>, Option<Vec<u8>>)> {
+) -> anyhow::Result<Case> {
     convert(calldata, bytecode, hardcode)
 }
+
+/// Parse `<address>[:<slot>,<slot>...]` into an access list item.
+fn parse_access_list_item(item: &str) -> anyhow::Result<(B160, Vec<U256>)> {
+    let (address, slots) = item.split_once(':').unwrap_or((item, ""));
+    let address = B160::from_str(address)?;
+    let slots = slots
+        .split(',')
+        .filter(|slot| !slot.is_empty())
+        .map(|slot| Ok(U256::from_str(slot)?))
+        .collect::<anyhow::Result<_>>()?;
+    Ok((address, slots))
+}
@@ -0,0 +1,37 @@
+use std::{ops::RangeInclusive, path::PathBuf};
+
+use crate::cli::command::FetchTracesArgs;
+use crate::run::{FetchOptions, TraceFetcher};
+
+pub(crate) fn exec_fetch_traces(args: &FetchTracesArgs) {
+    let options = FetchOptions {
+        cache_dir: PathBuf::from(&args.cache_dir),
+        retries: args.retries,
+        block_lookup: !args.no_block_lookup,
+        ..Default::default()
+    };
+    for path in fetch_traces(&args.rpc, args.blocks.clone(), options) {
+        println!("{}", path.display());
+    }
+}
+
+/// Cached trace files of `blocks`, exiting on the first block that can't
+/// be fetched.
+pub(crate) fn fetch_traces(
+    rpc: &str,
+    blocks: RangeInclusive<u64>,
+    options: FetchOptions,
+) -> Vec<PathBuf> {
+    log::info!(
+        "fetch blocks {blocks:?} from {rpc} into {}",
+        options.cache_dir.display()
+    );
+    let result = TraceFetcher::new(rpc, options).and_then(|fetcher| fetcher.fetch_blocks(blocks));
+    match result {
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
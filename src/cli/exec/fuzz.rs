@@ -0,0 +1,27 @@
+use crate::cli::command::FuzzArgs;
+use crate::dry_run::{
+    bytecode_run::RunConfig,
+    fuzz::{fuzz, FuzzConfig},
+};
+use std::time::Duration;
+
+pub(crate) fn exec_fuzz(args: &FuzzArgs) {
+    let config = FuzzConfig {
+        cases: args.cases,
+        run: RunConfig {
+            gas_limit: args.gas_limit,
+            hardfork: args.hardfork,
+            ..Default::default()
+        },
+        timeout: Duration::from_millis(args.timeout_ms),
+        ..Default::default()
+    };
+
+    match fuzz(&config) {
+        Ok(()) => println!("Fuzzing passed {} cases", config.cases),
+        Err(e) => {
+            println!("Fuzzing found a failing case:\n{e}");
+            std::process::exit(1);
+        }
+    }
+}
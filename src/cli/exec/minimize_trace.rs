@@ -0,0 +1,59 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use prover::{zkevm::circuit::SuperCircuit, BlockTrace};
+use serde_json::{json, Value};
+
+use crate::cli::command::MinimizeTraceArgs;
+use crate::dry_run::bytecode_run::RunConfig;
+use crate::run::{minimize_trace, read_trace_json, MinimizeOptions};
+
+pub(crate) fn exec_minimize_trace(args: &MinimizeTraceArgs) {
+    let trace = read_trace_json(&args.trace_path).unwrap();
+    let options = MinimizeOptions {
+        no_regenerate: args.no_regenerate,
+        any_failure: args.any_failure,
+        config: RunConfig {
+            hardfork: args.hardfork,
+            ..Default::default()
+        },
+    };
+
+    let minimized = match minimize_trace(trace, &options, mock_prove_failure) {
+        Ok(minimized) => minimized,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let json = json!({ "jsonrpc": "2.0", "id": 1, "result": minimized.trace });
+    std::fs::write(&args.output, serde_json::to_string_pretty(&json).unwrap()).unwrap();
+
+    println!("Checked {} traces", minimized.attempts);
+    for step in &minimized.steps {
+        println!("  {step}");
+    }
+    println!("Minimized trace written to {}, failing with:", args.output);
+    println!("{}", minimized.failure);
+}
+
+/// Failure of mock proving `trace`, panics included.
+fn mock_prove_failure(trace: &Value) -> Option<String> {
+    let block_trace: BlockTrace = match serde_json::from_value(trace.clone()) {
+        Ok(block_trace) => block_trace,
+        Err(e) => return Some(format!("invalid block trace: {e}")),
+    };
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        prover::inner::Prover::<SuperCircuit>::mock_prove_target_circuit_batch(&[block_trace])
+    }));
+    match result {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(format!("{e:?}")),
+        Err(panic) => Some(
+            panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                .map_or_else(|| "panicked".to_string(), |s| format!("panicked: {s}")),
+        ),
+    }
+}
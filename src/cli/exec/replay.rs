@@ -0,0 +1,45 @@
+use crate::cli::command::ReplayArgs;
+use crate::dry_run::{bytecode_run::RunConfig, replay::replay_trace_file};
+
+pub(crate) fn exec_replay(args: &ReplayArgs) {
+    let config = RunConfig {
+        hardfork: args.hardfork,
+        ..Default::default()
+    };
+    let mut diverged = false;
+    for path in &args.trace_path {
+        println!("Replaying {path}");
+        for (index, replay) in replay_trace_file(path, &config)
+            .unwrap()
+            .into_iter()
+            .enumerate()
+        {
+            let replay = match replay {
+                Ok(replay) => replay,
+                Err(e) => {
+                    println!("  tx {index}: not replayed, {e}");
+                    continue;
+                }
+            };
+            match &replay.divergence {
+                None => println!(
+                    "  tx {index} {}: {} steps match",
+                    replay.tx_hash, replay.steps_matched
+                ),
+                Some(divergence) => {
+                    diverged = true;
+                    println!(
+                        "  tx {index} {}: diverges after {} matching steps\n{divergence}",
+                        replay.tx_hash, replay.steps_matched
+                    );
+                }
+            }
+            if let Err(reason) = &replay.result {
+                println!("    dry run failed: {reason}");
+            }
+        }
+    }
+    if diverged {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::cli::command::RowUsageArgs;
+use crate::dry_run::{
+    bytecode_run::{bytecode_run_with_profile, RunConfig},
+    row_usage,
+};
+use crate::run::{load_block_trace, trace_row_usage};
+
+use super::dry_run::{parse_from_args, read_from_file};
+
+pub(crate) fn exec_row_usage(args: &RowUsageArgs) {
+    let usage = if !args.trace_path.is_empty() {
+        let block_traces = args
+            .trace_path
+            .iter()
+            .map(|path| load_block_trace(path).unwrap())
+            .collect();
+        println!("Row usage of traces {:?}:", args.trace_path);
+        trace_row_usage(&block_traces).unwrap()
+    } else {
+        let (calldata, bytecode, hardcode) = if let Some(file) = &args.file {
+            read_from_file(file).unwrap()
+        } else {
+            parse_from_args(
+                args.calldata.as_deref().expect("should have calldata"),
+                args.bytecode.as_deref().expect("should have bytecode"),
+                args.hardcode.as_deref(),
+            )
+            .unwrap()
+        };
+        let config = RunConfig {
+            hardfork: args.hardfork,
+            ..Default::default()
+        };
+        let (result, profile) = bytecode_run_with_profile(calldata, bytecode, hardcode, &config);
+        if let Err(e) = result {
+            println!("Bytecode exec failed, reason: {e}");
+        }
+        println!("Estimated row usage of dry run:");
+        profile.estimate()
+    };
+
+    print!("{}", row_usage::report(&usage, args.degree));
+}
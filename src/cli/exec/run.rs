@@ -1,30 +1,107 @@
-use crate::run::gen_and_verify_chunk_proofs;
-use prover::{utils::chunk_trace_to_witness_block, zkevm::circuit::SuperCircuit, BlockTrace};
-use std::env;
+use crate::cli::command::RunArgs;
+use crate::dry_run::row_usage::chunk_capacity;
+use crate::run::{
+    gen_and_verify_chunk_proofs, split_chunks, trace_row_usage, write_chunk_index, ChunkEntry,
+};
+use prover::{
+    config::LayerId, utils::chunk_trace_to_witness_block, zkevm::circuit::SuperCircuit, BlockTrace,
+};
+use std::{env, path::Path};
 
 pub(crate) fn run_mock_prove(block_traces: Vec<BlockTrace>) {
     prover::inner::Prover::<SuperCircuit>::mock_prove_target_circuit_batch(&block_traces).unwrap();
 }
 
+/// Split the traces into chunks fitting the configured chunk degree, prove
+/// each one and write the chunk index to `output_dir`.
+pub(crate) fn run_chunks(
+    args: &RunArgs,
+    names: Vec<String>,
+    mut block_traces: Vec<BlockTrace>,
+    output_dir: &str,
+) {
+    let degree = LayerId::Inner.degree();
+    let chunks = match split_chunks(
+        &names,
+        &block_traces,
+        chunk_capacity(degree).expect("chunk degree should leave usable rows"),
+        trace_row_usage,
+    ) {
+        Ok(chunks) => chunks,
+        Err(e) => {
+            log::error!("{e}");
+            std::process::exit(1);
+        }
+    };
+    log::info!(
+        "Split {} blocks into {} chunks of degree {degree}",
+        block_traces.len(),
+        chunks.len()
+    );
+
+    let mut zkevm_prover = (!args.mock).then(|| {
+        env::set_var("CHUNK_VK_FILENAME", &args.chunk_vk_filename);
+        let prover =
+            prover::zkevm::Prover::from_dirs(&args.chunk_params_dir, &args.chunk_assets_dir);
+        log::info!("Constructed zkevm prover");
+        prover
+    });
+    let mut index = vec![];
+    for (i, range) in chunks.into_iter().enumerate() {
+        let chunk_traces: Vec<BlockTrace> = block_traces.drain(..range.len()).collect();
+        let number =
+            |trace: Option<&BlockTrace>| trace.and_then(|t| t.header.number).map(|n| n.as_u64());
+        let mut entry = ChunkEntry {
+            index: i,
+            first_block: number(chunk_traces.first()),
+            last_block: number(chunk_traces.last()),
+            traces: names[range].to_vec(),
+            proof: None,
+        };
+        log::info!(
+            "Chunk {i}: blocks {:?} to {:?}",
+            entry.first_block,
+            entry.last_block
+        );
+
+        match &mut zkevm_prover {
+            None => run_mock_prove(chunk_traces),
+            Some(zkevm_prover) => {
+                let chunk_dir = Path::new(output_dir).join(format!("chunk_{i}"));
+                std::fs::create_dir_all(&chunk_dir).unwrap();
+                run_chunk_prove_verify(
+                    zkevm_prover,
+                    chunk_traces,
+                    chunk_dir.to_str().unwrap(),
+                    &args.chunk_params_dir,
+                    // Every chunk directory holds chunk 0, so that the
+                    // verifier finds its vk under `CHUNK_VK_FILENAME`.
+                    "0",
+                );
+                // `ChunkProof::dump` names the proof after the chunk.
+                entry.proof = Some(format!("chunk_{i}/chunk_0.json"));
+            }
+        }
+        index.push(entry);
+    }
+    write_chunk_index(output_dir, &index).unwrap();
+}
+
 pub(crate) fn run_chunk_prove_verify(
+    zkevm_prover: &mut prover::zkevm::Prover,
     chunk_trace: Vec<BlockTrace>,
     output_dir: &str,
     params_dir: &str,
-    assets_dir: &str,
-    chunk_vk_filename: &str,
+    name: &str,
 ) {
     let witness_block = chunk_trace_to_witness_block(chunk_trace).unwrap();
     log::info!("Got witness block");
 
-    env::set_var("CHUNK_VK_FILENAME", chunk_vk_filename);
-    let mut zkevm_prover = prover::zkevm::Prover::from_dirs(params_dir, assets_dir);
-    log::info!("Constructed zkevm prover");
-
     // Load or generate compression wide snark (layer-1).
     let layer1_snark = zkevm_prover
         .inner
-        .load_or_gen_last_chunk_snark("layer1", &witness_block, None, Some(&output_dir))
+        .load_or_gen_last_chunk_snark("layer1", &witness_block, None, Some(output_dir))
         .unwrap();
 
-    gen_and_verify_chunk_proofs(&mut zkevm_prover, layer1_snark, output_dir, params_dir);
+    gen_and_verify_chunk_proofs(zkevm_prover, layer1_snark, output_dir, params_dir, name);
 }
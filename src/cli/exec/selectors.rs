@@ -0,0 +1,41 @@
+use crate::cli::command::SelectorsArgs;
+use crate::dry_run::{
+    input,
+    selectors::{self, Signatures},
+};
+
+use super::dry_run::read_from_file;
+
+pub(crate) fn exec_selectors(args: &SelectorsArgs) {
+    let mut signatures = match &args.signatures {
+        Some(path) => selectors::load_signatures(path).unwrap(),
+        None => Signatures::new(),
+    };
+    let bytecode = match &args.file {
+        Some(file) if file.ends_with(".json") => {
+            let input = input::load_input(file, None).unwrap();
+            // The artifact's ABI names its own selectors.
+            for signature in input.artifact.iter().flat_map(|a| a.signatures()) {
+                let names = signatures
+                    .entry(selectors::selector_of(&signature))
+                    .or_default();
+                if !names.contains(&signature) {
+                    names.push(signature);
+                }
+            }
+            input.bytecode
+        }
+        Some(file) => read_from_file(file).unwrap().1,
+        None => {
+            let bytecode = args.bytecode.as_deref().expect("should have bytecode");
+            hex::decode(bytecode.trim_start_matches("0x")).unwrap()
+        }
+    };
+
+    let functions = selectors::extract_functions(&bytecode, &signatures);
+    if functions.is_empty() {
+        println!("No function dispatcher found");
+        return;
+    }
+    print!("{}", selectors::report(&functions));
+}
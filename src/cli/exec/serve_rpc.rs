@@ -0,0 +1,64 @@
+use std::{convert::Infallible, fs, net::SocketAddr, sync::Arc};
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use log::info;
+
+use crate::cli::command::ServeRpcArgs;
+use crate::dry_run::{
+    bytecode_run::RunConfig,
+    rpc::{internal_error, RpcHandler},
+    session::Session,
+};
+
+pub(crate) fn exec_serve_rpc(args: &ServeRpcArgs) {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let mut session = Session::new(RunConfig {
+        hardfork: args.hardfork,
+        #[cfg(feature = "memory_limit")]
+        memory_limit: args.memory_limit,
+        ..Default::default()
+    });
+    if let Some(state) = &args.state {
+        session
+            .import_state(&fs::read_to_string(state).unwrap())
+            .unwrap();
+    }
+    let handler = Arc::new(RpcHandler::new(session.config, args.chain_id, args.gas_cap));
+    let addr: SocketAddr = args.addr.parse().unwrap();
+
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        let make_service = make_service_fn(move |_| {
+            let handler = handler.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| serve(handler.clone(), request)))
+            }
+        });
+        let server = Server::bind(&addr).serve(make_service);
+        println!("JSON-RPC server listening on http://{addr}");
+        server.await.unwrap();
+    });
+}
+
+async fn serve(
+    handler: Arc<RpcHandler>,
+    request: Request<Body>,
+) -> Result<Response<Body>, hyper::Error> {
+    if request.method() != Method::POST {
+        let mut response = Response::new(Body::from("JSON-RPC requests must be POSTed"));
+        *response.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        return Ok(response);
+    }
+    let body = hyper::body::to_bytes(request.into_body()).await?;
+    info!("rpc request: {}", String::from_utf8_lossy(&body));
+    // Execution is CPU bound, keep it off the reactor threads.
+    let response = tokio::task::spawn_blocking(move || handler.handle_body(&body))
+        .await
+        .unwrap_or_else(|e| internal_error(format!("rpc handler failed: {e}")));
+    Ok(Response::builder()
+        .header("content-type", "application/json")
+        .body(Body::from(response.to_string()))
+        .unwrap())
+}
@@ -0,0 +1,26 @@
+use crate::cli::command::TraceStatsArgs;
+use crate::run::{batch_files, stats_report, BatchOptions, TraceStats};
+
+pub(crate) fn exec_trace_stats(args: &TraceStatsArgs) {
+    let mut stats: Vec<(String, TraceStats)> = args
+        .trace_path
+        .iter()
+        .map(|path| (path.clone(), TraceStats::from_file(path).unwrap()))
+        .collect();
+    if let Some(batch_dir) = &args.batch_dir {
+        let mut batch = TraceStats::default();
+        for path in batch_files(batch_dir, &BatchOptions::default()).unwrap() {
+            batch.merge(&TraceStats::from_file(path.to_str().unwrap()).unwrap());
+        }
+        stats.push((batch_dir.clone(), batch));
+    }
+    if stats.len() > 1 {
+        let mut total = TraceStats::default();
+        for (_, s) in &stats {
+            total.merge(s);
+        }
+        stats.push(("total".to_string(), total));
+    }
+
+    print!("{}", stats_report(&stats, args.top));
+}
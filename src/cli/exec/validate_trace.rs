@@ -0,0 +1,25 @@
+use crate::cli::command::ValidateTraceArgs;
+use crate::run::{validate_trace_file, Expectations};
+
+pub(crate) fn exec_validate_trace(args: &ValidateTraceArgs) {
+    let expect = Expectations {
+        version: args.trace_version.clone(),
+        chain_id: args.chain_id,
+    };
+    let mut invalid = false;
+    for path in &args.trace_path {
+        let problems = validate_trace_file(path, &expect).unwrap();
+        if problems.is_empty() {
+            println!("{path}: ok");
+            continue;
+        }
+        invalid = true;
+        println!("{path}: {} problems", problems.len());
+        for problem in problems {
+            println!("  {problem}");
+        }
+    }
+    if invalid {
+        std::process::exit(1);
+    }
+}
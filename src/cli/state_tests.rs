@@ -0,0 +1,53 @@
+use std::fs;
+
+use clap::{Parser, ValueEnum};
+use lambda_zkevm::dry_run::{hardfork::Hardfork, state_test, suite};
+
+/// Run ethereum/tests GeneralStateTests through the dry run host, skipping
+/// cases outside the instruction subset the circuits support.
+#[derive(Parser)]
+struct Args {
+    /// Directory of filled GeneralStateTests JSON files.
+    dir: String,
+    /// Only run tests whose name contains this.
+    #[arg(long)]
+    filter: Option<String>,
+    /// Hardforks to run the post-states of, repeatable. Defaults to all.
+    #[arg(long, value_enum)]
+    hardfork: Vec<Hardfork>,
+    /// Where to write the JUnit XML report.
+    #[arg(long)]
+    junit: Option<String>,
+}
+
+fn main() {
+    env_logger::init();
+    let args = Args::parse();
+    let hardforks = if args.hardfork.is_empty() {
+        Hardfork::value_variants().to_vec()
+    } else {
+        args.hardfork
+    };
+
+    let cases =
+        state_test::load_state_tests(&args.dir, args.filter.as_deref(), &hardforks).unwrap();
+    let summary = state_test::run_state_tests(&cases);
+    print!("{}", suite::table(&summary.reports));
+    let skipped: usize = summary.skipped.values().sum();
+    println!("{skipped} skipped:");
+    for (reason, count) in &summary.skipped {
+        println!("  {count:>8} {reason}");
+    }
+
+    if let Some(junit) = &args.junit {
+        fs::write(
+            junit,
+            suite::junit_xml(&suite::suite_name(&args.dir), &summary.reports),
+        )
+        .unwrap();
+        println!("JUnit report written to {junit}");
+    }
+    if summary.reports.iter().any(|r| !r.passed()) {
+        std::process::exit(1);
+    }
+}
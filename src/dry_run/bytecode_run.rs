@@ -1,20 +1,198 @@
-use crate::dry_run::dummy::*;
+use std::collections::BTreeMap;
+
+use crate::dry_run::coverage::{Coverage, RunHits};
+use crate::dry_run::dummy::Tracers;
 use crate::dry_run::error::{Error, Result};
+use crate::dry_run::gas;
+use crate::dry_run::hardfork::{with_spec, Hardfork};
+use crate::dry_run::replay::StepLog;
+use crate::dry_run::row_usage::ExecutionProfile;
+use crate::dry_run::trie::AccountState;
 
 use revm::inspectors::NoOpInspector;
 use revm::InMemoryDB;
-use revm_interpreter::{return_ok, CallContext, Contract, InstructionResult, Interpreter};
+use revm_interpreter::{
+    return_ok, CallContext, CallScheme, Contract, InstructionResult, Interpreter,
+};
 use revm_precompile::Precompiles;
-use revm_primitives::{Bytecode, BytecodeState, Env};
+use revm_primitives::{
+    AccountInfo, BlockEnv, Bytecode, BytecodeState, Env, HashMap, Log, Spec, TransactTo, B160, U256,
+};
 
 use super::dummy;
 
+/// Knobs for a single dry run.
+#[derive(Clone, Debug)]
+pub struct RunConfig {
+    /// Gas available to the entry frame.
+    pub gas_limit: u64,
+    /// Rules used by the interpreter and precompiles.
+    pub hardfork: Hardfork,
+    /// EIP-2930 access list of the transaction.
+    pub access_list: Vec<(B160, Vec<U256>)>,
+    /// Sender of the transaction and of the entry call.
+    pub caller: B160,
+    /// Address the entry bytecode runs at.
+    pub address: B160,
+    /// Value sent with the entry call.
+    pub value: U256,
+    /// Block the transaction is executed in.
+    pub block: BlockEnv,
+    /// Accounts existing before the run, visible to calls, `BALANCE`,
+    /// `SLOAD` and value transfers.
+    pub accounts: BTreeMap<B160, AccountState>,
+    /// Memory in bytes all live frames together may expand to, enforced with
+    /// the `memory_limit` feature.
+    pub memory_limit: u64,
+}
+
+/// Default memory cap, 32 MiB.
+pub const DEFAULT_MEMORY_LIMIT: u64 = 32 << 20;
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        Self {
+            gas_limit: u64::MAX,
+            hardfork: Hardfork::default(),
+            access_list: vec![],
+            caller: B160::zero(),
+            address: B160::zero(),
+            value: U256::ZERO,
+            block: BlockEnv::default(),
+            accounts: BTreeMap::new(),
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+        }
+    }
+}
+
+/// Outcome of a successful dry run.
+#[derive(Clone, Debug)]
+pub struct RunOutput {
+    pub return_data: Vec<u8>,
+    /// Gas spent by the bytecode itself.
+    pub execution_gas: u64,
+    /// Gas charged to the transaction, comparable with
+    /// `executionResults[].gas` of block traces: intrinsic gas plus
+    /// execution gas minus the capped refund.
+    pub gas_used: u64,
+    /// Logs emitted by frames that did not revert.
+    pub logs: Vec<Log>,
+    /// Storage slots changed by the run, by address and slot.
+    pub storage: BTreeMap<(B160, U256), U256>,
+    /// `accounts` of the config updated with the balances, nonces and
+    /// storage left by the run.
+    pub state: BTreeMap<B160, AccountState>,
+}
+
 pub fn bytecode_run(
     calldata: Vec<u8>,
     bytecode: Vec<u8>,
     hardcode: Option<Vec<u8>>,
 ) -> Result<Vec<u8>> {
-    let call_context = CallContext::default();
+    bytecode_run_with_config(calldata, bytecode, hardcode, &RunConfig::default())
+        .map(|output| output.return_data)
+}
+
+pub fn bytecode_run_with_config(
+    calldata: Vec<u8>,
+    bytecode: Vec<u8>,
+    hardcode: Option<Vec<u8>>,
+    config: &RunConfig,
+) -> Result<RunOutput> {
+    // The memory of live frames is tracked while stepping, which needs the
+    // inspecting interpreter loop.
+    run::<{ cfg!(feature = "memory_limit") }>(
+        calldata,
+        bytecode,
+        hardcode,
+        config,
+        Tracers::default(),
+    )
+    .0
+}
+
+/// Same as [`bytecode_run_with_config`], additionally folding the executed program
+/// counters of every frame into `coverage`.
+pub fn bytecode_run_with_coverage(
+    calldata: Vec<u8>,
+    bytecode: Vec<u8>,
+    hardcode: Option<Vec<u8>>,
+    config: &RunConfig,
+    coverage: &mut Coverage,
+) -> Result<RunOutput> {
+    let tracers = Tracers {
+        coverage: Some(RunHits::default()),
+        ..Default::default()
+    };
+    let (result, tracers) = run::<true>(calldata, bytecode, hardcode, config, tracers);
+    coverage.record_run(tracers.coverage.unwrap_or_default());
+    result
+}
+
+/// Same as [`bytecode_run_with_config`], additionally returning the
+/// execution profile used for row usage estimation.
+pub fn bytecode_run_with_profile(
+    calldata: Vec<u8>,
+    bytecode: Vec<u8>,
+    hardcode: Option<Vec<u8>>,
+    config: &RunConfig,
+) -> (Result<RunOutput>, ExecutionProfile) {
+    let tracers = Tracers {
+        // The entry frame runs without a host call.
+        profile: Some(ExecutionProfile {
+            calls: 1,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let (result, tracers) = run::<true>(calldata, bytecode, hardcode, config, tracers);
+    (result, tracers.profile.unwrap_or_default())
+}
+
+/// Same as [`bytecode_run_with_config`], additionally returning the state
+/// before every executed step, in `structLogs` order.
+pub fn bytecode_run_with_steps(
+    calldata: Vec<u8>,
+    bytecode: Vec<u8>,
+    hardcode: Option<Vec<u8>>,
+    config: &RunConfig,
+) -> (Result<RunOutput>, Vec<StepLog>) {
+    let tracers = Tracers {
+        steps: Some(vec![]),
+        ..Default::default()
+    };
+    let (result, tracers) = run::<true>(calldata, bytecode, hardcode, config, tracers);
+    (result, tracers.steps.unwrap_or_default())
+}
+
+fn run<const INSPECT: bool>(
+    calldata: Vec<u8>,
+    bytecode: Vec<u8>,
+    hardcode: Option<Vec<u8>>,
+    config: &RunConfig,
+    tracers: Tracers,
+) -> (Result<RunOutput>, Tracers) {
+    with_spec!(
+        config.hardfork,
+        run_with_spec::<_, INSPECT>(calldata, bytecode, hardcode, config, tracers)
+    )
+}
+
+fn run_with_spec<SPEC: Spec, const INSPECT: bool>(
+    calldata: Vec<u8>,
+    bytecode: Vec<u8>,
+    hardcode: Option<Vec<u8>>,
+    config: &RunConfig,
+    tracers: Tracers,
+) -> (Result<RunOutput>, Tracers) {
+    let call_context = CallContext {
+        address: config.address,
+        caller: config.caller,
+        code_address: config.address,
+        apparent_value: config.value,
+        scheme: CallScheme::Call,
+    };
+    let intrinsic_gas = gas::intrinsic_gas(&calldata, &config.access_list, SPEC::SPEC_ID);
     let bytecode = Bytecode {
         bytecode: bytecode.into(),
         state: BytecodeState::Raw,
@@ -22,25 +200,115 @@ pub fn bytecode_run(
     };
 
     let contract = Contract::new_with_context(calldata.into(), bytecode, &call_context);
-    let mut interpreter = Interpreter::new(contract, u64::MAX, false);
+    #[cfg(feature = "memory_limit")]
+    let mut interpreter =
+        Interpreter::new_with_memory_limit(contract, config.gas_limit, false, config.memory_limit);
+    #[cfg(not(feature = "memory_limit"))]
+    let mut interpreter = Interpreter::new(contract, config.gas_limit, false);
 
     let mut noop = NoOpInspector {};
     let mut db = InMemoryDB::default();
+    let mut storage = HashMap::new();
+    for (address, account) in &config.accounts {
+        db.insert_account_info(
+            *address,
+            AccountInfo::new(
+                account.balance,
+                account.nonce,
+                Bytecode::new_raw(account.code.clone().into()),
+            ),
+        );
+        for (index, value) in &account.storage {
+            storage.insert((*address, *index), *value);
+        }
+    }
     let mut env = Env::default();
-    let mut host: dummy::DummyHost<'_, DummySpec, _, false> = dummy::DummyHost::new(
+    env.cfg.spec_id = SPEC::SPEC_ID;
+    #[cfg(feature = "memory_limit")]
+    {
+        env.cfg.memory_limit = config.memory_limit;
+    }
+    env.block = config.block.clone();
+    env.tx.caller = call_context.caller;
+    env.tx.transact_to = TransactTo::Call(call_context.address);
+    env.tx.value = call_context.apparent_value;
+    env.tx.access_list = config.access_list.clone();
+    let mut host: dummy::DummyHost<'_, SPEC, _, INSPECT> = dummy::DummyHost::new(
         &mut db,
         &mut env,
         &mut noop,
         hardcode,
-        Precompiles::new(revm_precompile::SpecId::LATEST).clone(),
+        Precompiles::new(revm_precompile::SpecId::from_spec_id(SPEC::SPEC_ID)).clone(),
     );
-    let result = interpreter.run::<_, DummySpec>(&mut host);
+    host.tracers = tracers;
+    host.storage = storage.clone();
+    host.original_storage = storage;
+    let result = if INSPECT {
+        interpreter.run_inspect::<_, SPEC>(&mut host)
+    } else {
+        interpreter.run::<_, SPEC>(&mut host)
+    };
 
-    if matches!(result, return_ok!()) {
-        Ok(interpreter.return_value().to_vec())
+    // The cap guards the host, so hitting it in any frame fails the run
+    // even when the caller would carry on after the failed call.
+    let result = if result == InstructionResult::MemoryLimitOOG || host.memory_limit_hit {
+        Err(Error::MemoryLimitExceeded(config.memory_limit))
+    } else if matches!(result, return_ok!()) {
+        let execution_gas = interpreter.gas.spend();
+        Ok(RunOutput {
+            return_data: interpreter.return_value().to_vec(),
+            execution_gas,
+            gas_used: gas::tx_gas_used(
+                intrinsic_gas,
+                execution_gas,
+                interpreter.gas.refunded(),
+                SPEC::SPEC_ID,
+            ),
+            logs: std::mem::take(&mut host.logs),
+            storage: host
+                .storage
+                .iter()
+                .filter(|(key, value)| {
+                    host.original_storage.get(key).copied().unwrap_or_default() != **value
+                })
+                .map(|(key, value)| (*key, *value))
+                .collect(),
+            state: post_state(&config.accounts, &host),
+        })
+    } else if result == InstructionResult::Revert {
+        // Refunds are dropped with the reverted state.
+        Err(Error::Reverted {
+            output: interpreter.return_value().to_vec(),
+            gas_used: gas::tx_gas_used(intrinsic_gas, interpreter.gas.spend(), 0, SPEC::SPEC_ID),
+        })
     } else {
         Err(Error::InterpreterError(format!("{result:?}")))
+    };
+    (result, std::mem::take(&mut host.tracers))
+}
+
+fn post_state<SPEC: Spec, const INSPECT: bool>(
+    accounts: &BTreeMap<B160, AccountState>,
+    host: &dummy::DummyHost<'_, SPEC, InMemoryDB, INSPECT>,
+) -> BTreeMap<B160, AccountState> {
+    let mut state = accounts.clone();
+    for (address, info) in host.loaded_accounts() {
+        if !state.contains_key(address) && info.balance == U256::ZERO && info.nonce == 0 {
+            continue;
+        }
+        let account = state.entry(*address).or_default();
+        account.balance = info.balance;
+        account.nonce = info.nonce;
+    }
+    for ((address, index), value) in &host.storage {
+        let storage = &mut state.entry(*address).or_default().storage;
+        if *value == U256::ZERO {
+            storage.remove(index);
+        } else {
+            storage.insert(*index, *value);
+        }
     }
+    state
 }
 
 #[cfg(test)]
@@ -63,4 +331,56 @@ mod tests {
                 .unwrap()
         );
     }
+
+    #[test]
+    fn storage_slots_warm_up() {
+        // PUSH1 0 SLOAD PUSH1 0 SLOAD: a cold then a warm read
+        let bytecode = hex::decode("600054600054").unwrap();
+
+        let output =
+            bytecode_run_with_config(vec![], bytecode, None, &RunConfig::default()).unwrap();
+        assert_eq!(output.execution_gas, 3 + 2100 + 3 + 100);
+        assert_eq!(output.gas_used, 21000 + output.execution_gas);
+    }
+
+    #[test]
+    fn revert_reports_gas_used() {
+        // PUSH1 0 PUSH1 0 REVERT
+        let bytecode = hex::decode("60006000fd").unwrap();
+
+        let result = bytecode_run_with_config(vec![], bytecode, None, &RunConfig::default());
+        assert!(matches!(
+            result,
+            Err(Error::Reverted { output, gas_used: 21_006 }) if output.is_empty()
+        ));
+    }
+
+    #[cfg(feature = "memory_limit")]
+    #[test]
+    fn memory_limit_exceeded() {
+        // PUSH1 1 PUSH4 0x04000000 MSTORE: expand memory to 64 MiB
+        let bytecode = hex::decode("6001630400000052").unwrap();
+
+        let result = bytecode_run_with_config(vec![], bytecode, None, &RunConfig::default());
+        assert!(matches!(
+            result,
+            Err(Error::MemoryLimitExceeded(DEFAULT_MEMORY_LIMIT))
+        ));
+    }
+
+    #[cfg(feature = "memory_limit")]
+    #[test]
+    fn memory_limit_spans_frames() {
+        // Expand memory to 24 MiB, then CALL 0x42 with all the gas.
+        let bytecode = hex::decode("600163017fffe0526000600060006000600060425af100").unwrap();
+        // The callee expands memory to 16 MiB, under the limit on its own.
+        let hardcode = hex::decode("600163010000005200").unwrap();
+
+        let result =
+            bytecode_run_with_config(vec![], bytecode, Some(hardcode), &RunConfig::default());
+        assert!(matches!(
+            result,
+            Err(Error::MemoryLimitExceeded(DEFAULT_MEMORY_LIMIT))
+        ));
+    }
 }
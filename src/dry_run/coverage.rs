@@ -0,0 +1,381 @@
+//! Program counter coverage for dry runs, with optional mapping to Solidity
+//! sources through solc source maps and lcov output.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write,
+};
+
+use revm_interpreter::opcode;
+use revm_primitives::{keccak256, B256};
+
+use crate::dry_run::error::{Error, Result};
+
+/// Hits recorded for a single piece of bytecode.
+#[derive(Clone, Debug, Default)]
+pub struct PcHits {
+    /// Number of times each program counter was executed.
+    pub pcs: BTreeMap<usize, u64>,
+    /// For every `JUMPI` pc: `[not taken, taken]` counters.
+    pub jumps: BTreeMap<usize, [u64; 2]>,
+}
+
+impl PcHits {
+    fn merge(&mut self, other: &PcHits) {
+        for (pc, n) in &other.pcs {
+            *self.pcs.entry(*pc).or_default() += n;
+        }
+        for (pc, [not_taken, taken]) in &other.jumps {
+            let entry = self.jumps.entry(*pc).or_default();
+            entry[0] += not_taken;
+            entry[1] += taken;
+        }
+    }
+}
+
+/// Per-run recorder hooked into `DummyHost::step`/`step_end`, keyed by the
+/// keccak hash of the code each frame executes.
+#[derive(Debug, Default)]
+pub(crate) struct RunHits {
+    pub hits: HashMap<B256, (Vec<u8>, PcHits)>,
+    /// Code hash of every live frame, by call depth, so each frame's code is
+    /// hashed once rather than on every step.
+    frames: Vec<B256>,
+    pending_jumpi: Option<(B256, usize)>,
+}
+
+impl RunHits {
+    pub fn step(&mut self, depth: usize, code: &[u8], pc: usize, op: u8) {
+        // A frame at the same depth as an earlier one always follows a step of
+        // their common parent, which drops the stale hash.
+        self.frames.truncate(depth + 1);
+        if self.frames.len() <= depth {
+            let hash = keccak256(code);
+            self.hits
+                .entry(hash)
+                .or_insert_with(|| (code.to_vec(), PcHits::default()));
+            self.frames.push(hash);
+        }
+        let hash = *self.frames.last().unwrap();
+        let (_, hits) = self.hits.get_mut(&hash).unwrap();
+        *hits.pcs.entry(pc).or_default() += 1;
+        self.pending_jumpi = (op == opcode::JUMPI).then_some((hash, pc));
+    }
+
+    pub fn step_end(&mut self, pc: usize) {
+        if let Some((hash, jumpi_pc)) = self.pending_jumpi.take() {
+            let taken = usize::from(pc != jumpi_pc + 1);
+            if let Some((_, hits)) = self.hits.get_mut(&hash) {
+                hits.jumps.entry(jumpi_pc).or_default()[taken] += 1;
+            }
+        }
+    }
+}
+
+/// Coverage accumulated across any number of dry runs, keyed by the keccak
+/// hash of the executed bytecode.
+#[derive(Debug, Default)]
+pub struct Coverage {
+    codes: HashMap<B256, (Vec<u8>, PcHits)>,
+}
+
+impl Coverage {
+    /// Fold the hits of one run into the accumulated coverage.
+    pub(crate) fn record_run(&mut self, run: RunHits) {
+        for (hash, (code, hits)) in run.hits {
+            self.codes
+                .entry(hash)
+                .or_insert_with(|| (code, PcHits::default()))
+                .1
+                .merge(&hits);
+        }
+    }
+
+    /// Hits recorded for `bytecode`, if it was executed at all.
+    pub fn hits(&self, bytecode: &[u8]) -> Option<&PcHits> {
+        self.codes.get(&keccak256(bytecode)).map(|(_, hits)| hits)
+    }
+
+    /// Human readable summary of instruction and branch coverage per bytecode.
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        for (hash, (code, hits)) in &self.codes {
+            let total = instruction_offsets(code).len();
+            let branches = count_jumpis(code) * 2;
+            let covered_branches: usize = hits
+                .jumps
+                .values()
+                .map(|counts| counts.iter().filter(|n| **n > 0).count())
+                .sum();
+            writeln!(
+                out,
+                "{hash:?}: {}/{} instructions, {}/{} branches",
+                hits.pcs.len(),
+                total,
+                covered_branches,
+                branches
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+/// Byte offset of every instruction in `code`, skipping push data.
+pub fn instruction_offsets(code: &[u8]) -> Vec<usize> {
+    let mut offsets = vec![];
+    let mut pc = 0;
+    while pc < code.len() {
+        offsets.push(pc);
+        let op = code[pc];
+        pc += 1;
+        if (opcode::PUSH1..=opcode::PUSH32).contains(&op) {
+            pc += (op - opcode::PUSH1 + 1) as usize;
+        }
+    }
+    offsets
+}
+
+fn count_jumpis(code: &[u8]) -> usize {
+    instruction_offsets(code)
+        .into_iter()
+        .filter(|pc| code[*pc] == opcode::JUMPI)
+        .count()
+}
+
+/// A single decompressed solc source map entry.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SourceMapEntry {
+    pub offset: usize,
+    pub length: usize,
+    /// Source file index, `None` for compiler generated code (`-1`).
+    pub file: Option<usize>,
+}
+
+/// Decompress a solc `srcmap`/`srcmap-runtime` string into one entry per
+/// instruction.
+pub fn parse_source_map(srcmap: &str) -> Result<Vec<SourceMapEntry>> {
+    let mut entries = vec![];
+    let mut last = SourceMapEntry::default();
+    for item in srcmap.trim().split(';') {
+        let fields: Vec<&str> = item.split(':').collect();
+        let field = |idx: usize| fields.get(idx).filter(|f| !f.is_empty());
+        let parse = |s: &str| {
+            s.parse::<i64>()
+                .map_err(|_| Error::SourceMapError(format!("invalid field {s:?} in {item:?}")))
+        };
+        if let Some(s) = field(0) {
+            last.offset = parse(s)? as usize;
+        }
+        if let Some(l) = field(1) {
+            last.length = parse(l)? as usize;
+        }
+        if let Some(f) = field(2) {
+            let f = parse(f)?;
+            last.file = (f >= 0).then_some(f as usize);
+        }
+        entries.push(last);
+    }
+    Ok(entries)
+}
+
+/// A Solidity source file as referenced by the solc source index.
+pub struct Source {
+    pub path: String,
+    pub content: String,
+}
+
+impl Source {
+    /// 1-based line containing byte `offset`.
+    fn line_of(&self, offset: usize) -> usize {
+        let end = offset.min(self.content.len());
+        self.content.as_bytes()[..end]
+            .iter()
+            .filter(|b| **b == b'\n')
+            .count()
+            + 1
+    }
+}
+
+/// Write lcov records for `bytecode`, mapping its instructions to lines of
+/// `sources` through `srcmap`.
+pub fn to_lcov(
+    coverage: &Coverage,
+    bytecode: &[u8],
+    srcmap: &[SourceMapEntry],
+    sources: &[Source],
+) -> Result<String> {
+    let offsets = instruction_offsets(bytecode);
+    if srcmap.len() < offsets.len() {
+        return Err(Error::SourceMapError(format!(
+            "source map has {} entries but bytecode has {} instructions",
+            srcmap.len(),
+            offsets.len()
+        )));
+    }
+    let empty = PcHits::default();
+    let hits = coverage.hits(bytecode).unwrap_or(&empty);
+
+    // file -> line -> hits, and file -> line -> [(block, [not taken, taken])]
+    let mut lines: BTreeMap<usize, BTreeMap<usize, u64>> = BTreeMap::new();
+    let mut branches: BTreeMap<usize, BTreeMap<usize, Vec<(usize, [u64; 2])>>> = BTreeMap::new();
+    for (entry, pc) in srcmap.iter().zip(offsets) {
+        let Some(file) = entry.file.filter(|f| *f < sources.len()) else {
+            continue;
+        };
+        let line = sources[file].line_of(entry.offset);
+        let count = hits.pcs.get(&pc).copied().unwrap_or_default();
+        *lines.entry(file).or_default().entry(line).or_default() += count;
+        if bytecode[pc] == opcode::JUMPI {
+            let counts = hits.jumps.get(&pc).copied().unwrap_or_default();
+            branches
+                .entry(file)
+                .or_default()
+                .entry(line)
+                .or_default()
+                .push((pc, counts));
+        }
+    }
+
+    let mut out = String::new();
+    for (file, source) in sources.iter().enumerate() {
+        let file_lines = lines.remove(&file).unwrap_or_default();
+        let file_branches = branches.remove(&file).unwrap_or_default();
+        writeln!(out, "TN:").unwrap();
+        writeln!(out, "SF:{}", source.path).unwrap();
+        for (line, count) in &file_lines {
+            writeln!(out, "DA:{line},{count}").unwrap();
+        }
+        let mut found = BTreeSet::new();
+        let mut hit = 0;
+        for (line, jumps) in &file_branches {
+            for (pc, counts) in jumps {
+                for (branch, count) in counts.iter().enumerate() {
+                    let taken = if hits.pcs.contains_key(pc) {
+                        count.to_string()
+                    } else {
+                        "-".to_string()
+                    };
+                    writeln!(out, "BRDA:{line},{pc},{branch},{taken}").unwrap();
+                    found.insert((*pc, branch));
+                    hit += usize::from(*count > 0);
+                }
+            }
+        }
+        writeln!(out, "BRF:{}", found.len()).unwrap();
+        writeln!(out, "BRH:{hit}").unwrap();
+        writeln!(out, "LF:{}", file_lines.len()).unwrap();
+        writeln!(
+            out,
+            "LH:{}",
+            file_lines.values().filter(|count| **count > 0).count()
+        )
+        .unwrap();
+        writeln!(out, "end_of_record").unwrap();
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dry_run::bytecode_run::{bytecode_run_with_coverage, RunConfig};
+    use revm_primitives::B160;
+
+    #[test]
+    fn parse_compressed_source_map() {
+        let entries = parse_source_map("57:277:0:-:0;;;;8:9:-1;1:2").unwrap();
+        assert_eq!(entries.len(), 6);
+        assert_eq!(
+            entries[3],
+            SourceMapEntry {
+                offset: 57,
+                length: 277,
+                file: Some(0)
+            }
+        );
+        assert_eq!(entries[4].file, None);
+        assert_eq!(
+            entries[5],
+            SourceMapEntry {
+                offset: 1,
+                length: 2,
+                file: None
+            }
+        );
+    }
+
+    #[test]
+    fn instruction_offsets_skip_push_data() {
+        // PUSH1 0x80 PUSH2 0x0102 JUMPI STOP
+        let code = hex::decode("6080610102570000").unwrap();
+        assert_eq!(instruction_offsets(&code), vec![0, 2, 5, 6, 7]);
+    }
+
+    #[test]
+    fn hits_follow_the_code_not_the_address() {
+        // PUSH1 1 PUSH1 9 JUMPI STOP STOP STOP STOP JUMPDEST STOP
+        let bytecode = hex::decode("6001600957000000005b00").unwrap();
+        let config = RunConfig {
+            address: B160::from_low_u64_be(0x1234),
+            ..Default::default()
+        };
+
+        let mut coverage = Coverage::default();
+        bytecode_run_with_coverage(vec![], bytecode.clone(), None, &config, &mut coverage).unwrap();
+
+        let hits = coverage.hits(&bytecode).unwrap();
+        assert_eq!(
+            hits.pcs.keys().copied().collect::<Vec<_>>(),
+            [0, 2, 4, 9, 10]
+        );
+        assert_eq!(hits.jumps[&4], [0, 1]);
+    }
+
+    #[test]
+    fn lcov_of_calculation_run() {
+        let code = std::fs::read_to_string("data/calculation.code").unwrap();
+        let mut lines = code.lines();
+        let calldata = hex::decode(lines.next().unwrap()).unwrap();
+        let bytecode = hex::decode(lines.next().unwrap()).unwrap();
+
+        let mut coverage = Coverage::default();
+        bytecode_run_with_coverage(
+            calldata,
+            bytecode.clone(),
+            None,
+            &RunConfig::default(),
+            &mut coverage,
+        )
+        .unwrap();
+
+        // Line 1: the callvalue check up to its JUMPI (pc 11), taken.
+        // Line 2: the revert of a non-zero callvalue, not run.
+        // Line 3: the calldata size check up to its JUMPI (pc 25), not taken.
+        // The rest is compiler generated.
+        let rest = instruction_offsets(&bytecode).len() - 18;
+        let srcmap = format!(
+            "0:1:0{};2:1{};4:1{};-1:0:-1{}",
+            ";".repeat(7),
+            ";;",
+            ";".repeat(6),
+            ";".repeat(rest - 1)
+        );
+        let source = Source {
+            path: "Calculation.sol".to_string(),
+            content: "a\nb\nc\n".to_string(),
+        };
+        let lcov = to_lcov(
+            &coverage,
+            &bytecode,
+            &parse_source_map(&srcmap).unwrap(),
+            &[source],
+        )
+        .unwrap();
+        assert_eq!(
+            lcov,
+            "TN:\nSF:Calculation.sol\nDA:1,8\nDA:2,0\nDA:3,7\n\
+             BRDA:1,11,0,0\nBRDA:1,11,1,1\nBRDA:3,25,0,1\nBRDA:3,25,1,0\n\
+             BRF:4\nBRH:2\nLF:3\nLH:2\nend_of_record\n"
+        );
+    }
+}
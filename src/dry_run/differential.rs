@@ -0,0 +1,372 @@
+//! Differential execution of a dry run against a standard revm EVM, to catch
+//! where `DummyHost` diverges from real EVM semantics.
+use std::{collections::BTreeMap, convert::Infallible};
+
+use revm::Database;
+use revm_primitives::{
+    AccountInfo, Bytecode, ExecutionResult, HashMap, Log, Output, TransactTo, B160, B256, U256,
+};
+
+use crate::dry_run::{
+    bytecode_run::{bytecode_run_with_config, RunConfig},
+    error::Error,
+    gas,
+};
+
+/// Address the reference EVM runs the entry bytecode at when the config runs
+/// it at the caller's own address, which EIP-3607 forbids from holding code.
+pub const REFERENCE_ADDRESS: B160 = B160([
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x10, 0,
+]);
+
+/// What one side of the comparison observed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Observation {
+    pub success: bool,
+    /// Return or revert data.
+    pub output: Vec<u8>,
+    /// Transaction gas used, unknown for dry runs halting with an error.
+    pub gas_used: Option<u64>,
+    pub logs: Vec<Log>,
+    /// Storage changed by the call, by address and slot.
+    pub storage: BTreeMap<(B160, U256), U256>,
+}
+
+#[derive(Clone, Debug)]
+pub struct DiffReport {
+    pub dry_run: Observation,
+    pub reference: Observation,
+    /// Human readable discrepancies, empty when both sides agree.
+    pub discrepancies: Vec<String>,
+}
+
+/// Database holding the accounts of the run config, with `bytecode` at the
+/// entry address and, like `DummyHost::code`, `hardcode` as the code of any
+/// other address but the caller.
+struct ReferenceDB {
+    accounts: BTreeMap<B160, AccountInfo>,
+    storage: HashMap<(B160, U256), U256>,
+    hardcode: Option<Bytecode>,
+    entry: B160,
+    caller: B160,
+}
+
+impl ReferenceDB {
+    fn new(bytecode: &[u8], hardcode: Option<&[u8]>, entry: B160, config: &RunConfig) -> Self {
+        let hardcode = hardcode.map(|code| Bytecode::new_raw(code.to_vec().into()));
+        let mut accounts = BTreeMap::new();
+        let mut storage = HashMap::new();
+        for (address, account) in &config.accounts {
+            let address = if *address == config.address {
+                entry
+            } else {
+                *address
+            };
+            let code = Bytecode::new_raw(account.code.clone().into());
+            accounts.insert(
+                address,
+                AccountInfo::new(account.balance, account.nonce, code),
+            );
+            for (index, value) in &account.storage {
+                storage.insert((address, *index), *value);
+            }
+        }
+        let info = accounts.entry(entry).or_default();
+        *info = AccountInfo::new(
+            info.balance,
+            info.nonce,
+            Bytecode::new_raw(bytecode.to_vec().into()),
+        );
+        Self {
+            accounts,
+            storage,
+            hardcode,
+            entry,
+            caller: config.caller,
+        }
+    }
+}
+
+impl Database for ReferenceDB {
+    type Error = Infallible;
+
+    fn basic(&mut self, address: B160) -> Result<Option<AccountInfo>, Self::Error> {
+        let info = self.accounts.get(&address).cloned();
+        match &self.hardcode {
+            Some(hardcode) if address != self.entry && address != self.caller => {
+                let info = info.unwrap_or_default();
+                Ok(Some(AccountInfo::new(
+                    info.balance,
+                    info.nonce,
+                    hardcode.clone(),
+                )))
+            }
+            _ => Ok(info),
+        }
+    }
+
+    fn code_by_hash(&mut self, _code_hash: B256) -> Result<Bytecode, Self::Error> {
+        // Code is always returned inline by `basic`.
+        Ok(Bytecode::new())
+    }
+
+    fn storage(&mut self, address: B160, index: U256) -> Result<U256, Self::Error> {
+        Ok(self
+            .storage
+            .get(&(address, index))
+            .copied()
+            .unwrap_or_default())
+    }
+
+    fn block_hash(&mut self, _number: U256) -> Result<B256, Self::Error> {
+        Ok(B256::zero())
+    }
+}
+
+/// Execute the call through a standard revm EVM with an equivalent database.
+pub fn reference_run(
+    calldata: &[u8],
+    bytecode: &[u8],
+    hardcode: Option<&[u8]>,
+    config: &RunConfig,
+) -> Observation {
+    let entry = if config.address == config.caller {
+        REFERENCE_ADDRESS
+    } else {
+        config.address
+    };
+    let spec_id = config.hardfork.spec_id();
+    let gas_limit =
+        config
+            .gas_limit
+            .saturating_add(gas::intrinsic_gas(calldata, &config.access_list, spec_id));
+    let gas_price = config.block.basefee;
+    let mut db = ReferenceDB::new(bytecode, hardcode, entry, config);
+    // Dry runs charge no fees and debit no value: top the caller up by both so
+    // its balance while executing is the one of the config.
+    let caller = db.accounts.entry(config.caller).or_default();
+    caller.balance = caller
+        .balance
+        .saturating_add(U256::from(gas_limit).saturating_mul(gas_price))
+        .saturating_add(config.value);
+
+    let mut evm = revm::new();
+    evm.database(db);
+    evm.env.cfg.spec_id = spec_id;
+    evm.env.block = config.block.clone();
+    // The entry frame of a dry run is not bounded by the block gas limit.
+    evm.env.block.gas_limit = evm.env.block.gas_limit.max(U256::from(gas_limit));
+    evm.env.tx.caller = config.caller;
+    evm.env.tx.transact_to = TransactTo::Call(entry);
+    evm.env.tx.value = config.value;
+    evm.env.tx.data = calldata.to_vec().into();
+    evm.env.tx.access_list = config.access_list.clone();
+    evm.env.tx.gas_limit = gas_limit;
+    evm.env.tx.gas_price = gas_price;
+
+    let result_and_state = match evm.transact() {
+        Ok(result_and_state) => result_and_state,
+        Err(e) => {
+            log::warn!("Reference EVM rejected the call: {e:?}");
+            return Observation::default();
+        }
+    };
+    // Report logs and storage as if at the dry run entry address.
+    let map_address = |address: B160| {
+        if address == entry {
+            config.address
+        } else {
+            address
+        }
+    };
+    let storage = result_and_state
+        .state
+        .iter()
+        .flat_map(|(address, account)| {
+            account
+                .storage
+                .iter()
+                .map(move |(index, slot)| (map_address(*address), *index, slot))
+        })
+        .filter(|(_, _, slot)| slot.original_value != slot.present_value)
+        .map(|(address, index, slot)| ((address, index), slot.present_value))
+        .collect();
+    let map_log = |log: Log| Log {
+        address: map_address(log.address),
+        ..log
+    };
+
+    match result_and_state.result {
+        ExecutionResult::Success {
+            output,
+            gas_used,
+            logs,
+            ..
+        } => Observation {
+            success: true,
+            output: match output {
+                Output::Call(out) => out.to_vec(),
+                Output::Create(out, _) => out.to_vec(),
+            },
+            gas_used: Some(gas_used),
+            logs: logs.into_iter().map(map_log).collect(),
+            storage,
+        },
+        ExecutionResult::Revert { output, gas_used } => Observation {
+            output: output.to_vec(),
+            gas_used: Some(gas_used),
+            ..Default::default()
+        },
+        ExecutionResult::Halt { gas_used, .. } => Observation {
+            gas_used: Some(gas_used),
+            ..Default::default()
+        },
+    }
+}
+
+/// Execute the call through `bytecode_run` and the reference EVM and compare
+/// return data, gas, logs and storage.
+pub fn differential_run(
+    calldata: Vec<u8>,
+    bytecode: Vec<u8>,
+    hardcode: Option<Vec<u8>>,
+    config: &RunConfig,
+) -> DiffReport {
+    let reference = reference_run(&calldata, &bytecode, hardcode.as_deref(), config);
+    let dry_run = match bytecode_run_with_config(calldata, bytecode, hardcode, config) {
+        Ok(output) => Observation {
+            success: true,
+            output: output.return_data,
+            gas_used: Some(output.gas_used),
+            logs: output.logs,
+            storage: output.storage,
+        },
+        Err(Error::Reverted { output, gas_used }) => Observation {
+            output,
+            gas_used: Some(gas_used),
+            ..Default::default()
+        },
+        Err(_) => Observation::default(),
+    };
+
+    let mut discrepancies = vec![];
+    if dry_run.success != reference.success {
+        discrepancies.push(format!(
+            "success: dry run {}, reference {}",
+            dry_run.success, reference.success
+        ));
+    }
+    if dry_run.output != reference.output {
+        discrepancies.push(format!(
+            "output: dry run 0x{}, reference 0x{}",
+            hex::encode(&dry_run.output),
+            hex::encode(&reference.output)
+        ));
+    }
+    if let (Some(dry_run_gas), Some(reference_gas)) = (dry_run.gas_used, reference.gas_used) {
+        if dry_run_gas != reference_gas {
+            discrepancies.push(format!(
+                "gas: dry run {dry_run_gas}, reference {reference_gas}"
+            ));
+        }
+    }
+    if dry_run.logs != reference.logs {
+        discrepancies.push(format!(
+            "logs: dry run {:?}, reference {:?}",
+            dry_run.logs, reference.logs
+        ));
+    }
+    if dry_run.storage != reference.storage {
+        discrepancies.push(format!(
+            "storage: dry run {:?}, reference {:?}",
+            dry_run.storage, reference.storage
+        ));
+    }
+
+    DiffReport {
+        dry_run,
+        reference,
+        discrepancies,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use revm_primitives::KECCAK_EMPTY;
+
+    use super::*;
+    use crate::dry_run::trie::AccountState;
+
+    fn run(calldata: &str, bytecode: &str) -> DiffReport {
+        differential_run(
+            hex::decode(calldata).unwrap(),
+            hex::decode(bytecode).unwrap(),
+            None,
+            &RunConfig::default(),
+        )
+    }
+
+    #[test]
+    fn agrees_on_basic_token_add() {
+        let code = std::fs::read_to_string("data/calculation.code").unwrap();
+        let mut lines = code.lines();
+        let report = run(lines.next().unwrap(), lines.next().unwrap());
+        assert_eq!(report.discrepancies, Vec::<String>::new());
+        assert!(report.dry_run.success);
+        assert_eq!(report.dry_run.output[31], 5);
+        assert_eq!(report.dry_run.gas_used, report.reference.gas_used);
+    }
+
+    #[test]
+    fn agrees_on_storage_and_logs() {
+        // PUSH1 1 PUSH1 0 SSTORE PUSH1 0 PUSH1 0 LOG0 STOP
+        let report = run("", "600160005560006000a000");
+        assert_eq!(report.discrepancies, Vec::<String>::new());
+        assert_eq!(
+            report.reference.storage,
+            BTreeMap::from([((B160::zero(), U256::ZERO), U256::from(1))])
+        );
+        assert_eq!(report.reference.logs.len(), 1);
+    }
+
+    #[test]
+    fn reference_sees_config_accounts_and_tx() {
+        let address = B160::from_low_u64_be(0x100);
+        let config = RunConfig {
+            caller: B160::from_low_u64_be(0xca11),
+            address,
+            value: U256::from(5),
+            accounts: BTreeMap::from([(
+                address,
+                AccountState {
+                    storage: BTreeMap::from([(U256::ZERO, U256::from(7))]),
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        // PUSH1 0 SLOAD CALLVALUE ADD PUSH1 0 MSTORE PUSH1 0x20 PUSH1 0 RETURN
+        let report = differential_run(
+            vec![],
+            hex::decode("600054340160005260206000f3").unwrap(),
+            None,
+            &config,
+        );
+        assert_eq!(report.discrepancies, Vec::<String>::new());
+        assert_eq!(report.reference.output[31], 12);
+    }
+
+    #[test]
+    fn reports_extcodehash_discrepancy() {
+        // PUSH1 0x42 EXTCODEHASH PUSH1 0 MSTORE PUSH1 0x20 PUSH1 0 RETURN
+        let report = run("", "60423f60005260206000f3");
+        // The dry run host hashes every account as empty code, the reference
+        // hashes a missing account as zero.
+        assert_eq!(report.dry_run.output, KECCAK_EMPTY.as_bytes());
+        assert_eq!(report.reference.output, [0; 32]);
+        assert!(report
+            .discrepancies
+            .iter()
+            .any(|d| d.starts_with("output: dry run 0xc5d2")));
+    }
+}
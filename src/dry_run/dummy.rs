@@ -1,40 +1,101 @@
-use std::marker::PhantomData;
+use std::{collections::HashSet, marker::PhantomData};
+
+use crate::dry_run::{coverage::RunHits, replay::StepLog, row_usage::ExecutionProfile};
 
 use precompile::Precompile;
 use revm::{
     evm_impl::Transact,
     precompile::{self, Precompiles},
-    Database, EVMData, Inspector, JournaledState,
+    Database, EVMData, Inspector, JournalCheckpoint, JournaledState,
 };
 
 use revm_interpreter::{
-    return_ok, CallInputs, Contract, CreateInputs, Gas, Host, InstructionResult, Interpreter,
-    SelfDestructResult, CALL_STACK_LIMIT,
+    opcode, return_ok, CallInputs, Contract, CreateInputs, Gas, Host, InstructionResult,
+    Interpreter, SelfDestructResult, CALL_STACK_LIMIT,
 };
 use revm_primitives::{
-    hash_map::Entry, Bytecode, Bytes, EVMResult, Env, HashMap, Spec, SpecId, B160, B256,
-    KECCAK_EMPTY, U256,
+    AccountInfo, Bytecode, Bytes, EVMResult, Env, ExecutionResult, Halt, HashMap, Log,
+    ResultAndState, Spec, SpecId, TransactTo, B160, B256, KECCAK_EMPTY, U256,
 };
 
 pub(crate) struct DummyHost<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> {
-    pub storage: HashMap<U256, U256>,
+    /// Present storage values, by address and slot.
+    pub storage: HashMap<(B160, U256), U256>,
+    /// Storage values at the start of the transaction, for SSTORE gas.
+    pub original_storage: HashMap<(B160, U256), U256>,
+    pub logs: Vec<Log>,
     data: EVMData<'a, DB>,
     inspector: &'a mut dyn Inspector<DB>,
     hardcode: Option<Vec<u8>>,
+    pub(crate) tracers: Tracers,
+    /// Set when a frame ran out of its memory limit.
+    pub(crate) memory_limit_hit: bool,
+    /// Memory size of every live frame, the entry frame first, as of its
+    /// last call. A new frame may only use what the others leave of the limit.
+    #[cfg(feature = "memory_limit")]
+    frame_memory: Vec<u64>,
+    access: AccessSets,
+    /// Changes to `storage` and `access` since the transaction started, undone
+    /// down to a checkpoint when its frame reverts.
+    changes: Vec<HostChange>,
     _phantomdata: PhantomData<GSPEC>,
 }
 
-pub(crate) struct DummySpec;
+/// EIP-2929 accessed addresses and storage keys of the running transaction.
+#[derive(Clone, Debug, Default)]
+struct AccessSets {
+    accounts: HashSet<B160>,
+    slots: HashSet<(B160, U256)>,
+}
+
+impl AccessSets {
+    /// Mark `address` warm, returning whether it was cold.
+    fn touch_account(&mut self, address: B160) -> bool {
+        self.accounts.insert(address)
+    }
+
+    /// Mark `(address, index)` warm, returning whether it was cold.
+    fn touch_slot(&mut self, address: B160, index: U256) -> bool {
+        self.slots.insert((address, index))
+    }
+}
 
-impl Spec for DummySpec {
-    const SPEC_ID: SpecId = SpecId::LATEST;
+/// A change of host state, recorded to be undone on revert.
+#[derive(Debug)]
+enum HostChange {
+    AccountWarmed(B160),
+    SlotWarmed(B160, U256),
+    /// Storage written, with the value it held before.
+    StorageChanged((B160, U256), Option<U256>),
+}
+
+/// Host state to restore when a call frame reverts.
+struct Checkpoint {
+    journal: JournalCheckpoint,
+    changes: usize,
+    logs: usize,
+}
+
+/// Optional recorders fed while stepping, only used with `INSPECT`.
+#[derive(Debug, Default)]
+pub(crate) struct Tracers {
+    pub coverage: Option<RunHits>,
+    pub profile: Option<ExecutionProfile>,
+    pub steps: Option<Vec<StepLog>>,
 }
 
 impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> Transact<DB::Error>
     for DummyHost<'a, GSPEC, DB, INSPECT>
 {
+    /// Runs go through `call_inner`, a whole transaction halts unexecuted.
     fn transact(&mut self) -> EVMResult<DB::Error> {
-        panic!("transact")
+        Ok(ResultAndState {
+            result: ExecutionResult::Halt {
+                reason: Halt::NotActivated,
+                gas_used: 0,
+            },
+            state: HashMap::new(),
+        })
     }
 }
 
@@ -51,6 +112,25 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> DummyHost<'a, GSPEC, DB
         } else {
             JournaledState::new_legacy(precompiles.len())
         };
+        // Accounts and keys warm from the start of the transaction.
+        let mut access = AccessSets::default();
+        access.touch_account(env.tx.caller);
+        if let TransactTo::Call(address) = env.tx.transact_to {
+            access.touch_account(address);
+        }
+        if GSPEC::enabled(SpecId::SHANGHAI) {
+            access.touch_account(env.block.coinbase);
+        }
+        for address in precompiles.addresses() {
+            access.touch_account(*address);
+        }
+        for (address, slots) in &env.tx.access_list {
+            access.touch_account(*address);
+            for slot in slots {
+                access.touch_slot(*address, *slot);
+            }
+        }
+
         Self {
             data: EVMData {
                 env,
@@ -61,11 +141,77 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> DummyHost<'a, GSPEC, DB
             },
             inspector,
             storage: HashMap::new(),
+            original_storage: HashMap::new(),
+            logs: vec![],
             hardcode,
+            tracers: Tracers::default(),
+            memory_limit_hit: false,
+            #[cfg(feature = "memory_limit")]
+            frame_memory: vec![0],
+            access,
+            changes: vec![],
             _phantomdata: PhantomData {},
         }
     }
 
+    /// Accounts loaded during the run, with their balance and nonce after it.
+    pub(crate) fn loaded_accounts(&self) -> impl Iterator<Item = (&B160, &AccountInfo)> {
+        self.data
+            .journaled_state
+            .state
+            .iter()
+            .map(|(address, account)| (address, &account.info))
+    }
+
+    fn checkpoint(&mut self) -> Checkpoint {
+        Checkpoint {
+            journal: self.data.journaled_state.checkpoint(),
+            changes: self.changes.len(),
+            logs: self.logs.len(),
+        }
+    }
+
+    fn checkpoint_revert(&mut self, checkpoint: Checkpoint) {
+        self.data
+            .journaled_state
+            .checkpoint_revert(checkpoint.journal);
+        for change in self.changes.drain(checkpoint.changes..).rev() {
+            match change {
+                HostChange::AccountWarmed(address) => {
+                    self.access.accounts.remove(&address);
+                }
+                HostChange::SlotWarmed(address, index) => {
+                    self.access.slots.remove(&(address, index));
+                }
+                HostChange::StorageChanged(key, Some(previous)) => {
+                    self.storage.insert(key, previous);
+                }
+                HostChange::StorageChanged(key, None) => {
+                    self.storage.remove(&key);
+                }
+            }
+        }
+        self.logs.truncate(checkpoint.logs);
+    }
+
+    /// Mark `address` warm, returning whether it was cold.
+    fn warm_account(&mut self, address: B160) -> bool {
+        let is_cold = self.access.touch_account(address);
+        if is_cold {
+            self.changes.push(HostChange::AccountWarmed(address));
+        }
+        is_cold
+    }
+
+    /// Mark `(address, index)` warm, returning whether it was cold.
+    fn warm_slot(&mut self, address: B160, index: U256) -> bool {
+        let is_cold = self.access.touch_slot(address, index);
+        if is_cold {
+            self.changes.push(HostChange::SlotWarmed(address, index));
+        }
+        is_cold
+    }
+
     /// Main contract call of the EVM.
     fn call_inner(&mut self, inputs: &mut CallInputs) -> (InstructionResult, Gas, Bytes) {
         // Call the inspector
@@ -111,7 +257,7 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> DummyHost<'a, GSPEC, DB
         }
 
         // Create subroutine checkpoint
-        let checkpoint = self.data.journaled_state.checkpoint();
+        let checkpoint = self.checkpoint();
 
         // Touch address. For "EIP-158 State Clear", this will erase empty accounts.
         if inputs.transfer.value == U256::ZERO {
@@ -126,7 +272,7 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> DummyHost<'a, GSPEC, DB
             inputs.transfer.value,
             self.data.db,
         ) {
-            self.data.journaled_state.checkpoint_revert(checkpoint);
+            self.checkpoint_revert(checkpoint);
             let (ret, gas, out) = (e, gas, Bytes::new());
             if INSPECT {
                 return self.inspector.call_end(
@@ -145,6 +291,9 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> DummyHost<'a, GSPEC, DB
         // Call precompiles
         let (ret, gas, out) = if let Some(precompile) = self.data.precompiles.get(&inputs.contract)
         {
+            if let Some(profile) = &mut self.tracers.profile {
+                profile.precompile_call(inputs.contract);
+            }
             let out = match precompile {
                 Precompile::Standard(fun) => fun(inputs.input.as_ref(), inputs.gas_limit),
                 Precompile::Custom(fun) => fun(inputs.input.as_ref(), inputs.gas_limit),
@@ -155,7 +304,7 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> DummyHost<'a, GSPEC, DB
                         self.data.journaled_state.checkpoint_commit();
                         (InstructionResult::Return, gas, Bytes::from(data))
                     } else {
-                        self.data.journaled_state.checkpoint_revert(checkpoint);
+                        self.checkpoint_revert(checkpoint);
                         (InstructionResult::PrecompileOOG, gas, Bytes::new())
                     }
                 }
@@ -165,27 +314,26 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> DummyHost<'a, GSPEC, DB
                     } else {
                         InstructionResult::PrecompileError
                     };
-                    self.data.journaled_state.checkpoint_revert(checkpoint);
+                    self.checkpoint_revert(checkpoint);
                     (ret, gas, Bytes::new())
                 }
             }
         } else {
-            println!("#### calldata:{:?},", hex::encode(inputs.input.to_vec()));
-            println!(
-                "### bytecode:{:?},",
-                hex::encode(bytecode.bytecode.to_vec())
-            );
             // Create interpreter and execute subcall
             let contract =
                 Contract::new_with_context(inputs.input.clone(), bytecode, &inputs.context);
 
             #[cfg(feature = "memory_limit")]
-            let mut interpreter = Interpreter::new_with_memory_limit(
-                contract,
-                gas.limit(),
-                inputs.is_static,
-                self.data.env.cfg.memory_limit,
-            );
+            let mut interpreter = {
+                let live: u64 = self.frame_memory.iter().sum();
+                self.frame_memory.push(0);
+                Interpreter::new_with_memory_limit(
+                    contract,
+                    gas.limit(),
+                    inputs.is_static,
+                    self.data.env.cfg.memory_limit.saturating_sub(live),
+                )
+            };
 
             #[cfg(not(feature = "memory_limit"))]
             let mut interpreter = Interpreter::new(contract, gas.limit(), inputs.is_static);
@@ -200,11 +348,14 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> DummyHost<'a, GSPEC, DB
             } else {
                 interpreter.run::<Self, GSPEC>(self)
             };
+            self.memory_limit_hit |= exit_reason == InstructionResult::MemoryLimitOOG;
+            #[cfg(feature = "memory_limit")]
+            self.frame_memory.pop();
 
             if matches!(exit_reason, return_ok!()) {
                 self.data.journaled_state.checkpoint_commit();
             } else {
-                self.data.journaled_state.checkpoint_revert(checkpoint);
+                self.checkpoint_revert(checkpoint);
             }
 
             (exit_reason, interpreter.gas, interpreter.return_value())
@@ -222,16 +373,57 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> DummyHost<'a, GSPEC, DB
 impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> Host
     for DummyHost<'a, GSPEC, DB, INSPECT>
 {
-    fn step(&mut self, _interp: &mut Interpreter, _is_static: bool) -> InstructionResult {
+    fn step(&mut self, interp: &mut Interpreter, _is_static: bool) -> InstructionResult {
+        #[cfg(feature = "memory_limit")]
+        if let Some(size) = memory_at_call(interp) {
+            *self.frame_memory.last_mut().unwrap() = size;
+        }
+        if let Some(coverage) = &mut self.tracers.coverage {
+            coverage.step(
+                self.data.journaled_state.depth() as usize,
+                interp.contract.bytecode.original_bytecode_slice(),
+                interp.program_counter(),
+                interp.current_opcode(),
+            );
+        }
+        if let Some(profile) = &mut self.tracers.profile {
+            profile.step(interp);
+        }
+        if let Some(steps) = &mut self.tracers.steps {
+            let op = interp.current_opcode();
+            let key = interp.stack.peek(0).unwrap_or_default();
+            let storage = match op {
+                opcode::SLOAD => Some((
+                    key,
+                    self.storage
+                        .get(&(interp.contract.address, key))
+                        .copied()
+                        .unwrap_or_default(),
+                )),
+                opcode::SSTORE => Some((key, interp.stack.peek(1).unwrap_or_default())),
+                _ => None,
+            };
+            steps.push(StepLog {
+                pc: interp.program_counter() as u64,
+                op,
+                gas: interp.gas.remaining(),
+                depth: self.data.journaled_state.depth() as u64 + 1,
+                stack: interp.stack.data().clone(),
+                storage,
+            });
+        }
         InstructionResult::Continue
     }
 
     fn step_end(
         &mut self,
-        _interp: &mut Interpreter,
+        interp: &mut Interpreter,
         _is_static: bool,
         _ret: InstructionResult,
     ) -> InstructionResult {
+        if let Some(coverage) = &mut self.tracers.coverage {
+            coverage.step_end(interp.program_counter());
+        }
         InstructionResult::Continue
     }
 
@@ -239,80 +431,115 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> Host
         &mut self.data.env
     }
 
-    fn load_account(&mut self, _address: B160) -> Option<(bool, bool)> {
-        Some((true, true))
+    fn load_account(&mut self, address: B160) -> Option<(bool, bool)> {
+        let journal = &mut self.data.journaled_state;
+        let db = &mut self.data.db;
+        let error = &mut self.data.error;
+
+        let (_, exists) = journal
+            .load_account_exist(address, db)
+            .map_err(|e| *error = Some(e))
+            .ok()?;
+        // Hardcoded code makes every account exist.
+        let exists = exists || self.hardcode.is_some();
+        Some((self.warm_account(address), exists))
     }
 
     fn block_hash(&mut self, _number: U256) -> Option<B256> {
         Some(B256::zero())
     }
 
-    fn balance(&mut self, _address: B160) -> Option<(U256, bool)> {
-        Some((U256::ZERO, false))
+    fn balance(&mut self, address: B160) -> Option<(U256, bool)> {
+        let journal = &mut self.data.journaled_state;
+        let db = &mut self.data.db;
+        let error = &mut self.data.error;
+
+        let (acc, _) = journal
+            .load_account(address, db)
+            .map_err(|e| *error = Some(e))
+            .ok()?;
+        let balance = acc.info.balance;
+        Some((balance, self.warm_account(address)))
     }
 
     fn code(&mut self, address: B160) -> Option<(Bytecode, bool)> {
-        if self.hardcode.is_some() {
-            return Some((
-                Bytecode::new_raw(Bytes::from(self.hardcode.clone().unwrap())),
-                false,
-            ));
+        if let Some(hardcode) = self.hardcode.clone() {
+            let is_cold = self.warm_account(address);
+            return Some((Bytecode::new_raw(Bytes::from(hardcode)), is_cold));
         }
 
         let journal = &mut self.data.journaled_state;
         let db = &mut self.data.db;
         let error = &mut self.data.error;
 
-        let (acc, is_cold) = journal
+        let (acc, _) = journal
             .load_code(address, db)
             .map_err(|e| *error = Some(e))
             .ok()?;
-        Some((acc.info.code.clone().unwrap(), is_cold))
+        let code = acc.info.code.clone().unwrap_or_default();
+        Some((code, self.warm_account(address)))
     }
 
-    fn code_hash(&mut self, __address: B160) -> Option<(B256, bool)> {
-        Some((KECCAK_EMPTY, false))
+    fn code_hash(&mut self, address: B160) -> Option<(B256, bool)> {
+        Some((KECCAK_EMPTY, self.warm_account(address)))
     }
 
-    fn sload(&mut self, __address: B160, index: U256) -> Option<(U256, bool)> {
-        match self.storage.entry(index) {
-            Entry::Occupied(entry) => Some((*entry.get(), false)),
-            Entry::Vacant(entry) => {
-                entry.insert(U256::ZERO);
-                Some((U256::ZERO, true))
-            }
+    fn sload(&mut self, address: B160, index: U256) -> Option<(U256, bool)> {
+        if let Some(profile) = &mut self.tracers.profile {
+            profile.storage_access(address, index);
         }
+        let is_cold = self.warm_slot(address, index);
+        let value = self
+            .storage
+            .get(&(address, index))
+            .copied()
+            .unwrap_or_default();
+        Some((value, is_cold))
     }
 
     fn sstore(
         &mut self,
-        _address: B160,
+        address: B160,
         index: U256,
         value: U256,
     ) -> Option<(U256, U256, U256, bool)> {
-        let (present, is_cold) = match self.storage.entry(index) {
-            Entry::Occupied(mut entry) => (entry.insert(value), false),
-            Entry::Vacant(entry) => {
-                entry.insert(value);
-                (U256::ZERO, true)
-            }
-        };
-
-        Some((U256::ZERO, present, value, is_cold))
+        if let Some(profile) = &mut self.tracers.profile {
+            profile.storage_access(address, index);
+        }
+        let is_cold = self.warm_slot(address, index);
+        let original = self
+            .original_storage
+            .get(&(address, index))
+            .copied()
+            .unwrap_or_default();
+        let previous = self.storage.insert((address, index), value);
+        self.changes
+            .push(HostChange::StorageChanged((address, index), previous));
+        let present = previous.unwrap_or_default();
+
+        Some((original, present, value, is_cold))
     }
 
-    fn log(&mut self, _address: B160, _topics: Vec<B256>, _data: Bytes) {
-        println!("log")
+    fn log(&mut self, address: B160, topics: Vec<B256>, data: Bytes) {
+        self.logs.push(Log {
+            address,
+            topics,
+            data,
+        });
     }
 
     fn selfdestruct(&mut self, _address: B160, _target: B160) -> Option<SelfDestructResult> {
-        panic!("Selfdestruct is not supported for this host")
+        // Not supported for this host, halts with `FatalExternalError`.
+        None
     }
 
     fn create(
         &mut self,
         _inputs: &mut CreateInputs,
     ) -> (InstructionResult, Option<B160>, Gas, Bytes) {
+        if let Some(profile) = &mut self.tracers.profile {
+            profile.call();
+        }
         (
             InstructionResult::Continue,
             None,
@@ -322,6 +549,147 @@ impl<'a, GSPEC: Spec, DB: Database, const INSPECT: bool> Host
     }
 
     fn call(&mut self, inputs: &mut CallInputs) -> (InstructionResult, Gas, Bytes) {
+        if let Some(profile) = &mut self.tracers.profile {
+            profile.call();
+        }
         self.call_inner(inputs)
     }
 }
+
+/// Memory size of `interp` once the call of the current step has expanded
+/// memory for its input and output, `None` for other steps.
+#[cfg(feature = "memory_limit")]
+fn memory_at_call(interp: &Interpreter) -> Option<u64> {
+    // Stack positions of the (offset, length) pairs the call reads memory at.
+    let ranges: &[(usize, usize)] = match interp.current_opcode() {
+        opcode::CALL | opcode::CALLCODE => &[(3, 4), (5, 6)],
+        opcode::DELEGATECALL | opcode::STATICCALL => &[(2, 3), (4, 5)],
+        _ => return None,
+    };
+    let arg = |n: usize| {
+        interp
+            .stack
+            .peek(n)
+            .ok()
+            .and_then(|value| u64::try_from(value).ok())
+            .unwrap_or(u64::MAX)
+    };
+    Some(ranges.iter().fold(
+        interp.memory.len() as u64,
+        |size, (offset, len)| match arg(*len) {
+            0 => size,
+            len => size.max(arg(*offset).saturating_add(len).saturating_add(31) / 32 * 32),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use revm_primitives::BlockEnv;
+
+    use crate::dry_run::{
+        bytecode_run::{bytecode_run_with_config, RunConfig},
+        trie::AccountState,
+    };
+
+    use super::*;
+
+    const X: &str = "0000000000000000000000000000000000000042";
+    const CHILD: &str = "000000000000000000000000000000000000c0de";
+
+    fn address(hex: &str) -> B160 {
+        hex.parse().unwrap()
+    }
+
+    fn execution_gas(code: &str, config: &RunConfig) -> u64 {
+        bytecode_run_with_config(vec![], hex::decode(code).unwrap(), None, config)
+            .unwrap()
+            .execution_gas
+    }
+
+    #[test]
+    fn access_list_prewarms() {
+        // PUSH20 X BALANCE STOP, PUSH1 7 SLOAD STOP
+        let balance = format!("73{X}3100");
+        let sload = "60075400";
+        assert_eq!(execution_gas(&balance, &RunConfig::default()), 3 + 2600);
+        assert_eq!(execution_gas(sload, &RunConfig::default()), 3 + 2100);
+
+        let config = RunConfig {
+            access_list: vec![(address(X), vec![]), (B160::zero(), vec![U256::from(7)])],
+            ..Default::default()
+        };
+        assert_eq!(execution_gas(&balance, &config), 3 + 100);
+        assert_eq!(execution_gas(sload, &config), 3 + 100);
+    }
+
+    #[test]
+    fn precompiles_origin_target_and_coinbase_are_warm() {
+        let config = RunConfig {
+            caller: address("000000000000000000000000000000000000ca11"),
+            address: address("0000000000000000000000000000000000000100"),
+            block: BlockEnv {
+                coinbase: address("000000000000000000000000000000000000c014"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // PUSH20 <precompile 1> BALANCE STOP
+        let precompile = format!("73{}3100", "00".repeat(19) + "01");
+        assert_eq!(execution_gas(&precompile, &config), 3 + 100);
+        // ORIGIN BALANCE STOP, ADDRESS BALANCE STOP, COINBASE BALANCE STOP
+        for code in ["323100", "303100", "413100"] {
+            assert_eq!(execution_gas(code, &config), 2 + 100, "{code}");
+        }
+    }
+
+    #[test]
+    fn reverted_frames_drop_their_warm_accounts() {
+        // CALL(gas, CHILD, 0, 0, 0, 0, 0) POP PUSH20 X BALANCE STOP
+        let entry = format!("6000600060006000600073{CHILD}5af15073{X}3100");
+        let run = |child_end: &str| {
+            // PUSH20 X BALANCE PUSH1 0 PUSH1 0 <RETURN or REVERT>
+            let child = AccountState {
+                code: hex::decode(format!("73{X}3160006000{child_end}")).unwrap(),
+                ..Default::default()
+            };
+            let config = RunConfig {
+                accounts: BTreeMap::from([(address(CHILD), child)]),
+                ..Default::default()
+            };
+            execution_gas(&entry, &config)
+        };
+        assert_eq!(run("fd") - run("f3"), 2600 - 100);
+    }
+
+    #[test]
+    fn value_calls_to_missing_accounts_pay_for_creation() {
+        let target = address(X);
+        let run = |accounts: BTreeMap<B160, AccountState>| {
+            let mut accounts = accounts;
+            accounts.insert(
+                B160::zero(),
+                AccountState {
+                    balance: U256::from(10),
+                    ..Default::default()
+                },
+            );
+            let config = RunConfig {
+                accounts,
+                ..Default::default()
+            };
+            // CALL(gas, X, 1, 0, 0, 0, 0) STOP
+            execution_gas(&format!("6000600060006000600173{X}5af100"), &config)
+        };
+        let existing = BTreeMap::from([(
+            target,
+            AccountState {
+                balance: U256::from(1),
+                ..Default::default()
+            },
+        )]);
+        assert_eq!(run(BTreeMap::new()) - run(existing), 25_000);
+    }
+}
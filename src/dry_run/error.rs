@@ -6,4 +6,11 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     #[error("Interpreter inner error: {0}")]
     InterpreterError(String),
+    /// Revert data, and the transaction gas used up to the revert.
+    #[error("Execution reverted: 0x{}", hex::encode(.output))]
+    Reverted { output: Vec<u8>, gas_used: u64 },
+    #[error("Memory limit of {0} bytes exceeded")]
+    MemoryLimitExceeded(u64),
+    #[error("Source map error: {0}")]
+    SourceMapError(String),
 }
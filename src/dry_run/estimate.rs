@@ -0,0 +1,101 @@
+//! Gas estimation by binary search over the gas limit, as `eth_estimateGas`.
+use crate::dry_run::{
+    bytecode_run::{bytecode_run_with_config, RunConfig},
+    error::Result,
+    gas,
+};
+
+/// Stipend a value transfer adds to the callee's gas.
+const CALL_STIPEND: u64 = 2_300;
+
+/// Minimal transaction gas limit, intrinsic gas included, under which the
+/// call still succeeds. `config.gas_limit` caps the search, the error of the
+/// run at the cap is returned when the call fails there.
+///
+/// Gas spent is not enough: a frame forwards at most 63/64 of its gas to a
+/// nested call, so the limit has to leave the 1/64 each call keeps back.
+pub fn estimate_gas(
+    calldata: Vec<u8>,
+    bytecode: Vec<u8>,
+    hardcode: Option<Vec<u8>>,
+    config: &RunConfig,
+) -> Result<u64> {
+    let intrinsic_gas =
+        gas::intrinsic_gas(&calldata, &config.access_list, config.hardfork.spec_id());
+    let succeeds = |gas_limit| {
+        let config = RunConfig {
+            gas_limit,
+            ..config.clone()
+        };
+        bytecode_run_with_config(
+            calldata.clone(),
+            bytecode.clone(),
+            hardcode.clone(),
+            &config,
+        )
+        .is_ok()
+    };
+
+    let output =
+        bytecode_run_with_config(calldata.clone(), bytecode.clone(), hardcode.clone(), config)?;
+    if output.execution_gas == 0 {
+        return Ok(intrinsic_gas);
+    }
+    // Below the gas spent the run always fails.
+    let mut low = output.execution_gas.saturating_sub(1);
+    let mut high = config.gas_limit;
+    // Most calls succeed with the gas spent plus what nested calls keep
+    // back, try that before searching.
+    let optimistic = output
+        .execution_gas
+        .saturating_add(CALL_STIPEND)
+        .saturating_mul(64)
+        / 63;
+    if optimistic < high {
+        if succeeds(optimistic) {
+            high = optimistic;
+        } else {
+            low = optimistic;
+        }
+    }
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if succeeds(mid) {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    Ok(intrinsic_gas.saturating_add(high))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_call_keeps_one_64th() {
+        // STATICCALL(GAS, 0x1000, 0, 0, 0, 0), reverting when it fails
+        let bytecode = hex::decode("60006000600060006110005afa601457600080fd5b00").unwrap();
+        // PUSH1 1 PUSH2 0x2000 MSTORE: expand memory to 8 KiB
+        let callee = hex::decode("60016120005200").unwrap();
+        let config = RunConfig {
+            gas_limit: 1_000_000,
+            ..Default::default()
+        };
+
+        let estimate =
+            estimate_gas(vec![], bytecode.clone(), Some(callee.clone()), &config).unwrap();
+        let run = |gas_limit| {
+            let config = RunConfig {
+                gas_limit,
+                ..config.clone()
+            };
+            bytecode_run_with_config(vec![], bytecode.clone(), Some(callee.clone()), &config)
+        };
+        let spent = run(config.gas_limit).unwrap().gas_used;
+        assert!(estimate > spent);
+        assert!(run(estimate - 21_000).is_ok());
+        assert!(run(estimate - 21_001).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! Differential fuzzing of `bytecode_run` against the reference revm EVM,
+//! reporting panics, hangs and result or gas mismatches.
+use std::{sync::mpsc, thread, time::Duration};
+
+use proptest::{
+    collection::vec,
+    prelude::*,
+    test_runner::{Config, TestCaseError, TestError, TestRunner},
+};
+use revm_interpreter::opcode;
+
+use crate::dry_run::{
+    bytecode_run::{bytecode_run_with_config, RunConfig},
+    coverage::instruction_offsets,
+    differential,
+    error::Error,
+};
+
+/// Opcodes pushing values the two sides legitimately disagree on, so the
+/// return data of a case running them is not compared.
+const MASKED_OPCODES: &[u8] = &[opcode::GAS, opcode::BLOCKHASH];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Returned successfully with the given data and transaction gas.
+    Success { output: Vec<u8>, gas_used: u64 },
+    /// Reverted or halted, with the transaction gas when known.
+    Failure { gas_used: Option<u64> },
+}
+
+impl Outcome {
+    fn agrees_with(&self, other: &Outcome, compare_output: bool) -> bool {
+        match (self, other) {
+            (
+                Outcome::Success { output, gas_used },
+                Outcome::Success {
+                    output: other_output,
+                    gas_used: other_gas_used,
+                },
+            ) => gas_used == other_gas_used && (!compare_output || output == other_output),
+            (Outcome::Failure { gas_used }, Outcome::Failure { gas_used: other }) => {
+                gas_used.zip(*other).map_or(true, |(a, b)| a == b)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Finding {
+    Panic(String),
+    Hang,
+    Mismatch {
+        dry_run: Outcome,
+        reference: Outcome,
+    },
+}
+
+/// Settings of a fuzzing session.
+#[derive(Clone, Debug)]
+pub struct FuzzConfig {
+    pub cases: u32,
+    pub run: RunConfig,
+    pub timeout: Duration,
+    pub max_bytecode_len: usize,
+    pub max_calldata_len: usize,
+}
+
+impl Default for FuzzConfig {
+    fn default() -> Self {
+        Self {
+            cases: 10_000,
+            run: RunConfig {
+                gas_limit: 10_000_000,
+                ..Default::default()
+            },
+            timeout: Duration::from_secs(5),
+            max_bytecode_len: 512,
+            max_calldata_len: 132,
+        }
+    }
+}
+
+/// Run one case through the dry run host and the reference EVM.
+pub fn check(
+    calldata: &[u8],
+    bytecode: &[u8],
+    config: &RunConfig,
+    timeout: Duration,
+) -> Option<Finding> {
+    let (tx, rx) = mpsc::channel();
+    let (calldata_owned, bytecode_owned) = (calldata.to_vec(), bytecode.to_vec());
+    let config_owned = config.clone();
+    // A hanging run cannot be interrupted, its thread is left behind.
+    let handle = thread::spawn(move || {
+        let result = bytecode_run_with_config(calldata_owned, bytecode_owned, None, &config_owned);
+        let _ = tx.send(result);
+    });
+
+    let dry_run = match rx.recv_timeout(timeout) {
+        Ok(Ok(out)) => Outcome::Success {
+            output: out.return_data,
+            gas_used: out.gas_used,
+        },
+        Ok(Err(Error::Reverted { gas_used, .. })) => Outcome::Failure {
+            gas_used: Some(gas_used),
+        },
+        Ok(Err(_)) => Outcome::Failure { gas_used: None },
+        Err(mpsc::RecvTimeoutError::Timeout) => return Some(Finding::Hang),
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            let reason = handle
+                .join()
+                .err()
+                .and_then(|e| {
+                    e.downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| e.downcast_ref::<String>().cloned())
+                })
+                .unwrap_or_default();
+            return Some(Finding::Panic(reason));
+        }
+    };
+
+    let observation = differential::reference_run(calldata, bytecode, None, config);
+    let reference = if observation.success {
+        Outcome::Success {
+            output: observation.output,
+            gas_used: observation.gas_used.unwrap_or_default(),
+        }
+    } else {
+        Outcome::Failure {
+            gas_used: observation.gas_used,
+        }
+    };
+    let compare_output = !instruction_offsets(bytecode)
+        .into_iter()
+        .any(|pc| MASKED_OPCODES.contains(&bytecode[pc]));
+    (!dry_run.agrees_with(&reference, compare_output))
+        .then_some(Finding::Mismatch { dry_run, reference })
+}
+
+/// Fuzz with random calldata and bytecode until `config.cases` cases pass or
+/// a finding is shrunk to a minimal `(calldata, bytecode)` pair.
+pub fn fuzz(config: &FuzzConfig) -> Result<(), TestError<(Vec<u8>, Vec<u8>)>> {
+    let mut runner = TestRunner::new(Config {
+        cases: config.cases,
+        failure_persistence: None,
+        ..Config::default()
+    });
+    let strategy = (
+        vec(any::<u8>(), 0..=config.max_calldata_len),
+        vec(any::<u8>(), 1..=config.max_bytecode_len),
+    );
+    runner.run(&strategy, |(calldata, bytecode)| {
+        match check(&calldata, &bytecode, &config.run, config.timeout) {
+            Some(finding) => Err(TestCaseError::fail(format!("{finding:?}"))),
+            None => Ok(()),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn revert_and_stop_agree_with_reference() {
+        let timeout = Duration::from_secs(5);
+        let config = RunConfig {
+            gas_limit: 1_000_000,
+            ..Default::default()
+        };
+        // PUSH1 0 PUSH1 0 REVERT
+        let revert = hex::decode("60006000fd").unwrap();
+        assert!(check(&[], &revert, &config, timeout).is_none());
+        // PUSH1 0x2a PUSH1 0 MSTORE PUSH1 0x20 PUSH1 0 RETURN
+        let ret = hex::decode("602a60005260206000f3").unwrap();
+        assert!(check(&[1, 2, 3], &ret, &config, timeout).is_none());
+    }
+
+    #[test]
+    fn storage_paths_are_checked() {
+        let config = RunConfig {
+            gas_limit: 1_000_000,
+            ..Default::default()
+        };
+        // PUSH1 1 PUSH1 0 SSTORE PUSH1 0 SLOAD PUSH1 0 MSTORE PUSH1 0x20 PUSH1 0 RETURN
+        let code = hex::decode("600160005560005460005260206000f3").unwrap();
+        assert!(check(&[], &code, &config, Duration::from_secs(5)).is_none());
+    }
+}
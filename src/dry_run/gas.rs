@@ -0,0 +1,47 @@
+use revm_primitives::{SpecId, B160, U256};
+
+const TX_BASE_GAS: u64 = 21_000;
+const TX_DATA_ZERO_GAS: u64 = 4;
+const TX_DATA_NON_ZERO_GAS_FRONTIER: u64 = 68;
+const TX_DATA_NON_ZERO_GAS_ISTANBUL: u64 = 16;
+const ACCESS_LIST_ADDRESS_GAS: u64 = 2_400;
+const ACCESS_LIST_STORAGE_KEY_GAS: u64 = 1_900;
+
+/// Gas charged before execution starts for a call transaction.
+pub fn intrinsic_gas(calldata: &[u8], access_list: &[(B160, Vec<U256>)], spec: SpecId) -> u64 {
+    let non_zero_gas = if SpecId::enabled(spec, SpecId::ISTANBUL) {
+        TX_DATA_NON_ZERO_GAS_ISTANBUL
+    } else {
+        TX_DATA_NON_ZERO_GAS_FRONTIER
+    };
+    let data_gas: u64 = calldata
+        .iter()
+        .map(|b| {
+            if *b == 0 {
+                TX_DATA_ZERO_GAS
+            } else {
+                non_zero_gas
+            }
+        })
+        .sum();
+    let access_list_gas: u64 = access_list
+        .iter()
+        .map(|(_, slots)| {
+            ACCESS_LIST_ADDRESS_GAS + ACCESS_LIST_STORAGE_KEY_GAS * slots.len() as u64
+        })
+        .sum();
+
+    TX_BASE_GAS + data_gas + access_list_gas
+}
+
+/// Gas charged to the transaction once execution spent `spent` and
+/// accumulated `refunded`, with the refund capped per EIP-3529.
+pub fn tx_gas_used(intrinsic: u64, spent: u64, refunded: i64, spec: SpecId) -> u64 {
+    let used = intrinsic + spent;
+    let quotient = if SpecId::enabled(spec, SpecId::LONDON) {
+        5
+    } else {
+        2
+    };
+    used - (refunded.max(0) as u64).min(used / quotient)
+}
@@ -0,0 +1,45 @@
+use clap::ValueEnum;
+use revm_primitives::SpecId;
+
+/// Hardfork whose rules the dry run interpreter and precompiles follow.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Hardfork {
+    Berlin,
+    London,
+    Merge,
+    /// The linked prover is built with the `shanghai` feature.
+    #[default]
+    Shanghai,
+}
+
+impl Hardfork {
+    pub fn spec_id(&self) -> SpecId {
+        match self {
+            Hardfork::Berlin => SpecId::BERLIN,
+            Hardfork::London => SpecId::LONDON,
+            Hardfork::Merge => SpecId::MERGE,
+            Hardfork::Shanghai => SpecId::SHANGHAI,
+        }
+    }
+}
+
+/// Call `$f::<Spec, ...>($args)` with the revm spec type of `$hardfork`.
+macro_rules! with_spec {
+    ($hardfork:expr, $f:ident::<_ $(, $generic:tt)*>($($args:expr),* $(,)?)) => {
+        match $hardfork {
+            $crate::dry_run::hardfork::Hardfork::Berlin => {
+                $f::<revm_primitives::BerlinSpec $(, $generic)*>($($args),*)
+            }
+            $crate::dry_run::hardfork::Hardfork::London => {
+                $f::<revm_primitives::LondonSpec $(, $generic)*>($($args),*)
+            }
+            $crate::dry_run::hardfork::Hardfork::Merge => {
+                $f::<revm_primitives::MergeSpec $(, $generic)*>($($args),*)
+            }
+            $crate::dry_run::hardfork::Hardfork::Shanghai => {
+                $f::<revm_primitives::ShanghaiSpec $(, $generic)*>($($args),*)
+            }
+        }
+    };
+}
+pub(crate) use with_spec;
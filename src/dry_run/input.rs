@@ -0,0 +1,487 @@
+//! Structured dry run input: a JSON file naming the call and the contracts it
+//! runs against, or a Foundry/Hardhat compilation artifact.
+use std::{collections::BTreeMap, fs, path::Path, str::FromStr};
+
+use revm_primitives::{B160, U256};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::dry_run::{
+    bytecode_run::RunConfig, selectors::selector_of, session::decimal_or_hex, trie::AccountState,
+};
+
+/// JSON dry run input.
+///
+/// ```json
+/// {
+///   "contracts": [
+///     { "name": "BasicToken", "address": "0x0000000000000000000000000000000000000100",
+///       "artifact": "out/BasicToken.sol/BasicToken.json" }
+///   ],
+///   "call": { "to": "BasicToken", "function": "add(uint256,uint256)", "args": ["2", "3"] }
+/// }
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct InputFile {
+    pub contracts: Vec<ContractInput>,
+    #[serde(default)]
+    pub call: CallInput,
+    /// Code run by every nested call instead of the callee's.
+    #[serde(default)]
+    pub hardcode: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContractInput {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Required when there is more than one contract, the zero address
+    /// otherwise.
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Deployed bytecode in hex, exclusive with `artifact`.
+    #[serde(default)]
+    pub bytecode: Option<String>,
+    /// Foundry or Hardhat artifact, relative to the input file.
+    #[serde(default)]
+    pub artifact: Option<String>,
+    /// Decimal, or hex when `0x` prefixed, as are storage slots and values.
+    #[serde(default)]
+    pub balance: Option<String>,
+    #[serde(default)]
+    pub storage: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CallInput {
+    /// Name or address of the contract called, the first one by default.
+    #[serde(default)]
+    pub to: Option<String>,
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub value: Option<String>,
+    /// Calldata in hex, exclusive with `function`.
+    #[serde(default)]
+    pub calldata: Option<String>,
+    /// Function name, looked up in the ABI, or signature.
+    #[serde(default)]
+    pub function: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Foundry `out/*.json` or Hardhat artifact.
+#[derive(Clone, Debug, Default)]
+pub struct Artifact {
+    pub abi: Vec<Value>,
+    /// Creation code.
+    pub bytecode: Vec<u8>,
+    /// Runtime code, the one dry runs execute.
+    pub deployed_bytecode: Vec<u8>,
+    /// Source map of the runtime code, when the artifact has one.
+    pub deployed_source_map: Option<String>,
+}
+
+impl Artifact {
+    /// Parse an artifact, Foundry nesting code under `object` where Hardhat
+    /// has it inline.
+    pub fn from_json(json: &Value) -> anyhow::Result<Self> {
+        let code = |field: &str| -> anyhow::Result<Vec<u8>> {
+            let code = match json.get(field) {
+                Some(Value::String(code)) => code.as_str(),
+                Some(Value::Object(code)) => code
+                    .get("object")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default(),
+                _ => "",
+            };
+            anyhow::ensure!(
+                !code.contains("__"),
+                "{field} has unlinked library placeholders"
+            );
+            Ok(hex::decode(code.trim_start_matches("0x"))?)
+        };
+        let deployed_bytecode = code("deployedBytecode")?;
+        anyhow::ensure!(
+            !deployed_bytecode.is_empty(),
+            "artifact has no deployedBytecode"
+        );
+        Ok(Self {
+            abi: json
+                .get("abi")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default(),
+            bytecode: code("bytecode")?,
+            deployed_bytecode,
+            deployed_source_map: json
+                .pointer("/deployedBytecode/sourceMap")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+    }
+
+    /// `name(type,...)` of every function in the ABI.
+    pub fn signatures(&self) -> Vec<String> {
+        self.abi
+            .iter()
+            .filter(|item| item.get("type").and_then(Value::as_str) == Some("function"))
+            .filter_map(|item| {
+                let name = item.get("name")?.as_str()?;
+                let inputs: Vec<_> = item
+                    .get("inputs")?
+                    .as_array()?
+                    .iter()
+                    .map(abi_type)
+                    .collect::<Option<_>>()?;
+                Some(format!("{name}({})", inputs.join(",")))
+            })
+            .collect()
+    }
+}
+
+/// Canonical type of an ABI parameter, tuples expanded.
+fn abi_type(param: &Value) -> Option<String> {
+    let ty = param.get("type")?.as_str()?;
+    match ty.strip_prefix("tuple") {
+        Some(suffix) => {
+            let components: Vec<_> = param
+                .get("components")?
+                .as_array()?
+                .iter()
+                .map(abi_type)
+                .collect::<Option<_>>()?;
+            Some(format!("({}){suffix}", components.join(",")))
+        }
+        None => Some(ty.to_string()),
+    }
+}
+
+/// A loaded input, ready to run.
+#[derive(Clone, Debug, Default)]
+pub struct DryRunInput {
+    pub calldata: Vec<u8>,
+    /// Code of the called contract.
+    pub bytecode: Vec<u8>,
+    pub hardcode: Option<Vec<u8>>,
+    pub address: B160,
+    pub caller: B160,
+    pub value: U256,
+    /// Every contract, the called one included.
+    pub accounts: BTreeMap<B160, AccountState>,
+    /// Artifact of the called contract, when it came from one.
+    pub artifact: Option<Artifact>,
+}
+
+impl DryRunInput {
+    /// `config` running this input.
+    pub fn config(&self, config: &RunConfig) -> RunConfig {
+        let mut accounts = config.accounts.clone();
+        accounts.extend(self.accounts.clone());
+        RunConfig {
+            address: self.address,
+            caller: self.caller,
+            value: self.value,
+            accounts,
+            ..config.clone()
+        }
+    }
+}
+
+/// Load a JSON input or artifact file. `calldata` is used when the file does
+/// not give one, as artifacts never do.
+pub fn load_input(path: &str, calldata: Option<&str>) -> anyhow::Result<DryRunInput> {
+    let json: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if json.get("contracts").is_none() {
+        let artifact = Artifact::from_json(&json)?;
+        return Ok(DryRunInput {
+            calldata: decode_hex(calldata.unwrap_or_default())?,
+            bytecode: artifact.deployed_bytecode.clone(),
+            accounts: BTreeMap::from([(
+                B160::zero(),
+                AccountState {
+                    code: artifact.deployed_bytecode.clone(),
+                    ..Default::default()
+                },
+            )]),
+            artifact: Some(artifact),
+            ..Default::default()
+        });
+    }
+
+    let input: InputFile = serde_json::from_value(json)?;
+    let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    anyhow::ensure!(!input.contracts.is_empty(), "no contracts in {path}");
+    anyhow::ensure!(
+        input.contracts.len() == 1 || input.contracts.iter().all(|c| c.address.is_some()),
+        "every contract needs an address when there are several"
+    );
+
+    let mut accounts = BTreeMap::new();
+    let mut loaded = vec![];
+    for contract in &input.contracts {
+        let address = contract
+            .address
+            .as_deref()
+            .map_or(Ok(B160::zero()), B160::from_str)?;
+        let artifact = match (&contract.bytecode, &contract.artifact) {
+            (Some(_), Some(_)) => anyhow::bail!("give either bytecode or artifact, not both"),
+            (Some(_), None) => None,
+            (None, Some(artifact)) => {
+                let json = serde_json::from_str(&fs::read_to_string(dir.join(artifact))?)?;
+                Some(Artifact::from_json(&json)?)
+            }
+            (None, None) => anyhow::bail!("contract {address:?} has no code"),
+        };
+        let code = match (&contract.bytecode, &artifact) {
+            (Some(bytecode), _) => decode_hex(bytecode)?,
+            (None, Some(artifact)) => artifact.deployed_bytecode.clone(),
+            (None, None) => unreachable!(),
+        };
+        accounts.insert(
+            address,
+            AccountState {
+                balance: contract
+                    .balance
+                    .as_deref()
+                    .map_or(Ok(U256::ZERO), decimal_or_hex)?,
+                nonce: 1,
+                code,
+                storage: contract
+                    .storage
+                    .iter()
+                    .map(|(slot, value)| Ok((decimal_or_hex(slot)?, decimal_or_hex(value)?)))
+                    .collect::<anyhow::Result<_>>()?,
+            },
+        );
+        loaded.push((contract.name.as_deref(), address, artifact));
+    }
+
+    let call = &input.call;
+    let (_, address, artifact) = match &call.to {
+        None => loaded[0].clone(),
+        Some(to) => loaded
+            .iter()
+            .find(|(name, address, _)| {
+                *name == Some(to.as_str()) || B160::from_str(to).ok() == Some(*address)
+            })
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("no contract {to}"))?,
+    };
+    let calldata = match (&call.calldata, &call.function) {
+        (Some(_), Some(_)) => anyhow::bail!("give either calldata or function, not both"),
+        (Some(calldata), None) => decode_hex(calldata)?,
+        (None, Some(function)) => {
+            let signature = if function.contains('(') {
+                function.clone()
+            } else {
+                let abi = artifact
+                    .as_ref()
+                    .map(Artifact::signatures)
+                    .unwrap_or_default();
+                let mut matches = abi
+                    .into_iter()
+                    .filter(|signature| signature.starts_with(&format!("{function}(")));
+                let signature = matches
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("no function {function} in the ABI"))?;
+                anyhow::ensure!(
+                    matches.next().is_none(),
+                    "{function} is overloaded, give its signature"
+                );
+                signature
+            };
+            encode_call(&signature, &call.args)?
+        }
+        (None, None) => decode_hex(calldata.unwrap_or_default())?,
+    };
+
+    Ok(DryRunInput {
+        calldata,
+        bytecode: accounts[&address].code.clone(),
+        hardcode: input.hardcode.as_deref().map(decode_hex).transpose()?,
+        address,
+        caller: call
+            .from
+            .as_deref()
+            .map_or(Ok(B160::zero()), B160::from_str)?,
+        value: call
+            .value
+            .as_deref()
+            .map_or(Ok(U256::ZERO), decimal_or_hex)?,
+        accounts,
+        artifact,
+    })
+}
+
+fn decode_hex(hex_str: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(hex::decode(hex_str.trim_start_matches("0x"))?)
+}
+
+/// ABI encoded call of `signature` with `args`. Only static elementary
+/// parameters are supported, arrays are not.
+pub fn encode_call(signature: &str, args: &[String]) -> anyhow::Result<Vec<u8>> {
+    let types = signature
+        .split_once('(')
+        .and_then(|(_, rest)| rest.strip_suffix(')'))
+        .ok_or_else(|| anyhow::anyhow!("invalid signature {signature}"))?;
+    let types: Vec<_> = types.split(',').filter(|ty| !ty.is_empty()).collect();
+    anyhow::ensure!(
+        types.len() == args.len(),
+        "{signature} takes {} arguments, {} given",
+        types.len(),
+        args.len()
+    );
+
+    let mut calldata = selector_of(signature).to_vec();
+    for (ty, arg) in types.iter().zip(args) {
+        calldata.extend(encode_word(ty, arg)?);
+    }
+    Ok(calldata)
+}
+
+fn encode_word(ty: &str, arg: &str) -> anyhow::Result<[u8; 32]> {
+    anyhow::ensure!(!ty.contains('['), "unsupported array type {ty}");
+    // Width in bits of `uintN`/`intN`, 256 when omitted.
+    let bits = |suffix: &str| -> anyhow::Result<usize> {
+        let bits = if suffix.is_empty() {
+            256
+        } else {
+            suffix.parse()?
+        };
+        anyhow::ensure!(
+            bits % 8 == 0 && (8..=256).contains(&bits),
+            "invalid parameter type {ty}"
+        );
+        Ok(bits)
+    };
+    let word = match ty {
+        "address" => {
+            let mut word = [0u8; 32];
+            word[12..].copy_from_slice(B160::from_str(arg)?.as_bytes());
+            return Ok(word);
+        }
+        "bool" => match arg {
+            "true" => U256::from(1),
+            "false" => U256::ZERO,
+            _ => anyhow::bail!("invalid bool {arg}"),
+        },
+        _ if ty.starts_with("uint") => {
+            let value = decimal_or_hex(arg)?;
+            anyhow::ensure!(
+                value.bit_len() <= bits(&ty[4..])?,
+                "{arg} does not fit {ty}"
+            );
+            value
+        }
+        _ if ty.starts_with("int") => {
+            let bits = bits(&ty[3..])?;
+            let (negative, magnitude) = match arg.strip_prefix('-') {
+                Some(magnitude) => (true, decimal_or_hex(magnitude)?),
+                None => (false, decimal_or_hex(arg)?),
+            };
+            // Two's complement range, -2^(bits-1) to 2^(bits-1) - 1.
+            let bound = U256::from(1) << (bits - 1);
+            anyhow::ensure!(
+                if negative {
+                    magnitude <= bound
+                } else {
+                    magnitude < bound
+                },
+                "{arg} does not fit {ty}"
+            );
+            if negative {
+                U256::ZERO.wrapping_sub(magnitude)
+            } else {
+                magnitude
+            }
+        }
+        _ if ty.starts_with("bytes") && ty.len() > "bytes".len() => {
+            let size: usize = ty["bytes".len()..].parse()?;
+            anyhow::ensure!((1..=32).contains(&size), "invalid parameter type {ty}");
+            let bytes = decode_hex(arg)?;
+            anyhow::ensure!(bytes.len() <= size, "{arg} does not fit {ty}");
+            let mut word = [0u8; 32];
+            word[..bytes.len()].copy_from_slice(&bytes);
+            return Ok(word);
+        }
+        _ => anyhow::bail!("unsupported parameter type {ty}"),
+    };
+    Ok(word.to_be_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn foundry_and_hardhat_artifacts() {
+        let abi = serde_json::json!([{
+            "type": "function",
+            "name": "add",
+            "inputs": [{ "name": "a", "type": "uint256" }, { "name": "b", "type": "uint256" }],
+        }]);
+        let foundry = serde_json::json!({
+            "abi": abi,
+            "bytecode": { "object": "0x6000" },
+            "deployedBytecode": { "object": "0x00", "sourceMap": "0:1:0:-:0" },
+        });
+        let hardhat = serde_json::json!({
+            "_format": "hh-sol-artifact-1",
+            "abi": abi,
+            "bytecode": "0x6000",
+            "deployedBytecode": "0x00",
+        });
+
+        for json in [foundry, hardhat] {
+            let artifact = Artifact::from_json(&json).unwrap();
+            assert_eq!(artifact.deployed_bytecode, [0]);
+            assert_eq!(artifact.bytecode, [0x60, 0]);
+            assert_eq!(artifact.signatures(), ["add(uint256,uint256)"]);
+        }
+    }
+
+    #[test]
+    fn encode_add_call() {
+        let calldata = encode_call("add(uint256,uint256)", &["2".into(), "0x3".into()]).unwrap();
+        assert_eq!(
+            hex::encode(calldata),
+            "771602f700000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000003"
+        );
+    }
+
+    #[test]
+    fn encode_checks_types_and_ranges() {
+        let encode = |ty: &str, arg: &str| encode_word(ty, arg).map(hex::encode);
+        assert_eq!(encode("uint8", "255").unwrap(), format!("{:064x}", 255));
+        assert!(encode("uint8", "256").is_err());
+        assert!(encode("uint7", "1").is_err());
+        assert_eq!(encode("int8", "-128").unwrap(), format!("{:f>62}80", ""));
+        assert!(encode("int8", "-129").is_err());
+        assert!(encode("int8", "128").is_err());
+        assert_eq!(encode("int", "-1").unwrap(), "f".repeat(64));
+        assert!(encode("bytes2", "0x010203").is_err());
+        assert!(encode("uint256[]", "1").is_err());
+        assert!(encode("uint256[2]", "1").is_err());
+    }
+
+    #[test]
+    fn load_basic_token_input() {
+        let input = load_input("data/basic-token.json", None).unwrap();
+        let token = B160::from_low_u64_be(0x100);
+        assert_eq!(input.address, token);
+        assert_eq!(input.caller, B160::zero());
+        assert_eq!(
+            input.calldata,
+            encode_call("add(uint256,uint256)", &["2".into(), "3".into()]).unwrap()
+        );
+        assert_eq!(input.accounts.keys().collect::<Vec<_>>(), [&token]);
+        assert_eq!(input.accounts[&token].code, input.bytecode);
+        assert_eq!(input.accounts[&token].nonce, 1);
+        assert!(input.artifact.is_none());
+    }
+}
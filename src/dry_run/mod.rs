@@ -1,3 +1,19 @@
 pub mod bytecode_run;
+pub mod coverage;
+pub mod differential;
 mod dummy;
-mod error;
\ No newline at end of file
+pub mod error;
+pub mod estimate;
+pub mod fuzz;
+pub mod gas;
+pub mod hardfork;
+pub mod input;
+pub mod replay;
+pub mod row_usage;
+pub mod rpc;
+pub mod selectors;
+pub mod session;
+pub mod state_test;
+pub mod suite;
+pub mod trie;
+pub mod zk_state;
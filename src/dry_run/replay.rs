@@ -0,0 +1,632 @@
+//! Replay of block trace transactions through the dry run host, comparing
+//! every executed step with the trace `structLogs`.
+//!
+//! Only the trace fields the replay needs are read. The pre-state is decoded
+//! from the zktrie leaves of `storageTrace`, then completed with the accounts
+//! l2geth attaches to execution results and steps, code being matched by its
+//! keccak hash.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    str::FromStr,
+};
+
+use revm_interpreter::opcode::OPCODE_JUMPMAP;
+use revm_primitives::{keccak256, BlockEnv, B160, B256, U256};
+use serde::Deserialize;
+
+use crate::{
+    dry_run::{
+        bytecode_run::{bytecode_run_with_steps, RunConfig, RunOutput},
+        error::Error,
+        gas,
+        session::quantity,
+        trie::AccountState,
+    },
+    run::read_trace_json,
+};
+
+/// Magic bytes closing every zktrie proof.
+const ZKTRIE_PROOF_MAGIC: &[u8] = b"THIS IS SOME MAGIC BYTES FOR SMT m1rRXgP2xpDI";
+const ZKTRIE_LEAF: u8 = 4;
+
+/// State of the interpreter before executing a step, as in `structLogs`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepLog {
+    pub pc: u64,
+    pub op: u8,
+    /// Gas left before the step.
+    pub gas: u64,
+    /// Call depth, 1 for the transaction's own frame.
+    pub depth: u64,
+    /// Bottom first.
+    pub stack: Vec<U256>,
+    /// Slot read by `SLOAD` with its value, or written by `SSTORE` with the
+    /// new value.
+    pub storage: Option<(U256, U256)>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockTraceJson {
+    coinbase: AccountWrapper,
+    header: HeaderJson,
+    transactions: Vec<TransactionJson>,
+    storage_trace: StorageTraceJson,
+    execution_results: Vec<ExecutionResultJson>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HeaderJson {
+    number: String,
+    timestamp: String,
+    gas_limit: String,
+    #[serde(default)]
+    difficulty: Option<String>,
+    #[serde(default)]
+    base_fee_per_gas: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransactionJson {
+    tx_hash: String,
+    gas: u64,
+    from: String,
+    #[serde(default)]
+    to: Option<String>,
+    value: String,
+    data: String,
+    #[serde(default)]
+    access_list: Option<Vec<AccessListItem>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessListItem {
+    address: String,
+    storage_keys: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StorageTraceJson {
+    #[serde(default)]
+    proofs: Option<BTreeMap<String, Vec<String>>>,
+    #[serde(default)]
+    storage_proofs: Option<BTreeMap<String, BTreeMap<String, Vec<String>>>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExecutionResultJson {
+    #[serde(default)]
+    from: Option<AccountWrapper>,
+    #[serde(default)]
+    to: Option<AccountWrapper>,
+    #[serde(default)]
+    byte_code: Option<String>,
+    struct_logs: Vec<StructLog>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccountWrapper {
+    address: String,
+    nonce: u64,
+    balance: String,
+    #[serde(default)]
+    storage: Option<StorageWrapper>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StorageWrapper {
+    key: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StructLog {
+    pc: u64,
+    op: String,
+    gas: u64,
+    depth: u64,
+    #[serde(default)]
+    stack: Option<Vec<String>>,
+    #[serde(default)]
+    storage: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    extra_data: Option<ExtraData>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExtraData {
+    #[serde(default)]
+    code_list: Option<Vec<String>>,
+    #[serde(default)]
+    proof_list: Option<Vec<AccountWrapper>>,
+}
+
+/// First step where the dry run and the trace disagree.
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    /// Index in `structLogs`.
+    pub step: usize,
+    pub pc: u64,
+    pub op: String,
+    /// What differs: `pc`, `op`, `gas`, `depth`, `stack`, `storage` or
+    /// `length` when one side has more steps.
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "step {} (pc {}, {}): {} differs\n  - trace:   {}\n  + dry run: {}",
+            self.step, self.pc, self.op, self.field, self.expected, self.actual
+        )
+    }
+}
+
+/// Outcome of replaying one transaction.
+#[derive(Clone, Debug)]
+pub struct TxReplay {
+    pub tx_hash: String,
+    /// Steps compared before the first divergence, or all of them.
+    pub steps_matched: usize,
+    pub divergence: Option<Divergence>,
+    /// Dry run result, `Err` holding the failure reason.
+    pub result: std::result::Result<Vec<u8>, String>,
+}
+
+/// Replay every transaction of the block trace at `path`, compact or JSON.
+/// Contract creations are not supported by the dry run host and reported as
+/// errors.
+pub fn replay_trace_file(
+    path: &str,
+    config: &RunConfig,
+) -> anyhow::Result<Vec<anyhow::Result<TxReplay>>> {
+    replay_block(&serde_json::from_value(read_trace_json(path)?)?, config)
+}
+
+fn replay_block(
+    trace: &BlockTraceJson,
+    config: &RunConfig,
+) -> anyhow::Result<Vec<anyhow::Result<TxReplay>>> {
+    let mut accounts = pre_state(trace)?;
+    let block = block_env(trace)?;
+
+    let mut replays = vec![];
+    for (tx, result) in trace.transactions.iter().zip(&trace.execution_results) {
+        let replay = replay_tx(tx, result, &block, &accounts, config);
+        if let Ok((_, Some(state))) = &replay {
+            accounts = state.clone();
+        }
+        replays.push(replay.map(|(replay, _)| replay));
+    }
+    Ok(replays)
+}
+
+/// One transaction re-executed by [`rerun_block`].
+pub(crate) struct TxRun {
+    pub output: Result<RunOutput, Error>,
+    pub steps: Vec<StepLog>,
+    /// Gas limit of the execution, intrinsic gas excluded.
+    pub gas_limit: u64,
+}
+
+/// Re-execute the transactions of `json` in order, replacing the calldata of
+/// those with an entry in `calldata` (keyed by position). Returns the
+/// accounts before the block and the run of each transaction.
+pub(crate) fn rerun_block(
+    json: serde_json::Value,
+    calldata: &BTreeMap<usize, Vec<u8>>,
+    config: &RunConfig,
+) -> anyhow::Result<(BTreeMap<B160, AccountState>, Vec<TxRun>)> {
+    let trace: BlockTraceJson = serde_json::from_value(json)?;
+    let pre = pre_state(&trace)?;
+    let block = block_env(&trace)?;
+
+    let mut accounts = pre.clone();
+    let mut runs = vec![];
+    for (index, tx) in trace.transactions.iter().enumerate() {
+        let run = run_tx(tx, calldata.get(&index).cloned(), &block, &accounts, config)?;
+        if let Ok(output) = &run.output {
+            accounts = output.state.clone();
+        }
+        runs.push(run);
+    }
+    Ok((pre, runs))
+}
+
+fn block_env(trace: &BlockTraceJson) -> anyhow::Result<BlockEnv> {
+    let header = &trace.header;
+    let optional = |value: &Option<String>| value.as_deref().map(quantity).transpose();
+    Ok(BlockEnv {
+        number: quantity(&header.number)?,
+        coinbase: B160::from_str(&trace.coinbase.address)?,
+        timestamp: quantity(&header.timestamp)?,
+        gas_limit: quantity(&header.gas_limit)?,
+        basefee: optional(&header.base_fee_per_gas)?.unwrap_or_default(),
+        difficulty: optional(&header.difficulty)?.unwrap_or_default(),
+        prevrandao: None,
+    })
+}
+
+/// Replay `tx`, returning the state it leaves when it succeeds.
+fn replay_tx(
+    tx: &TransactionJson,
+    result: &ExecutionResultJson,
+    block: &BlockEnv,
+    accounts: &BTreeMap<B160, AccountState>,
+    config: &RunConfig,
+) -> anyhow::Result<(TxReplay, Option<BTreeMap<B160, AccountState>>)> {
+    let run = run_tx(tx, None, block, accounts, config)?;
+    let (divergence, steps_matched) = compare(&result.struct_logs, &run.steps)?;
+    let (result, state) = match run.output {
+        Ok(output) => (Ok(output.return_data), Some(output.state)),
+        Err(Error::Reverted { output, .. }) => (
+            Err(format!("reverted with 0x{}", hex::encode(output))),
+            None,
+        ),
+        Err(e) => (Err(e.to_string()), None),
+    };
+    Ok((
+        TxReplay {
+            tx_hash: tx.tx_hash.clone(),
+            steps_matched,
+            divergence,
+            result,
+        },
+        state,
+    ))
+}
+
+/// Execute `tx` from `accounts`, with `calldata` instead of its own if given.
+fn run_tx(
+    tx: &TransactionJson,
+    calldata: Option<Vec<u8>>,
+    block: &BlockEnv,
+    accounts: &BTreeMap<B160, AccountState>,
+    config: &RunConfig,
+) -> anyhow::Result<TxRun> {
+    let to = tx
+        .to
+        .as_deref()
+        .ok_or_else(|| anyhow::anyhow!("contract creation {} is not supported", tx.tx_hash))?;
+    let to = B160::from_str(to)?;
+    let calldata = match calldata {
+        Some(calldata) => calldata,
+        None => hex::decode(tx.data.trim_start_matches("0x"))?,
+    };
+    let access_list: Vec<(B160, Vec<U256>)> = tx
+        .access_list
+        .iter()
+        .flatten()
+        .map(|item| {
+            Ok((
+                B160::from_str(&item.address)?,
+                item.storage_keys
+                    .iter()
+                    .map(|key| quantity(key))
+                    .collect::<anyhow::Result<_>>()?,
+            ))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    let intrinsic_gas = gas::intrinsic_gas(&calldata, &access_list, config.hardfork.spec_id());
+    let config = RunConfig {
+        gas_limit: tx.gas.saturating_sub(intrinsic_gas),
+        access_list,
+        caller: B160::from_str(&tx.from)?,
+        address: to,
+        value: quantity(&tx.value)?,
+        block: block.clone(),
+        accounts: accounts.clone(),
+        ..config.clone()
+    };
+    let bytecode = accounts
+        .get(&to)
+        .map(|account| account.code.clone())
+        .unwrap_or_default();
+
+    let (output, steps) = bytecode_run_with_steps(calldata, bytecode, None, &config);
+    Ok(TxRun {
+        output,
+        steps,
+        gas_limit: config.gas_limit,
+    })
+}
+
+/// Compare `steps` with `logs`, returning the first divergence and how many
+/// steps matched before it.
+fn compare(logs: &[StructLog], steps: &[StepLog]) -> anyhow::Result<(Option<Divergence>, usize)> {
+    for (index, (log, step)) in logs.iter().zip(steps).enumerate() {
+        let stack = log
+            .stack
+            .iter()
+            .flatten()
+            .map(|value| quantity(value))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let op = op_name(step.op);
+        let divergence = |field, expected: String, actual: String| Divergence {
+            step: index,
+            pc: log.pc,
+            op: log.op.clone(),
+            field,
+            expected,
+            actual,
+        };
+        let found = if log.pc != step.pc {
+            Some(divergence("pc", log.pc.to_string(), step.pc.to_string()))
+        } else if !same_op(&log.op, &op) {
+            Some(divergence("op", log.op.clone(), op))
+        } else if log.gas != step.gas {
+            Some(divergence("gas", log.gas.to_string(), step.gas.to_string()))
+        } else if log.depth != step.depth {
+            Some(divergence(
+                "depth",
+                log.depth.to_string(),
+                step.depth.to_string(),
+            ))
+        } else if stack != step.stack {
+            Some(divergence(
+                "stack",
+                format!("{stack:?}"),
+                format!("{:?}", step.stack),
+            ))
+        } else {
+            storage_divergence(log, step)?
+                .map(|(expected, actual)| divergence("storage", expected, actual))
+        };
+        if found.is_some() {
+            return Ok((found, index));
+        }
+    }
+
+    let matched = logs.len().min(steps.len());
+    let divergence = (logs.len() != steps.len()).then(|| {
+        let (pc, op) = logs
+            .get(matched)
+            .map(|log| (log.pc, log.op.clone()))
+            .unwrap_or_else(|| (steps[matched].pc, op_name(steps[matched].op)));
+        Divergence {
+            step: matched,
+            pc,
+            op,
+            field: "length",
+            expected: format!("{} steps", logs.len()),
+            actual: format!("{} steps", steps.len()),
+        }
+    });
+    Ok((divergence, matched))
+}
+
+/// Mnemonic of `op`, its hex value when undefined.
+pub(crate) fn op_name(op: u8) -> String {
+    OPCODE_JUMPMAP[op as usize].map_or_else(|| format!("0x{op:02x}"), str::to_string)
+}
+
+/// The trace names `KECCAK256` what revm calls `SHA3`.
+fn same_op(trace: &str, dry_run: &str) -> bool {
+    trace == dry_run
+        || matches!(
+            (trace, dry_run),
+            ("KECCAK256", "SHA3") | ("SHA3", "KECCAK256")
+        )
+}
+
+/// The slot the step touches, when the trace records storage for it and
+/// holds another value.
+fn storage_divergence(log: &StructLog, step: &StepLog) -> anyhow::Result<Option<(String, String)>> {
+    let (Some(storage), Some((key, value))) = (&log.storage, step.storage) else {
+        return Ok(None);
+    };
+    for (trace_key, trace_value) in storage {
+        if quantity(trace_key)? == key {
+            let trace_value = quantity(trace_value)?;
+            return Ok((trace_value != value).then(|| {
+                (
+                    format!("{key:#x}: {trace_value:#x}"),
+                    format!("{key:#x}: {value:#x}"),
+                )
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Accounts and slots before the block.
+fn pre_state(trace: &BlockTraceJson) -> anyhow::Result<BTreeMap<B160, AccountState>> {
+    anyhow::ensure!(
+        trace.transactions.len() == trace.execution_results.len(),
+        "{} transactions but {} execution results",
+        trace.transactions.len(),
+        trace.execution_results.len()
+    );
+    let mut accounts: BTreeMap<B160, AccountState> = BTreeMap::new();
+    let mut code_hashes = HashMap::new();
+    for (address, proof) in trace.storage_trace.proofs.iter().flatten() {
+        let address = B160::from_str(address)?;
+        if let Some(leaf) = zktrie_leaf(proof, address.as_bytes())? {
+            let word = &leaf[0];
+            let account = accounts.entry(address).or_default();
+            account.nonce = u64::from_be_bytes(word[24..32].try_into()?);
+            account.balance = U256::from_be_bytes(leaf[1]);
+            code_hashes.insert(address, B256(leaf[3]));
+        }
+    }
+    for (address, slots) in trace.storage_trace.storage_proofs.iter().flatten() {
+        let address = B160::from_str(address)?;
+        for (key, proof) in slots {
+            let key = quantity(key)?;
+            if let Some(leaf) = zktrie_leaf(proof, &key.to_be_bytes::<32>())? {
+                let value = U256::from_be_bytes(leaf[0]);
+                accounts
+                    .entry(address)
+                    .or_default()
+                    .storage
+                    .insert(key, value);
+            }
+        }
+    }
+
+    // Accounts and slots seen during execution, first sighting being the
+    // value before the block.
+    let mut seen_accounts = HashMap::new();
+    let mut seen_slots = HashMap::new();
+    let mut codes = HashMap::new();
+    let mut see = |wrapper: &AccountWrapper| -> anyhow::Result<()> {
+        let address = B160::from_str(&wrapper.address)?;
+        seen_accounts
+            .entry(address)
+            .or_insert((wrapper.nonce, quantity(&wrapper.balance)?));
+        if let Some(storage) = &wrapper.storage {
+            seen_slots
+                .entry((address, quantity(&storage.key)?))
+                .or_insert(quantity(&storage.value)?);
+        }
+        Ok(())
+    };
+    for result in &trace.execution_results {
+        for wrapper in result.from.iter().chain(&result.to) {
+            see(wrapper)?;
+        }
+        for log in &result.struct_logs {
+            for wrapper in log
+                .extra_data
+                .iter()
+                .flat_map(|extra| extra.proof_list.iter().flatten())
+            {
+                see(wrapper)?;
+            }
+        }
+        let code_lists = result
+            .struct_logs
+            .iter()
+            .flat_map(|log| log.extra_data.iter())
+            .flat_map(|extra| extra.code_list.iter().flatten());
+        for code in result.byte_code.iter().chain(code_lists) {
+            let code = hex::decode(code.trim_start_matches("0x"))?;
+            codes.insert(keccak256(&code), code);
+        }
+    }
+    for (address, (nonce, balance)) in seen_accounts {
+        let account = accounts.entry(address).or_default();
+        account.nonce = nonce;
+        account.balance = balance;
+    }
+    for ((address, key), value) in seen_slots {
+        accounts
+            .entry(address)
+            .or_default()
+            .storage
+            .insert(key, value);
+    }
+    for (address, code_hash) in code_hashes {
+        if let (Some(code), Some(account)) = (codes.get(&code_hash), accounts.get_mut(&address)) {
+            account.code = code.clone();
+        }
+    }
+    // The entry code of each transaction is known even without a proof.
+    for (tx, result) in trace.transactions.iter().zip(&trace.execution_results) {
+        if let (Some(to), Some(code)) = (&tx.to, &result.byte_code) {
+            accounts.entry(B160::from_str(to)?).or_default().code =
+                hex::decode(code.trim_start_matches("0x"))?;
+        }
+    }
+    accounts.retain(|_, account| !account.is_empty() || !account.storage.is_empty());
+    Ok(accounts)
+}
+
+/// Values of the leaf ending a zktrie `proof`, `None` when the proof shows
+/// `key` is absent.
+pub(crate) fn zktrie_leaf(proof: &[String], key: &[u8]) -> anyhow::Result<Option<Vec<[u8; 32]>>> {
+    let nodes = proof
+        .iter()
+        .map(|node| hex::decode(node.trim_start_matches("0x")))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(node) = nodes
+        .iter()
+        .rev()
+        .find(|node| node.as_slice() != ZKTRIE_PROOF_MAGIC)
+    else {
+        return Ok(None);
+    };
+    // type, node key, value count and flags, values, key preimage
+    if node.first() != Some(&ZKTRIE_LEAF) || node.len() < 37 {
+        return Ok(None);
+    }
+    let count = node[33] as usize;
+    let values_end = 37 + 32 * count;
+    anyhow::ensure!(node.len() > values_end, "truncated zktrie leaf");
+    let values = node[37..values_end]
+        .chunks(32)
+        .map(|chunk| chunk.try_into().unwrap())
+        .collect();
+    // A leaf of another key proves `key` absent. Leaves without a preimage
+    // are taken as the key's own.
+    let preimage_len = node[values_end] as usize;
+    let preimage = &node[values_end + 1..];
+    if preimage_len > 0 && preimage.len() >= key.len() && &preimage[..key.len()] != key {
+        return Ok(None);
+    }
+    Ok(Some(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zktrie_account_leaf() {
+        let leaf = "0422302196af309a06944efc5748f8f23cc759a988a4241c0320c4046983f2db1505080000\
+            00000000000000000000000000000000000000000000060e0000000000000001\
+            0000000000000000000000000000000000000000000000000000000000000000\
+            0000000000000000000000000000000000000000000000000000000000000000\
+            6aa653993ef039cd4c638ea5864e981c9d633a3f9b8973d4ac8a2b48d3bced89\
+            1b85bb42554b0aaba2c93fa51023433631e2dc87f7bfe0d8717ae7430567ca00\
+            209e59f265bc4bb761f5f4fb301ac56043f5a3d7b00000000000000000000000\
+            00";
+        let proof = vec![
+            leaf.to_string(),
+            format!("0x{}", hex::encode(ZKTRIE_PROOF_MAGIC)),
+        ];
+        let address = B160::from_str("0x9e59f265bc4bb761f5f4fb301ac56043f5a3d7b0").unwrap();
+
+        let values = zktrie_leaf(&proof, address.as_bytes()).unwrap().unwrap();
+        assert_eq!(values.len(), 5);
+        assert_eq!(values[0][31], 1);
+        assert_eq!(values[3][0], 0x6a);
+        // The same leaf proves any other address absent.
+        assert!(zktrie_leaf(&proof, B160::zero().as_bytes())
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn replay_calculation_trace() {
+        let replays = replay_trace_file("traces/calculation.json", &RunConfig::default()).unwrap();
+        for replay in replays {
+            let replay = replay.unwrap();
+            assert!(
+                replay.divergence.is_none(),
+                "{}",
+                replay.divergence.unwrap()
+            );
+            assert!(replay.result.is_ok());
+        }
+    }
+    #[test]
+    fn mismatched_results_are_rejected() {
+        let mut json = read_trace_json("traces/calculation.json").unwrap();
+        json["executionResults"].as_array_mut().unwrap().pop();
+        let trace = serde_json::from_value(json).unwrap();
+        assert!(replay_block(&trace, &RunConfig::default()).is_err());
+    }
+}
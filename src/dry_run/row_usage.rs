@@ -0,0 +1,357 @@
+//! Circuit row usage estimation from a dry run profile, and capacity reports
+//! shared with the trace based (exact) row usage of `run`.
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write,
+};
+
+use revm_interpreter::{opcode, Interpreter};
+use revm_primitives::{B160, U256};
+
+// The row costs below, and the step heights of `evm_step_height`, are rough
+// guesses of the order of magnitude, not taken from the circuit layouts of the
+// linked prover. Use the trace based row usage for real numbers.
+
+/// Rows of one permutation of the keccak circuit.
+const KECCAK_ROWS_PER_PERMUTATION: usize = 300;
+/// Bytes absorbed per keccak permutation, the keccak-256 rate.
+const KECCAK_RATE: usize = 136;
+/// MPT rows for one account or storage proof.
+const MPT_ROWS_PER_PROOF: usize = 64;
+/// Per-call row costs of the precompile circuits.
+const ECC_ADD_ROWS: usize = 2_800;
+const ECC_MUL_ROWS: usize = 120_000;
+const ECC_PAIRING_ROWS: usize = 1_600_000;
+const MODEXP_ROWS: usize = 26_000;
+
+/// Rows consumed by one sub-circuit.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubCircuitRows {
+    pub name: String,
+    pub rows: usize,
+}
+
+/// Rows reserved at the end of a chunk for blinding.
+const BLINDING_ROWS: usize = 256;
+/// Smallest chunk degree with usable rows.
+pub const MIN_DEGREE: u32 = 9;
+/// Largest chunk degree whose row count fits a `u32`.
+pub const MAX_DEGREE: u32 = 31;
+
+/// Rows usable by every sub-circuit of a chunk at `degree`, leaving room for
+/// the blinding rows. `None` when the chunk has no row left or its row count
+/// overflows.
+pub fn chunk_capacity(degree: u32) -> Option<usize> {
+    1usize
+        .checked_shl(degree)
+        .and_then(|rows| rows.checked_sub(BLINDING_ROWS))
+        .filter(|rows| *rows > 0)
+}
+
+/// Print per sub-circuit utilization against the chunk capacity of `degree`.
+pub fn report(usage: &[SubCircuitRows], degree: u32) -> String {
+    let mut out = String::new();
+    let Some(capacity) = chunk_capacity(degree) else {
+        writeln!(out, "No chunk capacity at degree {degree}, expected {MIN_DEGREE} to {MAX_DEGREE}").unwrap();
+        return out;
+    };
+    writeln!(
+        out,
+        "{:<10} {:>12} {:>12} {:>8}",
+        "circuit", "rows", "capacity", "usage"
+    )
+    .unwrap();
+    for SubCircuitRows { name, rows } in usage {
+        writeln!(
+            out,
+            "{:<10} {:>12} {:>12} {:>7.2}%{}",
+            name,
+            rows,
+            capacity,
+            *rows as f64 * 100.0 / capacity as f64,
+            if *rows > capacity { "  OVERFLOW" } else { "" }
+        )
+        .unwrap();
+    }
+    let fits = usage.iter().all(|u| u.rows <= capacity);
+    writeln!(
+        out,
+        "{} a chunk of degree {degree}",
+        if fits { "Fits in" } else { "Does NOT fit in" }
+    )
+    .unwrap();
+    out
+}
+
+/// Execution statistics recorded by `DummyHost` while stepping.
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionProfile {
+    /// Executed steps per opcode.
+    pub opcodes: BTreeMap<u8, u64>,
+    /// Stack reads and writes.
+    pub stack_ops: u64,
+    /// Memory word reads and writes of `MLOAD`/`MSTORE`/`MSTORE8`.
+    pub memory_ops: u64,
+    /// Bytes moved by copy-like opcodes (`*COPY`, `SHA3`, `RETURN`, `LOG`...).
+    pub copy_bytes: u64,
+    /// Keccak permutations of `SHA3` inputs.
+    pub keccak_permutations: u64,
+    /// `SLOAD` and `SSTORE` accesses.
+    pub storage_ops: u64,
+    /// Distinct `(address, slot)` pairs touched.
+    pub storage_slots: HashSet<(B160, U256)>,
+    /// Calls into each precompile.
+    pub precompile_calls: BTreeMap<B160, u64>,
+    /// Length of each executed code, by address.
+    pub codes: HashMap<B160, usize>,
+    /// Call frames entered, the entry frame included.
+    pub calls: u64,
+}
+
+impl ExecutionProfile {
+    pub(crate) fn step(&mut self, interp: &Interpreter) {
+        let op = interp.current_opcode();
+        *self.opcodes.entry(op).or_default() += 1;
+        self.codes
+            .entry(interp.contract.address)
+            .or_insert_with(|| interp.contract.bytecode.len());
+
+        let (inputs, outputs) = stack_io(op);
+        self.stack_ops += (inputs + outputs) as u64;
+
+        let arg = |n: usize| {
+            interp
+                .stack
+                .peek(n)
+                .ok()
+                .and_then(|v| usize::try_from(v).ok())
+                .unwrap_or_default()
+        };
+        match op {
+            opcode::MLOAD | opcode::MSTORE | opcode::MSTORE8 => self.memory_ops += 1,
+            opcode::SLOAD | opcode::SSTORE => self.storage_ops += 1,
+            opcode::SHA3 => {
+                let len = arg(1);
+                self.copy_bytes += len as u64;
+                self.keccak_permutations += (len / KECCAK_RATE + 1) as u64;
+            }
+            opcode::CALLDATACOPY | opcode::CODECOPY | opcode::RETURNDATACOPY => {
+                self.copy_bytes += arg(2) as u64;
+            }
+            opcode::EXTCODECOPY => self.copy_bytes += arg(3) as u64,
+            opcode::RETURN | opcode::REVERT => self.copy_bytes += arg(1) as u64,
+            opcode::LOG0..=opcode::LOG4 => self.copy_bytes += arg(1) as u64,
+            _ => {}
+        }
+    }
+
+    pub(crate) fn storage_access(&mut self, address: B160, index: U256) {
+        self.storage_slots.insert((address, index));
+    }
+
+    pub(crate) fn call(&mut self) {
+        self.calls += 1;
+    }
+
+    pub(crate) fn precompile_call(&mut self, address: B160) {
+        *self.precompile_calls.entry(address).or_default() += 1;
+    }
+
+    fn precompile_rows(&self, precompiles: &[(u8, usize)]) -> usize {
+        self.precompile_calls
+            .iter()
+            .filter_map(|(address, calls)| {
+                precompiles
+                    .iter()
+                    .find(|(id, _)| *address == precompile_address(*id))
+                    .map(|(_, rows)| *calls as usize * rows)
+            })
+            .sum()
+    }
+
+    /// Roughly estimate the rows of the EVM, state, bytecode, keccak, ECC,
+    /// modexp, copy and MPT sub-circuits this execution would consume.
+    pub fn estimate(&self) -> Vec<SubCircuitRows> {
+        let evm = self
+            .opcodes
+            .iter()
+            .map(|(op, count)| evm_step_height(*op) * *count as usize)
+            .sum();
+        let state = self.stack_ops as usize
+            + self.memory_ops as usize
+            + 2 * self.storage_ops as usize
+            + self.copy_bytes as usize / 32
+            + 20 * self.calls as usize;
+        let bytecode = self.codes.values().map(|len| len + 1).sum();
+        let keccak = KECCAK_ROWS_PER_PERMUTATION
+            * (self.keccak_permutations as usize
+                + self
+                    .codes
+                    .values()
+                    .map(|len| len / KECCAK_RATE + 1)
+                    .sum::<usize>());
+        let ecc = self.precompile_rows(&[
+            (0x06, ECC_ADD_ROWS),
+            (0x07, ECC_MUL_ROWS),
+            (0x08, ECC_PAIRING_ROWS),
+        ]);
+        let modexp = self.precompile_rows(&[(0x05, MODEXP_ROWS)]);
+        let copy = 2 * self.copy_bytes as usize;
+        let mpt = MPT_ROWS_PER_PROOF * (self.codes.len() + self.storage_slots.len());
+
+        [
+            ("evm", evm),
+            ("state", state),
+            ("bytecode", bytecode),
+            ("keccak", keccak),
+            ("ecc", ecc),
+            ("modexp", modexp),
+            ("copy", copy),
+            ("mpt", mpt),
+        ]
+        .into_iter()
+        .map(|(name, rows)| SubCircuitRows {
+            name: name.to_string(),
+            rows,
+        })
+        .collect()
+    }
+}
+
+fn precompile_address(id: u8) -> B160 {
+    let mut address = B160::zero();
+    address.0[19] = id;
+    address
+}
+
+/// Approximate EVM circuit rows of one step of `op`.
+fn evm_step_height(op: u8) -> usize {
+    match op {
+        opcode::CALL
+        | opcode::CALLCODE
+        | opcode::DELEGATECALL
+        | opcode::STATICCALL
+        | opcode::CREATE
+        | opcode::CREATE2 => 20,
+        opcode::SHA3
+        | opcode::CALLDATACOPY
+        | opcode::CODECOPY
+        | opcode::EXTCODECOPY
+        | opcode::RETURNDATACOPY
+        | opcode::RETURN
+        | opcode::REVERT
+        | opcode::LOG0..=opcode::LOG4 => 6,
+        opcode::EXP | opcode::SLOAD | opcode::SSTORE => 4,
+        _ => 2,
+    }
+}
+
+/// Number of stack items `op` pops and pushes.
+pub(crate) fn stack_io(op: u8) -> (usize, usize) {
+    match op {
+        0x00 | opcode::JUMPDEST => (0, 0),
+        opcode::ADDMOD | opcode::MULMOD => (3, 1),
+        opcode::ISZERO | opcode::NOT => (1, 1),
+        0x01..=0x0b | 0x10..=0x1d | opcode::SHA3 => (2, 1),
+        opcode::BALANCE
+        | opcode::CALLDATALOAD
+        | opcode::EXTCODESIZE
+        | opcode::EXTCODEHASH
+        | opcode::BLOCKHASH
+        | opcode::MLOAD
+        | opcode::SLOAD => (1, 1),
+        opcode::CALLDATACOPY | opcode::CODECOPY | opcode::RETURNDATACOPY => (3, 0),
+        opcode::EXTCODECOPY => (4, 0),
+        0x30..=0x48 | 0x58..=0x5a | 0x5f..=0x7f => (0, 1),
+        opcode::POP | opcode::JUMP | opcode::SELFDESTRUCT => (1, 0),
+        opcode::MSTORE
+        | opcode::MSTORE8
+        | opcode::SSTORE
+        | opcode::JUMPI
+        | opcode::RETURN
+        | opcode::REVERT => (2, 0),
+        0x80..=0x8f => {
+            let n = (op - 0x80 + 1) as usize;
+            (n, n + 1)
+        }
+        0x90..=0x9f => {
+            let n = (op - 0x90 + 2) as usize;
+            (n, n)
+        }
+        0xa0..=0xa4 => ((op - 0xa0 + 2) as usize, 0),
+        opcode::CREATE => (3, 1),
+        opcode::CREATE2 => (4, 1),
+        opcode::CALL | opcode::CALLCODE => (7, 1),
+        opcode::DELEGATECALL | opcode::STATICCALL => (6, 1),
+        _ => (0, 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dry_run::bytecode_run::{bytecode_run_with_profile, RunConfig};
+
+    #[test]
+    fn estimate_of_known_bytecode() {
+        // PUSH1 1 PUSH1 2 ADD PUSH1 0 SSTORE STOP
+        let bytecode = hex::decode("600160020160005500").unwrap();
+
+        let (result, profile) =
+            bytecode_run_with_profile(vec![], bytecode, None, &RunConfig::default());
+        result.unwrap();
+        assert_eq!(profile.calls, 1);
+        assert_eq!(profile.stack_ops, 8);
+        assert_eq!(profile.storage_ops, 1);
+
+        let rows: Vec<_> = profile
+            .estimate()
+            .into_iter()
+            .map(|usage| (usage.name, usage.rows))
+            .collect();
+        let expected = [
+            ("evm", 14),
+            ("state", 30),
+            ("bytecode", 10),
+            ("keccak", 300),
+            ("ecc", 0),
+            ("modexp", 0),
+            ("copy", 0),
+            ("mpt", 128),
+        ]
+        .map(|(name, rows)| (name.to_string(), rows));
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn report_flags_overflow() {
+        let usage = [("evm", 1_000), ("state", 2_000_000)].map(|(name, rows)| SubCircuitRows {
+            name: name.to_string(),
+            rows,
+        });
+        let report = report(&usage, 20);
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(!lines[1].ends_with("OVERFLOW"));
+        assert!(lines[2].ends_with("OVERFLOW"));
+        assert_eq!(lines[3], "Does NOT fit in a chunk of degree 20");
+    }
+
+    #[test]
+    fn capacity_of_out_of_range_degrees() {
+        assert_eq!(chunk_capacity(8), None);
+        assert_eq!(chunk_capacity(9), Some(256));
+        assert_eq!(chunk_capacity(64), None);
+        assert!((MIN_DEGREE..=MAX_DEGREE).all(|degree| chunk_capacity(degree).is_some()));
+    }
+
+    #[test]
+    fn stack_items() {
+        assert_eq!(stack_io(opcode::PUSH0), (0, 1));
+        assert_eq!(stack_io(opcode::DUP1), (1, 2));
+        assert_eq!(stack_io(opcode::SWAP2), (3, 3));
+        assert_eq!(stack_io(opcode::LOG2), (4, 0));
+        assert_eq!(stack_io(opcode::CALL), (7, 1));
+        assert_eq!(stack_io(opcode::JUMPDEST), (0, 0));
+    }
+}
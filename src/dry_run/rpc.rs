@@ -0,0 +1,427 @@
+//! Ethereum JSON-RPC methods answered by the dry run host against a local
+//! state, for tooling that speaks JSON-RPC rather than this CLI.
+use std::str::FromStr;
+
+use revm_primitives::{B160, U256};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::dry_run::{
+    bytecode_run::{bytecode_run_with_config, bytecode_run_with_steps, RunConfig, RunOutput},
+    error::Error,
+    estimate::estimate_gas,
+    gas,
+    replay::{op_name, StepLog},
+    session::quantity,
+};
+
+/// Chain id of Scroll mainnet.
+pub const DEFAULT_CHAIN_ID: u64 = 534_352;
+/// Transaction gas a call may use at most, geth's default RPC gas cap.
+pub const DEFAULT_GAS_CAP: u64 = 50_000_000;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// Code geth answers reverted calls with, the revert data being attached.
+const EXECUTION_REVERTED: i64 = 3;
+const EXECUTION_ERROR: i64 = -32000;
+
+/// Transaction object of `eth_call` and friends.
+#[derive(Debug, Default, Deserialize)]
+struct CallRequest {
+    #[serde(default)]
+    from: Option<String>,
+    #[serde(default)]
+    to: Option<String>,
+    #[serde(default)]
+    gas: Option<String>,
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    data: Option<String>,
+    /// Newer name of `data`, preferred when both are given.
+    #[serde(default)]
+    input: Option<String>,
+}
+
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<String>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    fn invalid_params(message: impl ToString) -> Self {
+        Self::new(INVALID_PARAMS, message)
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Reverted { output, .. } => Self {
+                code: EXECUTION_REVERTED,
+                message: "execution reverted".to_string(),
+                data: Some(format!("0x{}", hex::encode(output))),
+            },
+            e => Self::new(EXECUTION_ERROR, e),
+        }
+    }
+}
+
+/// Answers JSON-RPC requests. Calls never change the state.
+#[derive(Clone, Debug)]
+pub struct RpcHandler {
+    /// Settings of every call, `config.accounts` being the state served.
+    pub config: RunConfig,
+    pub chain_id: u64,
+    /// Transaction gas a call may use at most, intrinsic gas included. Calls
+    /// without `gas` get all of it, `eth_estimateGas` searches up to it.
+    pub gas_cap: u64,
+}
+
+impl RpcHandler {
+    pub fn new(config: RunConfig, chain_id: u64, gas_cap: u64) -> Self {
+        Self {
+            config,
+            chain_id,
+            gas_cap,
+        }
+    }
+
+    /// Answer a request body, a single request or a batch.
+    pub fn handle_body(&self, body: &[u8]) -> Value {
+        match serde_json::from_slice::<Value>(body) {
+            Ok(Value::Array(requests)) => {
+                Value::Array(requests.iter().map(|r| self.handle(r)).collect())
+            }
+            Ok(request) => self.handle(&request),
+            Err(e) => response(Value::Null, Err(RpcError::new(PARSE_ERROR, e))),
+        }
+    }
+
+    /// Answer a single request.
+    pub fn handle(&self, request: &Value) -> Value {
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let Some(method) = request.get("method").and_then(Value::as_str) else {
+            return response(id, Err(RpcError::new(INVALID_REQUEST, "missing method")));
+        };
+        let params = match request.get("params") {
+            Some(Value::Array(params)) => params.clone(),
+            None | Some(Value::Null) => vec![],
+            Some(_) => {
+                return response(id, Err(RpcError::invalid_params("params must be an array")))
+            }
+        };
+        response(id, self.dispatch(method, &params))
+    }
+
+    fn dispatch(&self, method: &str, params: &[Value]) -> Result<Value, RpcError> {
+        match method {
+            "eth_chainId" => Ok(json!(format!("0x{:x}", self.chain_id))),
+            "eth_call" => {
+                let (config, calldata, bytecode) = self.call_config(params)?;
+                let output = bytecode_run_with_config(calldata, bytecode, None, &config)?;
+                Ok(json!(format!("0x{}", hex::encode(output.return_data))))
+            }
+            "eth_estimateGas" => {
+                let (config, calldata, bytecode) = self.call_config(params)?;
+                let gas = estimate_gas(calldata, bytecode, None, &config)?;
+                Ok(json!(format!("0x{gas:x}")))
+            }
+            "eth_getCode" => {
+                let address = address_param(params, 0)?;
+                let code = self
+                    .config
+                    .accounts
+                    .get(&address)
+                    .map(|account| account.code.as_slice())
+                    .unwrap_or_default();
+                Ok(json!(format!("0x{}", hex::encode(code))))
+            }
+            "eth_getStorageAt" => {
+                let address = address_param(params, 0)?;
+                let slot = params
+                    .get(1)
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| RpcError::invalid_params("missing storage slot"))?;
+                let slot = quantity(slot).map_err(RpcError::invalid_params)?;
+                let value = self
+                    .config
+                    .accounts
+                    .get(&address)
+                    .and_then(|account| account.storage.get(&slot))
+                    .copied()
+                    .unwrap_or_default();
+                Ok(json!(format!(
+                    "0x{}",
+                    hex::encode(value.to_be_bytes::<32>())
+                )))
+            }
+            "debug_traceCall" => {
+                let (config, calldata, bytecode) = self.call_config(params)?;
+                Ok(trace_call(calldata, bytecode, &config))
+            }
+            _ => Err(RpcError::new(
+                METHOD_NOT_FOUND,
+                format!("the method {method} does not exist/is not available"),
+            )),
+        }
+    }
+
+    /// Config, calldata and code of the call described by `params[0]`. The
+    /// block parameter is ignored, the local state being the only one.
+    fn call_config(&self, params: &[Value]) -> Result<(RunConfig, Vec<u8>, Vec<u8>), RpcError> {
+        let request: CallRequest = params
+            .first()
+            .map(|param| serde_json::from_value(param.clone()))
+            .transpose()
+            .map_err(RpcError::invalid_params)?
+            .unwrap_or_default();
+        let address = |value: Option<&String>| {
+            value
+                .map(|value| B160::from_str(value).map_err(RpcError::invalid_params))
+                .transpose()
+                .map(Option::unwrap_or_default)
+        };
+        let number = |value: Option<&String>| {
+            value
+                .map(|value| quantity(value).map_err(RpcError::invalid_params))
+                .transpose()
+        };
+        let to = address(request.to.as_ref())?;
+        let data = request.input.as_ref().or(request.data.as_ref());
+        let calldata = hex::decode(data.map_or("", |data| data.trim_start_matches("0x")))
+            .map_err(RpcError::invalid_params)?;
+        // `gas` is the transaction's, intrinsic gas included.
+        let intrinsic_gas = gas::intrinsic_gas(
+            &calldata,
+            &self.config.access_list,
+            self.config.hardfork.spec_id(),
+        );
+        let config = RunConfig {
+            caller: address(request.from.as_ref())?,
+            address: to,
+            value: number(request.value.as_ref())?.unwrap_or_default(),
+            gas_limit: number(request.gas.as_ref())?
+                .map_or(self.gas_cap, |gas| {
+                    u64::try_from(gas).unwrap_or(u64::MAX).min(self.gas_cap)
+                })
+                .saturating_sub(intrinsic_gas),
+            ..self.config.clone()
+        };
+        let bytecode = config
+            .accounts
+            .get(&to)
+            .map(|account| account.code.clone())
+            .unwrap_or_default();
+        Ok((config, calldata, bytecode))
+    }
+}
+
+/// `debug_traceCall` result in the geth struct logger format.
+fn trace_call(calldata: Vec<u8>, bytecode: Vec<u8>, config: &RunConfig) -> Value {
+    let (result, steps) = bytecode_run_with_steps(calldata, bytecode, None, config);
+    struct_logger_result(&result, &steps, config.gas_limit)
+}
+
+/// Struct logger result of a run of `gas_limit` that took `steps`. Reverted
+/// runs report the gas they used, other failures all of `gas_limit`.
+pub(crate) fn struct_logger_result(
+    result: &Result<RunOutput, Error>,
+    steps: &[StepLog],
+    gas_limit: u64,
+) -> Value {
+    let struct_logs: Vec<Value> = steps
+        .iter()
+        .enumerate()
+        .map(|(index, step)| {
+            // Cost is known from the next step of the same frame only.
+            let gas_cost = steps
+                .get(index + 1)
+                .filter(|next| next.depth == step.depth)
+                .map_or(0, |next| step.gas.saturating_sub(next.gas));
+            json!({
+                "pc": step.pc,
+                "op": op_name(step.op),
+                "gas": step.gas,
+                "gasCost": gas_cost,
+                "depth": step.depth,
+                "stack": step.stack.iter().map(|value| format!("{value:#x}")).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    let (failed, gas, return_value) = match result {
+        Ok(output) => (false, output.gas_used, hex::encode(&output.return_data)),
+        Err(Error::Reverted { output, gas_used }) => (true, *gas_used, hex::encode(output)),
+        Err(_) => (true, gas_limit, String::new()),
+    };
+    json!({
+        "gas": gas,
+        "failed": failed,
+        "returnValue": return_value,
+        "structLogs": struct_logs,
+    })
+}
+
+/// Response to a request whose handling failed unexpectedly.
+pub fn internal_error(message: impl ToString) -> Value {
+    response(Value::Null, Err(RpcError::new(INTERNAL_ERROR, message)))
+}
+
+fn address_param(params: &[Value], index: usize) -> Result<B160, RpcError> {
+    let address = params
+        .get(index)
+        .and_then(Value::as_str)
+        .ok_or_else(|| RpcError::invalid_params("missing address"))?;
+    B160::from_str(address).map_err(RpcError::invalid_params)
+}
+
+fn response(id: Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => {
+            let mut error = json!({ "code": e.code, "message": e.message });
+            if let Some(data) = e.data {
+                error["data"] = json!(data);
+            }
+            json!({ "jsonrpc": "2.0", "id": id, "error": error })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_and_storage() {
+        let token = B160::from_low_u64_be(0x100);
+        let mut config = RunConfig::default();
+        // PUSH1 0 SLOAD PUSH1 0 MSTORE PUSH1 0x20 PUSH1 0 RETURN
+        let account = config.accounts.entry(token).or_default();
+        account.code = hex::decode("60005460005260206000f3").unwrap();
+        account.storage.insert(U256::ZERO, U256::from(42));
+        let handler = RpcHandler::new(config, DEFAULT_CHAIN_ID, DEFAULT_GAS_CAP);
+
+        let call = handler.handle_body(
+            br#"{"jsonrpc":"2.0","id":1,"method":"eth_call","params":[{"to":"0x0000000000000000000000000000000000000100"},"latest"]}"#,
+        );
+        assert_eq!(call["result"], json!(format!("0x{:064x}", 42)));
+        let storage = handler.handle(&json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "eth_getStorageAt",
+            "params": ["0x0000000000000000000000000000000000000100", "0x0", "latest"],
+        }));
+        assert_eq!(storage["result"], call["result"]);
+        let unknown = handler.handle(&json!({ "jsonrpc": "2.0", "id": 3, "method": "eth_mining" }));
+        assert_eq!(unknown["error"]["code"], json!(METHOD_NOT_FOUND));
+    }
+
+    fn storage_contract() -> RpcHandler {
+        let mut config = RunConfig::default();
+        // PUSH1 0 SLOAD PUSH1 0 MSTORE PUSH1 0x20 PUSH1 0 RETURN
+        let account = config
+            .accounts
+            .entry(B160::from_low_u64_be(0x100))
+            .or_default();
+        account.code = hex::decode("60005460005260206000f3").unwrap();
+        account.storage.insert(U256::ZERO, U256::from(42));
+        // JUMPDEST PUSH1 0 JUMP: loops until out of gas
+        config
+            .accounts
+            .entry(B160::from_low_u64_be(0x200))
+            .or_default()
+            .code = hex::decode("5b600056").unwrap();
+        RpcHandler::new(config, DEFAULT_CHAIN_ID, 1_000_000)
+    }
+
+    fn call(method: &str, to: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": [{ "to": to }, "latest"],
+        })
+    }
+
+    const STORAGE: &str = "0x0000000000000000000000000000000000000100";
+    const LOOP: &str = "0x0000000000000000000000000000000000000200";
+
+    #[test]
+    fn estimate_and_trace() {
+        let handler = storage_contract();
+
+        // Intrinsic gas, then a cold SLOAD, a one word MSTORE and 4 pushes.
+        let estimate = handler.handle(&call("eth_estimateGas", STORAGE));
+        assert_eq!(estimate["result"], json!(format!("0x{:x}", 21_000 + 2_118)));
+
+        let trace = handler.handle(&call("debug_traceCall", STORAGE));
+        let result = &trace["result"];
+        assert_eq!(result["failed"], json!(false));
+        assert_eq!(result["gas"], json!(21_000 + 2_118));
+        assert_eq!(result["returnValue"], json!(format!("{:064x}", 42)));
+        let ops: Vec<_> = result["structLogs"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|log| log["op"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            ops,
+            ["PUSH1", "SLOAD", "PUSH1", "MSTORE", "PUSH1", "PUSH1", "RETURN"]
+        );
+    }
+
+    #[test]
+    fn gas_is_capped() {
+        let handler = storage_contract();
+
+        let looping = handler.handle(&call("eth_call", LOOP));
+        assert_eq!(looping["error"]["code"], json!(EXECUTION_ERROR));
+        let mut request = call("eth_call", LOOP);
+        request["params"][0]["gas"] = json!("0xffffffffffffffff");
+        assert_eq!(handler.handle(&request)["error"], looping["error"]);
+
+        let estimate = handler.handle(&call("eth_estimateGas", LOOP));
+        assert_eq!(estimate["error"]["code"], json!(EXECUTION_ERROR));
+    }
+
+    #[test]
+    fn batch_requests() {
+        let handler = storage_contract();
+        let batch = json!([
+            call("eth_call", STORAGE),
+            { "jsonrpc": "2.0", "id": 2, "method": "eth_chainId" },
+            { "jsonrpc": "2.0", "id": 3, "method": "eth_getCode", "params": [] },
+        ]);
+
+        let responses = handler.handle_body(batch.to_string().as_bytes());
+        let responses = responses.as_array().unwrap();
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["result"], json!(format!("0x{:064x}", 42)));
+        assert_eq!(responses[1]["id"], json!(2));
+        assert_eq!(
+            responses[1]["result"],
+            json!(format!("0x{DEFAULT_CHAIN_ID:x}"))
+        );
+        assert_eq!(responses[2]["error"]["code"], json!(INVALID_PARAMS));
+        assert_eq!(
+            handler.handle_body(b"[{")["error"]["code"],
+            json!(PARSE_ERROR)
+        );
+    }
+}
@@ -0,0 +1,353 @@
+//! Public function selectors of deployed bytecode, found by walking the
+//! Solidity or Vyper dispatcher.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    fmt::Write,
+    fs,
+};
+
+use revm_interpreter::opcode;
+use revm_primitives::{keccak256, U256};
+
+use crate::dry_run::{
+    coverage::instruction_offsets, replay::op_name, row_usage::stack_io, state_test::is_supported,
+};
+
+/// Abstract states explored per function before giving up.
+const MAX_STATES: usize = 20_000;
+
+/// A function the dispatcher jumps to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Function {
+    pub selector: [u8; 4],
+    /// Program counter the dispatcher continues at for this selector.
+    pub entry: usize,
+    /// False when a `CALLVALUE` check guards the function or the whole
+    /// contract.
+    pub payable: bool,
+    /// Signatures of the selector in the signature file.
+    pub signatures: Vec<String>,
+    /// Unsupported opcodes the function can reach.
+    pub unsupported: BTreeSet<u8>,
+    /// Whether the function can return without executing an unsupported
+    /// opcode.
+    pub supported_path: bool,
+}
+
+/// Signatures keyed by selector.
+pub type Signatures = BTreeMap<[u8; 4], Vec<String>>;
+
+/// Load a 4byte style signature file. Each line holds a signature, optionally
+/// preceded by its hex selector, a `.json` file maps selectors to a signature
+/// or a list of them.
+pub fn load_signatures(path: &str) -> anyhow::Result<Signatures> {
+    let content = fs::read_to_string(path)?;
+    let mut signatures = Signatures::new();
+    if path.ends_with(".json") {
+        let json: BTreeMap<String, serde_json::Value> = serde_json::from_str(&content)?;
+        for (selector, value) in json {
+            let selector = parse_selector(&selector)?;
+            let names = match value {
+                serde_json::Value::Array(names) => names
+                    .iter()
+                    .filter_map(|name| name.as_str().map(str::to_string))
+                    .collect(),
+                serde_json::Value::String(name) => vec![name],
+                _ => anyhow::bail!("invalid signatures of {selector:02x?}"),
+            };
+            signatures.entry(selector).or_default().extend(names);
+        }
+        return Ok(signatures);
+    }
+
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (selector, signature) = match line.split_once(char::is_whitespace) {
+            Some((selector, signature)) => (parse_selector(selector)?, signature.trim()),
+            None => (selector_of(line), line),
+        };
+        signatures
+            .entry(selector)
+            .or_default()
+            .push(signature.to_string());
+    }
+    Ok(signatures)
+}
+
+/// Selector of a function signature such as `transfer(address,uint256)`.
+pub fn selector_of(signature: &str) -> [u8; 4] {
+    keccak256(signature.as_bytes()).as_bytes()[..4]
+        .try_into()
+        .unwrap()
+}
+
+fn parse_selector(selector: &str) -> anyhow::Result<[u8; 4]> {
+    hex::decode(selector.trim_start_matches("0x"))?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("selector {selector} is not 4 bytes"))
+}
+
+/// Functions of `code` in dispatcher order, with the signatures matching
+/// their selectors.
+pub fn extract_functions(code: &[u8], signatures: &Signatures) -> Vec<Function> {
+    let offsets = instruction_offsets(code);
+    let payable_contract = !prologue_checks_value(code, &offsets);
+    let mut seen = HashSet::new();
+    let mut functions = vec![];
+    for (index, pc) in offsets.iter().enumerate() {
+        if code[*pc] != opcode::PUSH4 || pc + 5 > code.len() {
+            continue;
+        }
+        let Some(entry) = dispatch_target(code, &offsets[index + 1..])
+            .filter(|entry| *entry < code.len())
+        else {
+            continue;
+        };
+        let selector: [u8; 4] = code[pc + 1..pc + 5].try_into().unwrap();
+        if !seen.insert(selector) {
+            continue;
+        }
+        let (unsupported, supported_path) = reachable_unsupported(code, entry);
+        functions.push(Function {
+            selector,
+            entry,
+            payable: payable_contract && !block_checks_value(code, entry),
+            signatures: signatures.get(&selector).cloned().unwrap_or_default(),
+            unsupported,
+            supported_path,
+        });
+    }
+    functions
+}
+
+/// Entry of the function a selector comparison starting at `rest` dispatches
+/// to. Solidity jumps to the entry when the selector is equal:
+/// `[DUPn] EQ PUSHn <entry> JUMPI`, Vyper falls through to it after jumping
+/// away on inequality: `[DUPn] XOR PUSHn <next> JUMPI` or
+/// `[DUPn] EQ ISZERO PUSHn <next> JUMPI`.
+fn dispatch_target(code: &[u8], rest: &[usize]) -> Option<usize> {
+    let ops: Vec<u8> = rest.iter().take(5).map(|pc| code[*pc]).collect();
+    let skip = usize::from(matches!(ops.first(), Some(opcode::DUP1..=opcode::DUP16)));
+    let ops = &ops[skip..];
+    let rest = &rest[skip..];
+    let is_push = |op: &u8| (opcode::PUSH1..=opcode::PUSH32).contains(op);
+    match ops {
+        [opcode::EQ, push, opcode::JUMPI, ..] if is_push(push) => push_value(code, rest[1]),
+        [opcode::XOR, push, opcode::JUMPI, ..] if is_push(push) => rest.get(3).copied(),
+        [opcode::EQ, opcode::ISZERO, push, opcode::JUMPI, ..] if is_push(push) => {
+            rest.get(4).copied()
+        }
+        _ => None,
+    }
+}
+
+fn push_value(code: &[u8], pc: usize) -> Option<usize> {
+    let len = (code[pc] - opcode::PUSH1 + 1) as usize;
+    let bytes = code.get(pc + 1..pc + 1 + len)?;
+    Some(
+        bytes
+            .iter()
+            .fold(0usize, |value, b| value << 8 | *b as usize),
+    )
+}
+
+/// Whether the code before the selector is loaded rejects call value, as
+/// Solidity does when no function is payable.
+fn prologue_checks_value(code: &[u8], offsets: &[usize]) -> bool {
+    offsets
+        .iter()
+        .map(|pc| code[*pc])
+        .take_while(|op| *op != opcode::CALLDATALOAD && *op != opcode::CALLDATASIZE)
+        .any(|op| op == opcode::CALLVALUE)
+}
+
+/// Whether the basic block at `pc` reads `CALLVALUE`, the non-payable check
+/// both compilers emit at the function entry.
+fn block_checks_value(code: &[u8], pc: usize) -> bool {
+    instruction_offsets(&code[pc..])
+        .into_iter()
+        .map(|offset| code[pc + offset])
+        .take_while(|op| !matches!(*op, opcode::JUMP | opcode::JUMPI | opcode::STOP))
+        .any(|op| op == opcode::CALLVALUE)
+}
+
+/// Unsupported opcodes reachable from `entry`, and whether `STOP` or
+/// `RETURN` is reachable without executing one. Jump targets are followed
+/// by tracking pushed constants on an abstract stack.
+fn reachable_unsupported(code: &[u8], entry: usize) -> (BTreeSet<u8>, bool) {
+    let mut unsupported = BTreeSet::new();
+    let mut supported_path = false;
+    // Paths free of unsupported opcodes are explored first, for them to get
+    // the state budget. The dispatcher leaves the selector on the stack.
+    let mut pending = vec![(entry, vec![None], true)];
+    let mut deferred = vec![];
+    let mut visited = HashSet::new();
+    while let Some((mut pc, mut stack, mut clean)) = pending.pop().or_else(|| deferred.pop()) {
+        if visited.len() >= MAX_STATES {
+            break;
+        }
+        loop {
+            if !visited.insert((pc, stack.clone(), clean)) {
+                break;
+            }
+            let Some(&op) = code.get(pc) else {
+                // Running off the code stops execution.
+                supported_path |= clean;
+                break;
+            };
+            if !is_supported(op) {
+                unsupported.insert(op);
+                clean = false;
+            }
+            match step(code, pc, op, &mut stack) {
+                Next::Continue(next) => pc = next,
+                Next::Branch(target, next) => {
+                    let branch = (target, stack.clone(), clean);
+                    if clean {
+                        pending.push(branch);
+                    } else {
+                        deferred.push(branch);
+                    }
+                    pc = next;
+                }
+                Next::Success => {
+                    supported_path |= clean;
+                    break;
+                }
+                Next::Halt => break,
+            }
+        }
+    }
+    (unsupported, supported_path)
+}
+
+enum Next {
+    Continue(usize),
+    /// Jump target and fall through of a `JUMPI`.
+    Branch(usize, usize),
+    Success,
+    Halt,
+}
+
+/// Apply `op` to the abstract `stack`, unknown values being `None`.
+fn step(code: &[u8], pc: usize, op: u8, stack: &mut Vec<Option<U256>>) -> Next {
+    let jumpdest = |target: Option<U256>| {
+        target
+            .and_then(|target| usize::try_from(target).ok())
+            .filter(|target| code.get(*target) == Some(&opcode::JUMPDEST))
+    };
+    match op {
+        opcode::STOP | opcode::RETURN => return Next::Success,
+        opcode::REVERT | opcode::INVALID | opcode::SELFDESTRUCT => return Next::Halt,
+        opcode::PUSH1..=opcode::PUSH32 => {
+            let len = (op - opcode::PUSH1 + 1) as usize;
+            let mut bytes = [0u8; 32];
+            let data = &code[(pc + 1).min(code.len())..(pc + 1 + len).min(code.len())];
+            bytes[32 - len..32 - len + data.len()].copy_from_slice(data);
+            stack.push(Some(U256::from_be_bytes(bytes)));
+            return Next::Continue(pc + 1 + len);
+        }
+        opcode::DUP1..=opcode::DUP16 => {
+            let n = (op - opcode::DUP1 + 1) as usize;
+            let Some(value) = stack.len().checked_sub(n).map(|i| stack[i]) else {
+                return Next::Halt;
+            };
+            stack.push(value);
+        }
+        opcode::SWAP1..=opcode::SWAP16 => {
+            let n = (op - opcode::SWAP1 + 1) as usize;
+            let Some(other) = stack.len().checked_sub(n + 1) else {
+                return Next::Halt;
+            };
+            let top = stack.len() - 1;
+            stack.swap(top, other);
+        }
+        opcode::JUMP => {
+            return match stack.pop().and_then(jumpdest) {
+                Some(target) => Next::Continue(target),
+                None => Next::Halt,
+            }
+        }
+        opcode::JUMPI => {
+            let target = stack.pop().and_then(jumpdest);
+            stack.pop();
+            return match target {
+                Some(target) => Next::Branch(target, pc + 1),
+                None => Next::Continue(pc + 1),
+            };
+        }
+        _ if opcode::OPCODE_JUMPMAP[op as usize].is_none() => {
+            return Next::Halt;
+        }
+        _ => {
+            let (inputs, outputs) = stack_io(op);
+            if stack.len() < inputs {
+                return Next::Halt;
+            }
+            stack.truncate(stack.len() - inputs);
+            stack.extend(std::iter::repeat(None).take(outputs));
+        }
+    }
+    Next::Continue(pc + 1)
+}
+
+/// Table of `functions`, one per line.
+pub fn report(functions: &[Function]) -> String {
+    let mut out = String::new();
+    for function in functions {
+        let signatures = if function.signatures.is_empty() {
+            "unknown".to_string()
+        } else {
+            function.signatures.join(" | ")
+        };
+        write!(
+            out,
+            "0x{} pc {:>5} {:<11} {signatures}",
+            hex::encode(function.selector),
+            function.entry,
+            if function.payable {
+                "payable"
+            } else {
+                "non-payable"
+            },
+        )
+        .unwrap();
+        if !function.unsupported.is_empty() {
+            let ops: Vec<_> = function.unsupported.iter().map(|op| op_name(*op)).collect();
+            let reach = if function.supported_path {
+                "may execute"
+            } else {
+                "only returns through"
+            };
+            write!(out, " ({reach} unsupported {})", ops.join(", ")).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASIC_TOKEN: &str = "608060405234801561001057600080fd5b506004361061004c5760003560e01c806318160ddd1461005157806370a0823114610068578063771602f714610091578063a9059cbb146100a4575b600080fd5b6000545b6040519081526020015b60405180910390f35b610055610076366004610198565b6001600160a01b031660009081526001602052604090205490565b61005561009f3660046101b3565b6100c7565b6100b76100b23660046101d5565b6100dc565b604051901515815260200161005f565b60006100d38284610215565b90505b92915050565b60006001600160a01b0383166100f157600080fd5b3360009081526001602052604090205482111561010d57600080fd5b33600090815260016020526040902054610128908390610228565b33600090815260016020526040808220929092556001600160a01b03851681522054610155908390610215565b6001600160a01b038416600090815260016020819052604090912091909155905092915050565b80356001600160a01b038116811461019357600080fd5b919050565b6000602082840312156101aa57600080fd5b6100d38261017c565b600080604083850312156101c657600080fd5b50508035926020909101359150565b600080604083850312156101e857600080fd5b6101f18361017c565b946020939093013593505050565b634e487b7160e01b600052601160045260246000fd5b808201808211156100d6576100d66101ff565b818103818111156100d6576100d66101ff56fea26469706673582212203c8cf1d0b0ffb741e4b0758b951e25d3fde6108d8823a4ae95a0c0fe926284bf64736f6c63430008150033";
+
+    #[test]
+    fn basic_token_interface() {
+        let signatures = Signatures::from([(
+            selector_of("transfer(address,uint256)"),
+            vec!["transfer(address,uint256)".to_string()],
+        )]);
+        let functions = extract_functions(&hex::decode(BASIC_TOKEN).unwrap(), &signatures);
+
+        let selectors: Vec<_> = functions.iter().map(|f| hex::encode(f.selector)).collect();
+        assert_eq!(selectors, ["18160ddd", "70a08231", "771602f7", "a9059cbb"]);
+        assert!(functions.iter().all(|f| !f.payable));
+        assert_eq!(functions[3].signatures, ["transfer(address,uint256)"]);
+        // `totalSupply` and `balanceOf` read storage with SLOAD.
+        assert!(!functions[0].supported_path);
+        assert!(functions[2].unsupported.is_empty() && functions[2].supported_path);
+    }
+}
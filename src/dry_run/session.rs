@@ -0,0 +1,265 @@
+//! Dry runs sharing state across calls, with snapshots and JSON export.
+use std::{collections::BTreeMap, fs, str::FromStr};
+
+use revm_primitives::{B160, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::dry_run::{
+    bytecode_run::{bytecode_run_with_config, RunConfig, RunOutput},
+    error::Result,
+    trie::AccountState,
+};
+
+/// Account in the JSON format of the ethereum/tests pre-state, hex encoded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct AccountJson {
+    pub balance: String,
+    pub code: String,
+    pub nonce: String,
+    pub storage: BTreeMap<String, String>,
+}
+
+/// Parse accounts keyed by address from their JSON form.
+pub(crate) fn accounts_from_json(
+    accounts: &BTreeMap<String, AccountJson>,
+) -> anyhow::Result<BTreeMap<B160, AccountState>> {
+    accounts
+        .iter()
+        .map(|(address, account)| {
+            Ok((
+                B160::from_str(address)?,
+                AccountState {
+                    balance: decimal_or_hex(&account.balance)?,
+                    nonce: u64::try_from(decimal_or_hex(&account.nonce)?)?,
+                    code: hex::decode(account.code.trim_start_matches("0x"))?,
+                    storage: account
+                        .storage
+                        .iter()
+                        .map(|(index, value)| Ok((decimal_or_hex(index)?, decimal_or_hex(value)?)))
+                        .collect::<anyhow::Result<_>>()?,
+                },
+            ))
+        })
+        .collect()
+}
+
+fn accounts_to_json(accounts: &BTreeMap<B160, AccountState>) -> BTreeMap<String, AccountJson> {
+    accounts
+        .iter()
+        .map(|(address, account)| {
+            (
+                format!("{address:?}"),
+                AccountJson {
+                    balance: format!("0x{:x}", account.balance),
+                    code: format!("0x{}", hex::encode(&account.code)),
+                    nonce: format!("0x{:x}", account.nonce),
+                    storage: account
+                        .storage
+                        .iter()
+                        .map(|(index, value)| (format!("0x{index:x}"), format!("0x{value:x}")))
+                        .collect(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Hex quantity, `0x` alone being zero.
+pub(crate) fn quantity(hex_str: &str) -> anyhow::Result<U256> {
+    let digits = hex_str.trim_start_matches("0x");
+    if digits.is_empty() {
+        return Ok(U256::ZERO);
+    }
+    Ok(U256::from_str_radix(digits, 16)?)
+}
+
+/// Hex when `0x` prefixed, decimal otherwise, as numbers are written in
+/// scripts and state files. `0x` alone is zero.
+pub(crate) fn decimal_or_hex(number: &str) -> anyhow::Result<U256> {
+    Ok(match number.strip_prefix("0x") {
+        Some("") => U256::ZERO,
+        Some(hex) => U256::from_str_radix(hex, 16)?,
+        None => U256::from_str_radix(number, 10)?,
+    })
+}
+
+/// Calls executed one after the other against the same accounts.
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    /// Settings of every call, `config.accounts` being the session state.
+    pub config: RunConfig,
+    snapshots: Vec<BTreeMap<B160, AccountState>>,
+}
+
+impl Session {
+    pub fn new(config: RunConfig) -> Self {
+        Self {
+            config,
+            snapshots: vec![],
+        }
+    }
+
+    pub fn accounts(&self) -> &BTreeMap<B160, AccountState> {
+        &self.config.accounts
+    }
+
+    /// The account at `address`, created empty if missing.
+    pub fn account_mut(&mut self, address: B160) -> &mut AccountState {
+        self.config.accounts.entry(address).or_default()
+    }
+
+    /// Call the code at `to` from `caller`. The state the call leaves is
+    /// kept when it succeeds and discarded otherwise.
+    pub fn call(&mut self, caller: B160, to: B160, calldata: Vec<u8>) -> Result<RunOutput> {
+        self.config.caller = caller;
+        self.config.address = to;
+        let bytecode = self
+            .config
+            .accounts
+            .get(&to)
+            .map(|account| account.code.clone())
+            .unwrap_or_default();
+        let output = bytecode_run_with_config(calldata, bytecode, None, &self.config)?;
+        self.config.accounts = output.state.clone();
+        Ok(output)
+    }
+
+    /// Record the current state, returning the id to [`Self::revert`] to.
+    pub fn snapshot(&mut self) -> usize {
+        self.snapshots.push(self.config.accounts.clone());
+        self.snapshots.len() - 1
+    }
+
+    /// Restore the state of snapshot `id`, dropping it and every later one.
+    /// Returns false for an unknown id.
+    pub fn revert(&mut self, id: usize) -> bool {
+        if id >= self.snapshots.len() {
+            return false;
+        }
+        self.config.accounts = self.snapshots[id].clone();
+        self.snapshots.truncate(id);
+        true
+    }
+
+    /// Accounts as JSON, in the ethereum/tests pre-state format.
+    pub fn export_state(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(&accounts_to_json(
+            &self.config.accounts,
+        ))?)
+    }
+
+    /// Replace the accounts with the ones of an [`Self::export_state`] JSON.
+    pub fn import_state(&mut self, json: &str) -> anyhow::Result<()> {
+        self.config.accounts = accounts_from_json(&serde_json::from_str(json)?)?;
+        Ok(())
+    }
+}
+
+/// One step of a session script. Numbers are hex when `0x` prefixed and
+/// decimal otherwise, as in state files.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptStep {
+    SetCode {
+        address: String,
+        code: String,
+    },
+    SetBalance {
+        address: String,
+        balance: String,
+    },
+    SetStorage {
+        address: String,
+        slot: String,
+        value: String,
+    },
+    Call {
+        /// Defaults to the zero address.
+        #[serde(default)]
+        from: Option<String>,
+        to: String,
+        calldata: String,
+    },
+    /// Take a snapshot under the given name.
+    Snapshot(String),
+    /// Revert to the named snapshot.
+    Revert(String),
+}
+
+/// Load a JSON or YAML list of script steps.
+pub fn load_script(path: &str) -> anyhow::Result<Vec<ScriptStep>> {
+    let content = fs::read_to_string(path)?;
+    if path.ends_with(".yaml") || path.ends_with(".yml") {
+        Ok(serde_yaml::from_str(&content)?)
+    } else {
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use revm_primitives::keccak256;
+
+    const BASIC_TOKEN: &str = "608060405234801561001057600080fd5b506004361061004c5760003560e01c806318160ddd1461005157806370a0823114610068578063771602f714610091578063a9059cbb146100a4575b600080fd5b6000545b6040519081526020015b60405180910390f35b610055610076366004610198565b6001600160a01b031660009081526001602052604090205490565b61005561009f3660046101b3565b6100c7565b6100b76100b23660046101d5565b6100dc565b604051901515815260200161005f565b60006100d38284610215565b90505b92915050565b60006001600160a01b0383166100f157600080fd5b3360009081526001602052604090205482111561010d57600080fd5b33600090815260016020526040902054610128908390610228565b33600090815260016020526040808220929092556001600160a01b03851681522054610155908390610215565b6001600160a01b038416600090815260016020819052604090912091909155905092915050565b80356001600160a01b038116811461019357600080fd5b919050565b6000602082840312156101aa57600080fd5b6100d38261017c565b600080604083850312156101c657600080fd5b50508035926020909101359150565b600080604083850312156101e857600080fd5b6101f18361017c565b946020939093013593505050565b634e487b7160e01b600052601160045260246000fd5b808201808211156100d6576100d66101ff565b818103818111156100d6576100d66101ff56fea26469706673582212203c8cf1d0b0ffb741e4b0758b951e25d3fde6108d8823a4ae95a0c0fe926284bf64736f6c63430008150033";
+
+    fn word(value: u64) -> String {
+        format!("{value:064x}")
+    }
+
+    fn balance_of(session: &mut Session, token: B160, owner: B160) -> U256 {
+        let calldata = hex::decode(format!("70a08231{:0>64}", hex::encode(owner))).unwrap();
+        let output = session.call(owner, token, calldata).unwrap();
+        U256::from_be_bytes::<32>(output.return_data.try_into().unwrap())
+    }
+
+    #[test]
+    fn transfer_then_balance_of() {
+        let token = B160::from_low_u64_be(0x100);
+        let (alice, bob) = (B160::from_low_u64_be(0xaa), B160::from_low_u64_be(0xbb));
+        let mut session = Session::default();
+        session.account_mut(token).code = hex::decode(BASIC_TOKEN).unwrap();
+        // balances[alice] = 100, `balances` being at slot 1.
+        let slot =
+            keccak256(&hex::decode(format!("{:0>64}{}", hex::encode(alice), word(1))).unwrap());
+        session
+            .account_mut(token)
+            .storage
+            .insert(U256::from_be_bytes(slot.0), U256::from(100));
+
+        let snapshot = session.snapshot();
+        let transfer = format!("a9059cbb{:0>64}{}", hex::encode(bob), word(30));
+        session
+            .call(alice, token, hex::decode(transfer).unwrap())
+            .unwrap();
+        assert_eq!(balance_of(&mut session, token, bob), U256::from(30));
+        assert_eq!(balance_of(&mut session, token, alice), U256::from(70));
+
+        let exported = session.export_state().unwrap();
+        assert!(session.revert(snapshot));
+        assert_eq!(balance_of(&mut session, token, bob), U256::ZERO);
+
+        session.import_state(&exported).unwrap();
+        assert_eq!(balance_of(&mut session, token, bob), U256::from(30));
+    }
+
+    #[test]
+    fn numbers_read_alike_in_scripts_and_state() {
+        assert_eq!(decimal_or_hex("10").unwrap(), U256::from(10));
+        assert_eq!(decimal_or_hex("0x10").unwrap(), U256::from(16));
+        assert_eq!(decimal_or_hex("0x").unwrap(), U256::ZERO);
+        assert!(decimal_or_hex("1a").is_err());
+
+        let mut session = Session::default();
+        session
+            .import_state(r#"{"0x00000000000000000000000000000000000000aa": {"balance": "10", "code": "0x", "nonce": "0x10", "storage": {"1": "0x2"}}}"#)
+            .unwrap();
+        let account = &session.accounts()[&B160::from_low_u64_be(0xaa)];
+        assert_eq!(account.balance, U256::from(10));
+        assert_eq!(account.nonce, 16);
+        assert_eq!(account.storage[&U256::from(1)], U256::from(2));
+
+        let overflow = r#"{"0x00000000000000000000000000000000000000aa": {"balance": "0", "code": "0x", "nonce": "0x10000000000000000", "storage": {}}}"#;
+        assert!(session.import_state(overflow).is_err());
+    }
+}
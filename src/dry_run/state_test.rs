@@ -0,0 +1,430 @@
+//! Runner for the ethereum/tests `GeneralStateTests`, restricted to the
+//! instruction subset the lambda-zkevm circuits support.
+//!
+//! Only the entry call goes through the dry run host. The transaction level
+//! effects it does not model (nonce bump, gas payment, value transfer and
+//! miner reward) are applied here to compute the post-state root.
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    str::FromStr,
+    sync::Arc,
+    time::Instant,
+};
+
+use glob::glob;
+use rayon::prelude::*;
+use revm_interpreter::opcode;
+use revm_primitives::{BlockEnv, B160, B256, U256};
+use serde::Deserialize;
+
+use crate::dry_run::{
+    bytecode_run::{bytecode_run_with_config, RunConfig},
+    coverage::instruction_offsets,
+    error::Error,
+    gas,
+    hardfork::Hardfork,
+    session::{accounts_from_json, quantity, AccountJson},
+    suite::CaseReport,
+    trie::{self, AccountState},
+};
+
+/// Whether the circuits support `op`, see `docs/instruction-set.png`.
+pub fn is_supported(op: u8) -> bool {
+    matches!(
+        op,
+        opcode::STOP..=opcode::SIGNEXTEND
+            | opcode::LT..=opcode::SAR
+            | opcode::SHA3
+            | opcode::ADDRESS
+            | opcode::CALLER..=opcode::CODECOPY
+            | opcode::RETURNDATASIZE
+            | opcode::RETURNDATACOPY
+            | opcode::POP..=opcode::MSTORE8
+            | opcode::JUMP..=opcode::JUMPDEST
+            | opcode::PUSH1..=opcode::PUSH32
+            | opcode::DUP1..=opcode::DUP16
+            | opcode::SWAP1..=opcode::SWAP16
+            | opcode::RETURN
+            | opcode::STATICCALL
+            | opcode::REVERT
+            | opcode::INVALID
+    )
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StateTest {
+    env: TestEnv,
+    pre: BTreeMap<String, AccountJson>,
+    transaction: TestTransaction,
+    post: BTreeMap<String, Vec<PostState>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TestEnv {
+    current_coinbase: String,
+    current_gas_limit: String,
+    current_number: String,
+    current_timestamp: String,
+    #[serde(default)]
+    current_difficulty: Option<String>,
+    #[serde(default)]
+    current_random: Option<String>,
+    #[serde(default)]
+    current_base_fee: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TestTransaction {
+    data: Vec<String>,
+    gas_limit: Vec<String>,
+    value: Vec<String>,
+    to: String,
+    #[serde(default)]
+    sender: Option<String>,
+    #[serde(default)]
+    gas_price: Option<String>,
+    #[serde(default)]
+    max_fee_per_gas: Option<String>,
+    #[serde(default)]
+    max_priority_fee_per_gas: Option<String>,
+    #[serde(default)]
+    access_lists: Option<Vec<Option<Vec<AccessListItem>>>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AccessListItem {
+    address: String,
+    storage_keys: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PostState {
+    hash: String,
+    logs: String,
+    indexes: Indexes,
+    #[serde(default)]
+    expect_exception: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+struct Indexes {
+    data: usize,
+    gas: usize,
+    value: usize,
+}
+
+/// One post-state of a test: a transaction variant under a hardfork.
+pub struct StateTestCase {
+    pub name: String,
+    hardfork: Hardfork,
+    test: Arc<StateTest>,
+    post: PostState,
+}
+
+/// Cases run and skipped by [`run_state_tests`].
+#[derive(Debug, Default)]
+pub struct StateTestSummary {
+    pub reports: Vec<CaseReport>,
+    /// Number of skipped cases per reason.
+    pub skipped: BTreeMap<String, usize>,
+}
+
+/// Load every test case of the JSON files under `dir` whose name contains
+/// `filter`, for the given `hardforks`.
+pub fn load_state_tests(
+    dir: &str,
+    filter: Option<&str>,
+    hardforks: &[Hardfork],
+) -> anyhow::Result<Vec<StateTestCase>> {
+    let mut cases = vec![];
+    for path in glob(&format!("{dir}/**/*.json"))? {
+        let path = path?;
+        let tests: HashMap<String, StateTest> =
+            match serde_json::from_str(&fs::read_to_string(&path)?) {
+                Ok(tests) => tests,
+                Err(e) => {
+                    log::warn!("Skipping {}: {e}", path.display());
+                    continue;
+                }
+            };
+        for (name, mut test) in tests {
+            if filter.map_or(false, |filter| !name.contains(filter)) {
+                continue;
+            }
+            let posts = std::mem::take(&mut test.post);
+            let test = Arc::new(test);
+            for (fork, posts) in posts {
+                let Some(hardfork) = parse_fork(&fork).filter(|h| hardforks.contains(h)) else {
+                    continue;
+                };
+                for post in posts {
+                    let Indexes { data, gas, value } = post.indexes;
+                    cases.push(StateTestCase {
+                        name: format!("{name}_d{data}g{gas}v{value}_{fork}"),
+                        hardfork,
+                        test: test.clone(),
+                        post,
+                    });
+                }
+            }
+        }
+    }
+    cases.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(cases)
+}
+
+fn parse_fork(name: &str) -> Option<Hardfork> {
+    match name {
+        "Berlin" => Some(Hardfork::Berlin),
+        "London" => Some(Hardfork::London),
+        "Merge" | "Paris" => Some(Hardfork::Merge),
+        "Shanghai" => Some(Hardfork::Shanghai),
+        _ => None,
+    }
+}
+
+/// Run all `cases` in parallel, skipping the ones outside the supported
+/// subset.
+pub fn run_state_tests(cases: &[StateTestCase]) -> StateTestSummary {
+    let outcomes: Vec<_> = cases.par_iter().map(run_case).collect();
+    let mut summary = StateTestSummary::default();
+    for outcome in outcomes {
+        match outcome {
+            Ok(report) => summary.reports.push(report),
+            Err(reason) => *summary.skipped.entry(reason).or_default() += 1,
+        }
+    }
+    summary
+}
+
+/// Run one case, or return why it is skipped.
+fn run_case(case: &StateTestCase) -> Result<CaseReport, String> {
+    let start = Instant::now();
+    let (test, post) = (&case.test, &case.post);
+    let tx = &test.transaction;
+    if post.expect_exception.is_some() {
+        return Err("invalid transaction".into());
+    }
+    if tx.to.is_empty() {
+        return Err("contract creation".into());
+    }
+    let sender = tx.sender.as_deref().ok_or("no sender")?;
+    let accounts = accounts_from_json(&test.pre).map_err(|e| format!("invalid test: {e}"))?;
+    for account in accounts.values() {
+        let code = &account.code;
+        if let Some(pc) = instruction_offsets(code)
+            .into_iter()
+            .find(|pc| !is_supported(code[*pc]))
+        {
+            return Err(format!("unsupported opcode 0x{:02x}", code[pc]));
+        }
+    }
+
+    let (calldata, gas_limit, value, access_list) =
+        tx_variant(tx, &post.indexes).map_err(|e| format!("invalid test: {e}"))?;
+    let intrinsic_gas = gas::intrinsic_gas(&calldata, &access_list, case.hardfork.spec_id());
+    if gas_limit < intrinsic_gas {
+        return Err("invalid transaction".into());
+    }
+
+    let mut failures = vec![];
+    let (block, gas_price) = block_and_gas_price(test).map_err(|e| format!("invalid test: {e}"))?;
+    let priority_fee = if case.hardfork == Hardfork::Berlin {
+        gas_price
+    } else {
+        // A gas price below the base fee is rejected before execution.
+        gas_price
+            .checked_sub(block.basefee)
+            .ok_or("invalid transaction")?
+    };
+    let config = RunConfig {
+        gas_limit: gas_limit - intrinsic_gas,
+        hardfork: case.hardfork,
+        access_list,
+        caller: B160::from_str(sender).map_err(|e| format!("invalid test: {e}"))?,
+        address: B160::from_str(&tx.to).map_err(|e| format!("invalid test: {e}"))?,
+        value,
+        block,
+        accounts,
+        ..Default::default()
+    };
+    let bytecode = config
+        .accounts
+        .get(&config.address)
+        .map(|account| account.code.clone())
+        .unwrap_or_default();
+
+    let result = bytecode_run_with_config(calldata, bytecode, None, &config);
+    let (success, gas_used, logs, mut accounts) = match result {
+        Ok(output) => (true, Some(output.gas_used), output.logs, output.state),
+        Err(Error::Reverted { gas_used, .. }) => {
+            (false, Some(gas_used), vec![], config.accounts.clone())
+        }
+        // An exceptional halt consumes all the gas.
+        Err(_) => (false, Some(gas_limit), vec![], config.accounts.clone()),
+    };
+
+    if let Some(gas_used) = gas_used {
+        let sender = accounts.entry(config.caller).or_default();
+        sender.nonce += 1;
+        sender.balance = sender
+            .balance
+            .saturating_sub(U256::from(gas_used).saturating_mul(gas_price));
+        if success {
+            sender.balance = sender.balance.saturating_sub(value);
+            accounts.entry(config.address).or_default().balance += value;
+        }
+        let coinbase = accounts.entry(config.block.coinbase).or_default();
+        coinbase.balance = coinbase
+            .balance
+            .saturating_add(U256::from(gas_used).saturating_mul(priority_fee));
+        // EIP-161: touched accounts left empty are removed.
+        for address in [config.address, config.block.coinbase] {
+            if accounts.get(&address).map_or(false, AccountState::is_empty) {
+                accounts.remove(&address);
+            }
+        }
+        let root = trie::state_root(&accounts);
+        if Some(root) != B256::from_str(&post.hash).ok() {
+            failures.push(format!(
+                "state root:\n  - expected: {}\n  + actual:   {root:?}",
+                post.hash
+            ));
+        }
+    }
+    let logs_hash = trie::logs_hash(&logs);
+    if Some(logs_hash) != B256::from_str(&post.logs).ok() {
+        failures.push(format!(
+            "logs hash:\n  - expected: {}\n  + actual:   {logs_hash:?}",
+            post.logs
+        ));
+    }
+
+    Ok(CaseReport {
+        name: case.name.clone(),
+        failures,
+        gas_used,
+        duration: start.elapsed(),
+    })
+}
+
+/// Calldata, gas limit, value and access list of the variant at `indexes`.
+fn tx_variant(
+    tx: &TestTransaction,
+    indexes: &Indexes,
+) -> anyhow::Result<(Vec<u8>, u64, U256, Vec<(B160, Vec<U256>)>)> {
+    let variant = |values: &[String], index: usize| {
+        values
+            .get(index)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("no transaction variant {index}"))
+    };
+    let calldata = decode(&variant(&tx.data, indexes.data)?)?;
+    let gas_limit = quantity(&variant(&tx.gas_limit, indexes.gas)?)?;
+    let gas_limit = u64::try_from(gas_limit)
+        .map_err(|_| anyhow::anyhow!("gas limit {gas_limit} does not fit 64 bits"))?;
+    let value = quantity(&variant(&tx.value, indexes.value)?)?;
+    let access_list = tx
+        .access_lists
+        .as_ref()
+        .and_then(|lists| lists.get(indexes.data))
+        .and_then(Option::as_ref)
+        .map_or(Ok(vec![]), |items| {
+            items
+                .iter()
+                .map(|item| {
+                    Ok((
+                        B160::from_str(&item.address)?,
+                        item.storage_keys
+                            .iter()
+                            .map(|key| quantity(key))
+                            .collect::<anyhow::Result<_>>()?,
+                    ))
+                })
+                .collect::<anyhow::Result<_>>()
+        })?;
+    Ok((calldata, gas_limit, value, access_list))
+}
+
+/// Block of the test and the effective gas price.
+fn block_and_gas_price(test: &StateTest) -> anyhow::Result<(BlockEnv, U256)> {
+    let env = &test.env;
+    let tx = &test.transaction;
+    let optional = |value: &Option<String>| value.as_deref().map(quantity).transpose();
+    let block = BlockEnv {
+        number: quantity(&env.current_number)?,
+        coinbase: B160::from_str(&env.current_coinbase)?,
+        timestamp: quantity(&env.current_timestamp)?,
+        gas_limit: quantity(&env.current_gas_limit)?,
+        basefee: optional(&env.current_base_fee)?.unwrap_or_default(),
+        difficulty: optional(&env.current_difficulty)?.unwrap_or_default(),
+        prevrandao: env
+            .current_random
+            .as_deref()
+            .map(B256::from_str)
+            .transpose()?,
+    };
+
+    let gas_price = match (optional(&tx.gas_price)?, optional(&tx.max_fee_per_gas)?) {
+        (Some(gas_price), _) => gas_price,
+        (None, Some(max_fee)) => {
+            let max_priority_fee = optional(&tx.max_priority_fee_per_gas)?.unwrap_or_default();
+            max_fee.min(block.basefee.saturating_add(max_priority_fee))
+        }
+        (None, None) => anyhow::bail!("no gas price"),
+    };
+    Ok((block, gas_price))
+}
+
+fn decode(hex_str: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(hex::decode(hex_str.trim_start_matches("0x"))?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn supported_subset() {
+        for op in [
+            opcode::ADD,
+            opcode::SHA3,
+            opcode::CALLDATACOPY,
+            opcode::STATICCALL,
+        ] {
+            assert!(is_supported(op), "0x{op:02x}");
+        }
+        for op in [
+            opcode::BALANCE,
+            opcode::ORIGIN,
+            opcode::SLOAD,
+            opcode::LOG0,
+            opcode::CALL,
+            opcode::PUSH0,
+        ] {
+            assert!(!is_supported(op), "0x{op:02x}");
+        }
+    }
+    #[test]
+    fn oversized_gas_limit_is_invalid() {
+        let tx: TestTransaction = serde_json::from_value(serde_json::json!({
+            "data": ["0x"],
+            "gasLimit": ["0x010000000000000000"],
+            "value": ["0x00"],
+            "to": "0x0000000000000000000000000000000000000100",
+        }))
+        .unwrap();
+        let indexes = Indexes {
+            data: 0,
+            gas: 0,
+            value: 0,
+        };
+        assert!(tx_variant(&tx, &indexes).is_err());
+    }
+}
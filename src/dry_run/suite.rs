@@ -0,0 +1,362 @@
+//! Conformance suites of dry run test vectors with expected outputs.
+use std::{
+    fmt::Write,
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use glob::glob;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::dry_run::{
+    bytecode_run::{bytecode_run_with_config, RunConfig, RunOutput},
+    error::{Error, Result},
+};
+
+/// A single dry run case and what it is expected to produce.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TestVector {
+    pub name: String,
+    /// Hex encoded, with or without `0x`.
+    pub calldata: String,
+    pub bytecode: String,
+    #[serde(default)]
+    pub hardcode: Option<String>,
+    #[serde(default)]
+    pub expected: Expectation,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Expectation {
+    /// Expected return data of a successful run.
+    #[serde(default, rename = "return")]
+    pub return_data: Option<String>,
+    /// Expected revert data, the run must revert when set.
+    #[serde(default)]
+    pub revert: Option<String>,
+    /// Expected logs, in emission order.
+    #[serde(default)]
+    pub logs: Option<Vec<ExpectedLog>>,
+    /// Upper bound of the transaction gas used.
+    #[serde(default)]
+    pub max_gas: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExpectedLog {
+    pub address: String,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub data: String,
+}
+
+/// A vector file holds one vector or a list of them.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VectorFile {
+    One(TestVector),
+    Many(Vec<TestVector>),
+}
+
+/// Outcome of one vector.
+#[derive(Clone, Debug)]
+pub struct CaseReport {
+    pub name: String,
+    /// One entry per unmet expectation, empty when the case passed.
+    pub failures: Vec<String>,
+    pub gas_used: Option<u64>,
+    pub duration: Duration,
+}
+
+impl CaseReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Load every `*.json`, `*.yaml` and `*.yml` vector file under `dir`.
+pub fn load_suite(dir: &str) -> anyhow::Result<Vec<TestVector>> {
+    let mut vectors = vec![];
+    for ext in ["json", "yaml", "yml"] {
+        for path in glob(&format!("{dir}/**/*.{ext}"))? {
+            let path = path?;
+            let content = fs::read_to_string(&path)?;
+            let file: VectorFile = if ext == "json" {
+                serde_json::from_str(&content)?
+            } else {
+                serde_yaml::from_str(&content)?
+            };
+            match file {
+                VectorFile::One(vector) => vectors.push(vector),
+                VectorFile::Many(many) => vectors.extend(many),
+            }
+        }
+    }
+    vectors.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(vectors)
+}
+
+/// Run all `vectors` in parallel.
+pub fn run_suite(vectors: &[TestVector], config: &RunConfig) -> Vec<CaseReport> {
+    vectors
+        .par_iter()
+        .map(|vector| run_vector(vector, config))
+        .collect()
+}
+
+pub fn run_vector(vector: &TestVector, config: &RunConfig) -> CaseReport {
+    let start = Instant::now();
+    let mut failures = vec![];
+    let result = decode(&vector.calldata).and_then(|calldata| {
+        let bytecode = decode(&vector.bytecode)?;
+        let hardcode = vector.hardcode.as_deref().map(decode).transpose()?;
+        Ok(bytecode_run_with_config(
+            calldata, bytecode, hardcode, config,
+        ))
+    });
+    let gas_used = match result {
+        Err(e) => {
+            failures.push(format!("invalid vector: {e}"));
+            None
+        }
+        Ok(result) => {
+            check(&vector.expected, &result, &mut failures);
+            result.ok().map(|output| output.gas_used)
+        }
+    };
+
+    CaseReport {
+        name: vector.name.clone(),
+        failures,
+        gas_used,
+        duration: start.elapsed(),
+    }
+}
+
+fn check(expected: &Expectation, result: &Result<RunOutput>, failures: &mut Vec<String>) {
+    match (result, &expected.revert) {
+        (Ok(output), None) => {
+            if let Some(return_data) = &expected.return_data {
+                failures.extend(diff(
+                    "return",
+                    return_data,
+                    &hex::encode(&output.return_data),
+                ));
+            }
+            if let Some(logs) = &expected.logs {
+                let actual: Vec<_> = output
+                    .logs
+                    .iter()
+                    .map(|log| ExpectedLog {
+                        address: format!("{:?}", log.address),
+                        topics: log.topics.iter().map(|t| format!("{t:?}")).collect(),
+                        data: hex::encode(&log.data),
+                    })
+                    .collect();
+                failures.extend(diff("logs", &format_logs(logs), &format_logs(&actual)));
+            }
+            if let Some(max_gas) = expected.max_gas {
+                if output.gas_used > max_gas {
+                    failures.push(format!(
+                        "gas: used {} exceeds max {max_gas}",
+                        output.gas_used
+                    ));
+                }
+            }
+        }
+        (Ok(output), Some(revert)) => failures.extend(diff(
+            "revert",
+            revert,
+            &format!("success with {}", hex::encode(&output.return_data)),
+        )),
+        (Err(Error::Reverted { output, .. }), Some(revert)) => {
+            failures.extend(diff("revert", revert, &hex::encode(output)))
+        }
+        (Err(e), _) => failures.push(format!("execution failed: {e}")),
+    }
+}
+
+fn diff(what: &str, expected: &str, actual: &str) -> Option<String> {
+    (normalize(expected) != normalize(actual))
+        .then(|| format!("{what}:\n  - expected: {expected}\n  + actual:   {actual}"))
+}
+
+fn decode(hex_str: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(hex::decode(hex_str.trim().trim_start_matches("0x"))?)
+}
+
+/// Lower case hex without `0x`, so expectations may be written either way.
+fn normalize(s: &str) -> String {
+    s.replace("0x", "").to_lowercase()
+}
+
+fn format_logs(logs: &[ExpectedLog]) -> String {
+    let logs: Vec<_> = logs
+        .iter()
+        .map(|log| {
+            format!(
+                "{{address: {}, topics: [{}], data: {}}}",
+                log.address,
+                log.topics.join(", "),
+                log.data
+            )
+        })
+        .collect();
+    format!("[{}]", logs.join(", "))
+}
+
+/// Pass/fail table followed by the diffs of failed cases.
+pub fn table(reports: &[CaseReport]) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{:<40} {:<6} {:>12} {:>10}",
+        "case", "result", "gas", "time"
+    )
+    .unwrap();
+    for report in reports {
+        writeln!(
+            out,
+            "{:<40} {:<6} {:>12} {:>8}ms",
+            report.name,
+            if report.passed() { "PASS" } else { "FAIL" },
+            report
+                .gas_used
+                .map_or_else(|| "-".to_string(), |gas| gas.to_string()),
+            report.duration.as_millis()
+        )
+        .unwrap();
+    }
+    for report in reports.iter().filter(|r| !r.passed()) {
+        writeln!(out, "\n{}:", report.name).unwrap();
+        for failure in &report.failures {
+            writeln!(out, "{failure}").unwrap();
+        }
+    }
+    let passed = reports.iter().filter(|r| r.passed()).count();
+    writeln!(out, "\n{passed}/{} passed", reports.len()).unwrap();
+    out
+}
+
+/// JUnit XML report of `reports`, one `testcase` per vector.
+pub fn junit_xml(suite: &str, reports: &[CaseReport]) -> String {
+    let failures = reports.iter().filter(|r| !r.passed()).count();
+    let time: f64 = reports.iter().map(|r| r.duration.as_secs_f64()).sum();
+    let mut out = String::new();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        out,
+        r#"<testsuite name="{}" tests="{}" failures="{failures}" time="{time:.3}">"#,
+        escape(suite),
+        reports.len()
+    )
+    .unwrap();
+    for report in reports {
+        write!(
+            out,
+            r#"  <testcase name="{}" classname="{}" time="{:.3}""#,
+            escape(&report.name),
+            escape(suite),
+            report.duration.as_secs_f64()
+        )
+        .unwrap();
+        if report.passed() {
+            writeln!(out, "/>").unwrap();
+        } else {
+            writeln!(out, ">").unwrap();
+            writeln!(
+                out,
+                r#"    <failure message="{}">{}</failure>"#,
+                escape(&report.failures[0]),
+                escape(&report.failures.join("\n"))
+            )
+            .unwrap();
+            writeln!(out, "  </testcase>").unwrap();
+        }
+    }
+    writeln!(out, "</testsuite>").unwrap();
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Name of the suite under `dir`, used in reports.
+pub fn suite_name(dir: &str) -> String {
+    Path::new(dir)
+        .file_name()
+        .map_or_else(|| dir.to_string(), |name| name.to_string_lossy().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_suite_passes() {
+        let vectors = load_suite("data/suite").unwrap();
+        let reports = run_suite(&vectors, &RunConfig::default());
+        let names: Vec<_> = reports.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, ["calculation_add", "calculation_unknown_selector"]);
+        assert!(reports.iter().all(CaseReport::passed));
+        assert!(reports[0].gas_used.unwrap() <= 30_000);
+        assert_eq!(reports[1].gas_used, None);
+
+        let xml = junit_xml(&suite_name("data/suite"), &reports);
+        assert!(xml.contains(r#"<testsuite name="suite" tests="2" failures="0""#));
+        assert!(xml.contains(r#"<testcase name="calculation_add" classname="suite""#));
+        assert!(!xml.contains("<failure"));
+    }
+
+    #[test]
+    fn failing_yaml_vectors() {
+        let dir = std::env::temp_dir().join(format!("suite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("vectors.yaml"),
+            r#"
+- name: log <&> "quoted"
+  calldata: ""
+  # PUSH1 0 PUSH1 0 LOG0 STOP
+  bytecode: "60006000a000"
+  expected:
+    logs:
+      - address: "0x0000000000000000000000000000000000000000"
+    max_gas: 21000
+- name: unexpected_success
+  calldata: ""
+  bytecode: "00"
+  expected:
+    revert: "0x"
+- name: wrong_return
+  calldata: ""
+  # PUSH1 0x2a PUSH1 0 MSTORE8 PUSH1 1 PUSH1 0 RETURN
+  bytecode: "602a60005360016000f3"
+  expected:
+    return: "0x2b"
+"#,
+        )
+        .unwrap();
+
+        let vectors = load_suite(dir.to_str().unwrap()).unwrap();
+        let reports = run_suite(&vectors, &RunConfig::default());
+        let failures: Vec<_> = reports.iter().map(|r| r.failures.as_slice()).collect();
+        assert_eq!(failures.len(), 3);
+        assert_eq!(failures[0], ["gas: used 21381 exceeds max 21000"]);
+        assert!(failures[1][0].starts_with("revert:"));
+        assert!(failures[2][0].starts_with("return:"));
+
+        let xml = junit_xml("vectors", &reports);
+        assert!(xml.contains(r#"tests="3" failures="3""#));
+        assert!(xml.contains(r#"<testcase name="log &lt;&amp;&gt; &quot;quoted&quot;""#));
+        assert_eq!(xml.matches("<failure message=").count(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+//! Ethereum Merkle Patricia trie roots and RLP commitments of world state and
+//! logs, as found in the post-state of ethereum/tests.
+use std::collections::BTreeMap;
+
+use revm_primitives::{keccak256, Log, B160, B256, U256};
+use rlp::RlpStream;
+
+/// Account fields committed to by the state root.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AccountState {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code: Vec<u8>,
+    pub storage: BTreeMap<U256, U256>,
+}
+
+impl AccountState {
+    /// Empty per EIP-161.
+    pub fn is_empty(&self) -> bool {
+        self.balance == U256::ZERO && self.nonce == 0 && self.code.is_empty()
+    }
+}
+
+/// Root of the secure trie of `accounts`.
+pub fn state_root(accounts: &BTreeMap<B160, AccountState>) -> B256 {
+    trie_root(accounts.iter().map(|(address, account)| {
+        let mut stream = RlpStream::new_list(4);
+        stream
+            .append(&account.nonce)
+            .append(&u256_bytes(&account.balance))
+            .append(&storage_root(&account.storage).as_bytes().to_vec())
+            .append(&keccak256(&account.code).as_bytes().to_vec());
+        (
+            keccak256(address.as_bytes()).as_bytes().to_vec(),
+            stream.out().to_vec(),
+        )
+    }))
+}
+
+/// Root of the secure trie of the non-zero `storage` slots.
+pub fn storage_root(storage: &BTreeMap<U256, U256>) -> B256 {
+    trie_root(
+        storage
+            .iter()
+            .filter(|(_, value)| **value != U256::ZERO)
+            .map(|(index, value)| {
+                (
+                    keccak256(&index.to_be_bytes::<32>()).as_bytes().to_vec(),
+                    rlp::encode(&u256_bytes(value)).to_vec(),
+                )
+            }),
+    )
+}
+
+/// Keccak of the RLP list of `logs`, the `logs` field of state test posts.
+pub fn logs_hash(logs: &[Log]) -> B256 {
+    let mut stream = RlpStream::new_list(logs.len());
+    for log in logs {
+        stream.begin_list(3);
+        stream.append(&log.address.as_bytes().to_vec());
+        stream.begin_list(log.topics.len());
+        for topic in &log.topics {
+            stream.append(&topic.as_bytes().to_vec());
+        }
+        stream.append(&log.data.to_vec());
+    }
+    keccak256(&stream.out())
+}
+
+/// Big endian bytes of `value` without leading zeros, as RLP encodes scalars.
+pub(crate) fn u256_bytes(value: &U256) -> Vec<u8> {
+    let bytes = value.to_be_bytes::<32>();
+    let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len());
+    bytes[start..].to_vec()
+}
+
+/// Root of the trie holding `entries`, keys are used as given.
+pub fn trie_root(entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> B256 {
+    let mut items: Vec<_> = entries
+        .into_iter()
+        .map(|(key, value)| (nibbles(&key), value))
+        .collect();
+    if items.is_empty() {
+        return keccak256(&rlp::NULL_RLP);
+    }
+    items.sort();
+    keccak256(&encode_node(&items, 0))
+}
+
+fn nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect()
+}
+
+/// Compact encoding of a nibble path.
+fn hex_prefix(path: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 } + (path.len() % 2) as u8;
+    let mut out = vec![];
+    let rest = if path.len() % 2 == 1 {
+        out.push(flag << 4 | path[0]);
+        &path[1..]
+    } else {
+        out.push(flag << 4);
+        path
+    };
+    out.extend(rest.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+    out
+}
+
+/// RLP of the node holding the sorted `items`, whose keys share the first
+/// `depth` nibbles.
+fn encode_node(items: &[(Vec<u8>, Vec<u8>)], depth: usize) -> Vec<u8> {
+    if let [(key, value)] = items {
+        let mut stream = RlpStream::new_list(2);
+        stream
+            .append(&hex_prefix(&key[depth..], true))
+            .append(value);
+        return stream.out().to_vec();
+    }
+
+    let (first, last) = (&items[0].0, &items[items.len() - 1].0);
+    let common = first[depth..]
+        .iter()
+        .zip(&last[depth..])
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common > 0 {
+        let mut stream = RlpStream::new_list(2);
+        stream.append(&hex_prefix(&first[depth..depth + common], false));
+        append_child(&mut stream, encode_node(items, depth + common));
+        return stream.out().to_vec();
+    }
+
+    let mut stream = RlpStream::new_list(17);
+    for nibble in 0..16 {
+        let children: Vec<_> = items
+            .iter()
+            .filter(|(key, _)| key.get(depth) == Some(&nibble))
+            .cloned()
+            .collect();
+        if children.is_empty() {
+            stream.append_empty_data();
+        } else {
+            append_child(&mut stream, encode_node(&children, depth + 1));
+        }
+    }
+    match items.iter().find(|(key, _)| key.len() == depth) {
+        Some((_, value)) => stream.append(value),
+        None => stream.append_empty_data(),
+    };
+    stream.out().to_vec()
+}
+
+/// Nodes shorter than a hash are embedded, others referenced by hash.
+fn append_child(stream: &mut RlpStream, node: Vec<u8>) {
+    if node.len() < 32 {
+        stream.append_raw(&node, 1);
+    } else {
+        stream.append(&keccak256(&node).as_bytes().to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn empty_roots() {
+        let empty_root =
+            B256::from_str("0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+                .unwrap();
+        assert_eq!(state_root(&BTreeMap::new()), empty_root);
+        assert_eq!(storage_root(&BTreeMap::new()), empty_root);
+        assert_eq!(
+            logs_hash(&[]),
+            B256::from_str("0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn dogs_trie() {
+        // Reference vector of the ethereum/tests `trietest.json` suite.
+        let root = trie_root(
+            [
+                ("doe", "reindeer"),
+                ("dog", "puppy"),
+                ("dogglesworth", "cat"),
+            ]
+            .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec())),
+        );
+        assert_eq!(
+            root,
+            B256::from_str("0x8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3")
+                .unwrap()
+        );
+    }
+}
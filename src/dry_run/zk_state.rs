@@ -0,0 +1,229 @@
+//! World state kept in Scroll's zktrie, giving the roots and proofs a block
+//! trace's `storageTrace` needs for the MPT circuit.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+use bus_mapping::util::hash_code_poseidon;
+use mpt_zktrie::state::builder::HASH_SCHEME_DONE;
+use revm_primitives::{keccak256, B160, B256, U256};
+use serde_json::{json, Value};
+use zktrie::{AccountData, ZkMemoryDb, ZkTrie};
+
+use crate::dry_run::trie::AccountState;
+
+/// Root of the empty zktrie.
+const EMPTY_ROOT: [u8; 32] = [0; 32];
+
+/// Accounts and storage in zktries sharing one node database.
+pub struct ZkState {
+    db: Rc<ZkMemoryDb>,
+    trie: ZkTrie,
+}
+
+impl Default for ZkState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ZkState {
+    /// The empty state.
+    pub fn new() -> Self {
+        assert!(*HASH_SCHEME_DONE);
+        let mut db = ZkMemoryDb::new();
+        let trie = db.new_trie(&EMPTY_ROOT).expect("empty zktrie");
+        Self { db, trie }
+    }
+
+    /// State holding `accounts`.
+    pub fn from_accounts(accounts: &BTreeMap<B160, AccountState>) -> anyhow::Result<Self> {
+        let mut state = Self::new();
+        state.apply(accounts)?;
+        Ok(state)
+    }
+
+    pub fn root(&self) -> B256 {
+        B256(self.trie.root())
+    }
+
+    /// Write `accounts`, as left by a dry run in `RunOutput::state`. Slots
+    /// set to zero are deleted, empty accounts without storage too.
+    pub fn apply(&mut self, accounts: &BTreeMap<B160, AccountState>) -> anyhow::Result<()> {
+        for (address, account) in accounts {
+            let mut storage = self.storage_trie(*address)?;
+            for (slot, value) in &account.storage {
+                let key = slot.to_be_bytes::<32>();
+                if *value == U256::ZERO {
+                    storage.delete(&key);
+                } else {
+                    storage
+                        .update_store(&key, &value.to_be_bytes::<32>())
+                        .map_err(|e| anyhow::anyhow!("storage of {address:?}: {e:?}"))?;
+                }
+            }
+            let storage_root = storage.root();
+
+            if account.is_empty() && storage_root == EMPTY_ROOT {
+                self.trie.delete(address.as_bytes());
+                continue;
+            }
+            self.trie
+                .update_account(address.as_bytes(), &account_data(account, storage_root))
+                .map_err(|e| anyhow::anyhow!("account {address:?}: {e:?}"))?;
+        }
+        Ok(())
+    }
+
+    /// Delete the account at `address`, its storage with it: a later write
+    /// starts from empty storage.
+    pub fn delete_account(&mut self, address: B160) {
+        self.trie.delete(address.as_bytes());
+    }
+
+    /// Proof of the account at `address`, or of its absence.
+    pub fn account_proof(&self, address: B160) -> anyhow::Result<Vec<Vec<u8>>> {
+        self.trie
+            .prove(address.as_bytes())
+            .map_err(|e| anyhow::anyhow!("proof of {address:?}: {e:?}"))
+    }
+
+    /// Proof of `slot` in the storage of `address`, or of its absence.
+    pub fn storage_proof(&mut self, address: B160, slot: U256) -> anyhow::Result<Vec<Vec<u8>>> {
+        self.storage_trie(address)?
+            .prove(&slot.to_be_bytes::<32>())
+            .map_err(|e| anyhow::anyhow!("proof of slot {slot:#x} of {address:?}: {e:?}"))
+    }
+
+    /// `storageTrace` of a block touching the `touched` accounts and slots,
+    /// deleting the `deleted` accounts and leaving `post`: proofs against the
+    /// current state, which is then updated. Accounts both deleted and in
+    /// `post` were created again, from empty storage.
+    pub fn storage_trace(
+        &mut self,
+        touched: &BTreeMap<B160, BTreeSet<U256>>,
+        deleted: &BTreeSet<B160>,
+        post: &BTreeMap<B160, AccountState>,
+    ) -> anyhow::Result<Value> {
+        let root_before = self.root();
+        let mut proofs = serde_json::Map::new();
+        let mut storage_proofs = serde_json::Map::new();
+        for (address, slots) in touched {
+            proofs.insert(
+                format!("{address:?}"),
+                hex_nodes(self.account_proof(*address)?),
+            );
+            if slots.is_empty() {
+                continue;
+            }
+            let mut slot_proofs = serde_json::Map::new();
+            for slot in slots {
+                slot_proofs.insert(
+                    format!("0x{}", hex::encode(slot.to_be_bytes::<32>())),
+                    hex_nodes(self.storage_proof(*address, *slot)?),
+                );
+            }
+            storage_proofs.insert(format!("{address:?}"), Value::Object(slot_proofs));
+        }
+        for address in deleted {
+            self.delete_account(*address);
+        }
+        self.apply(post)?;
+
+        Ok(json!({
+            "rootBefore": format!("{root_before:?}"),
+            "rootAfter": format!("{:?}", self.root()),
+            "proofs": proofs,
+            "storageProofs": storage_proofs,
+        }))
+    }
+
+    fn storage_trie(&mut self, address: B160) -> anyhow::Result<ZkTrie> {
+        let root = self
+            .trie
+            .get_account(address.as_bytes())
+            .map_or(EMPTY_ROOT, |data| data[2]);
+        self.db
+            .new_trie(&root)
+            .ok_or_else(|| anyhow::anyhow!("missing storage trie of {address:?}"))
+    }
+}
+
+/// Account leaf values: code size and nonce, balance, storage root, keccak
+/// and poseidon code hashes.
+fn account_data(account: &AccountState, storage_root: [u8; 32]) -> AccountData {
+    let mut sizes = [0; 32];
+    sizes[16..24].copy_from_slice(&(account.code.len() as u64).to_be_bytes());
+    sizes[24..32].copy_from_slice(&account.nonce.to_be_bytes());
+    [
+        sizes,
+        account.balance.to_be_bytes::<32>(),
+        storage_root,
+        keccak256(&account.code).0,
+        hash_code_poseidon(&account.code).0,
+    ]
+}
+
+fn hex_nodes(nodes: Vec<Vec<u8>>) -> Value {
+    nodes
+        .into_iter()
+        .map(|node| format!("0x{}", hex::encode(node)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dry_run::replay::zktrie_leaf;
+
+    #[test]
+    fn proofs_decode_to_state() {
+        let token = B160::from_low_u64_be(0x100);
+        let mut account = AccountState {
+            balance: U256::from(7),
+            nonce: 1,
+            code: hex::decode("60005460005260206000f3").unwrap(),
+            ..Default::default()
+        };
+        account.storage.insert(U256::ZERO, U256::from(42));
+        let mut state =
+            ZkState::from_accounts(&BTreeMap::from([(token, account.clone())])).unwrap();
+        let root = state.root();
+
+        let encode =
+            |nodes: Vec<Vec<u8>>| -> Vec<String> { nodes.iter().map(hex::encode).collect() };
+        let leaf = zktrie_leaf(
+            &encode(state.account_proof(token).unwrap()),
+            token.as_bytes(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(U256::from_be_bytes(leaf[1]), account.balance);
+        assert_eq!(B256(leaf[3]), keccak256(&account.code));
+        let slot = zktrie_leaf(
+            &encode(state.storage_proof(token, U256::ZERO).unwrap()),
+            &[0; 32],
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(U256::from_be_bytes(slot[0]), U256::from(42));
+
+        // Clearing the slot changes the root, clearing the account too
+        // brings back the empty state.
+        account.storage.insert(U256::ZERO, U256::ZERO);
+        let trace = state
+            .storage_trace(
+                &BTreeMap::from([(token, BTreeSet::from([U256::ZERO]))]),
+                &BTreeSet::new(),
+                &BTreeMap::from([(token, account.clone())]),
+            )
+            .unwrap();
+        assert_eq!(trace["rootBefore"], json!(format!("{root:?}")));
+        assert_ne!(state.root(), root);
+        state
+            .apply(&BTreeMap::from([(token, AccountState::default())]))
+            .unwrap();
+        assert_eq!(state.root(), B256(EMPTY_ROOT));
+    }
+}
@@ -0,0 +1,721 @@
+//! Assembly of Scroll block traces from what plain geth or anvil nodes
+//! provide: the block, `debug_traceTransaction` struct logs and
+//! `prestateTracer` output.
+//!
+//! The pre-state gives every touched account and slot, from which the
+//! `storageTrace` is proven in a local zktrie. The post-state comes from
+//! `prestateTracer` in diff mode. Without it, only the storage writes of the
+//! struct logs and sender nonces are applied, balances keeping their values
+//! before the block.
+//!
+//! Diff mode `pre` only holds what a transaction changed, so accounts and
+//! slots it merely read get no proof. Merge it with a non diff mode result of
+//! the same transaction for a `storageTrace` covering reads too.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    str::FromStr,
+};
+
+use bus_mapping::util::hash_code_poseidon;
+use revm_primitives::{keccak256, B160, U256};
+use rlp::RlpStream;
+use serde_json::{json, Value};
+
+use super::validate::{EXPECTED_TRACE_VERSION, L1_MESSAGE_TX_TYPE};
+use crate::dry_run::{
+    session::quantity,
+    trie::{u256_bytes, AccountState},
+    zk_state::ZkState,
+};
+
+/// Scroll's fee vault, the coinbase of blocks whose miner is unset.
+const L2_FEE_VAULT: &str = "0x5300000000000000000000000000000000000005";
+/// Predeploy whose slot 0 holds the withdraw trie root.
+const L2_MESSAGE_QUEUE: &str = "0x5300000000000000000000000000000000000000";
+/// Predeploy holding the L1 base fee, fee overhead and scalar in slots 1 to 3.
+const L1_GAS_PRICE_ORACLE: &str = "0x5300000000000000000000000000000000000002";
+const L1_FEE_PRECISION: u64 = 1_000_000_000;
+
+/// Assemble the block trace, as a JSON-RPC response like l2geth's, from the
+/// files of an `eth_getBlockByNumber` result with full transactions, of
+/// `debug_traceBlockByNumber` (or per transaction `debug_traceTransaction`)
+/// results, and of `prestateTracer` results of the same shape.
+pub fn assemble_trace_files(
+    block: &str,
+    traces: &str,
+    prestates: &str,
+    chain_id: Option<u64>,
+) -> anyhow::Result<Value> {
+    let read = |path: &str| -> anyhow::Result<Value> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    };
+    let trace = assemble_block_trace(
+        &read(block)?,
+        &results(read(traces)?),
+        &results(read(prestates)?),
+        chain_id,
+    )?;
+    Ok(json!({ "jsonrpc": "2.0", "id": 1, "result": trace }))
+}
+
+/// Assemble the block trace of `block` from the struct logger result and
+/// the prestate tracer result of each of its transactions. `chain_id`
+/// defaults to the one of the transactions.
+pub fn assemble_block_trace(
+    block: &Value,
+    traces: &[Value],
+    prestates: &[Value],
+    chain_id: Option<u64>,
+) -> anyhow::Result<Value> {
+    let block = block.get("result").unwrap_or(block);
+    let transactions = block["transactions"]
+        .as_array()
+        .ok_or_else(|| anyhow::anyhow!("block without transactions"))?;
+    anyhow::ensure!(
+        transactions.iter().all(Value::is_object),
+        "block transactions must be full objects"
+    );
+    anyhow::ensure!(
+        traces.len() == transactions.len() && prestates.len() == transactions.len(),
+        "{} transactions but {} traces and {} prestates",
+        transactions.len(),
+        traces.len(),
+        prestates.len()
+    );
+    let chain_id = match chain_id {
+        Some(chain_id) => chain_id,
+        None => transactions
+            .iter()
+            .find_map(|tx| tx.get("chainId").and_then(Value::as_str))
+            .map(|chain_id| Ok::<_, anyhow::Error>(u64::try_from(quantity(chain_id)?)?))
+            .transpose()?
+            .ok_or_else(|| anyhow::anyhow!("no chain id in the block, give it explicitly"))?,
+    };
+    let coinbase = match block.get("miner").and_then(Value::as_str).map(address) {
+        Some(Ok(miner)) if miner != B160::zero() => miner,
+        _ => address(L2_FEE_VAULT)?,
+    };
+
+    // State before the block: what each transaction's prestate first shows.
+    let mut pre: BTreeMap<B160, AccountState> = BTreeMap::new();
+    let mut touched: BTreeMap<B160, BTreeSet<U256>> = BTreeMap::new();
+    for prestate in prestates {
+        let prestate = prestate.get("pre").unwrap_or(prestate);
+        for (account_address, account, _) in accounts(prestate)? {
+            let slots = touched.entry(account_address).or_default();
+            slots.extend(account.storage.keys());
+            let entry = pre.entry(account_address).or_insert_with(|| AccountState {
+                storage: BTreeMap::new(),
+                ..account.clone()
+            });
+            for (slot, value) in account.storage {
+                entry.storage.entry(slot).or_insert(value);
+            }
+        }
+    }
+    touched.entry(coinbase).or_default();
+
+    let mut state = pre.clone();
+    // Accounts diff mode shows deleted, some maybe created again later.
+    let mut deleted = BTreeSet::new();
+    let mut tx_traces = vec![];
+    let mut results = vec![];
+    for ((tx, trace), prestate) in transactions.iter().zip(traces).zip(prestates) {
+        let tx_trace = transaction(tx, chain_id)?;
+        let from = address(tx_trace["from"].as_str().unwrap_or_default())?;
+        let to = tx_trace["to"].as_str().map(address).transpose()?;
+        touched.entry(from).or_default();
+        if let Some(to) = to {
+            touched.entry(to).or_default();
+        }
+
+        let l1_fee = if tx_trace["type"] == json!(L1_MESSAGE_TX_TYPE) {
+            U256::ZERO
+        } else {
+            l1_fee(&state, &signed_rlp(tx, chain_id)?)?
+        };
+        let account = |state: &BTreeMap<B160, AccountState>, address: B160| {
+            account_wrapper(address, &state.get(&address).cloned().unwrap_or_default())
+        };
+        let from_before = account(&state, from);
+        let to_before = to.map(|to| account(&state, to));
+        let code = to
+            .and_then(|to| state.get(&to))
+            .map(|account| account.code.clone())
+            .unwrap_or_default();
+        let struct_logs = struct_logs(trace, to, &state)?;
+
+        match prestate.get("post") {
+            Some(post) => apply_diff(&mut state, &mut deleted, prestate, post)?,
+            None => {
+                state.entry(from).or_default().nonce += 1;
+                // A failed transaction's writes are all reverted.
+                let writes = if trace["failed"].as_bool().unwrap_or_default() {
+                    vec![]
+                } else {
+                    storage_writes(&struct_logs, to)?
+                };
+                for (address, slot, value) in writes {
+                    state
+                        .entry(address)
+                        .or_default()
+                        .storage
+                        .insert(slot, value);
+                }
+            }
+        }
+
+        results.push(json!({
+            "l1DataFee": format!("{l1_fee:#x}"),
+            "gas": trace["gas"],
+            "failed": trace["failed"],
+            "returnValue": trace["returnValue"]
+                .as_str()
+                .unwrap_or_default()
+                .trim_start_matches("0x"),
+            "from": from_before,
+            "to": to_before,
+            "accountAfter": [account(&state, from), to.map(|to| account(&state, to)), account(&state, coinbase)],
+            "poseidonCodeHash": format!("{:?}", hash_code_poseidon(&code)),
+            "byteCode": format!("0x{}", hex::encode(&code)),
+            "structLogs": struct_logs,
+        }));
+        tx_traces.push(tx_trace);
+    }
+
+    let mut zk_state = ZkState::from_accounts(&pre)?;
+    let storage_trace = zk_state.storage_trace(&touched, &deleted, &state)?;
+    let withdraw_trie_root = state
+        .get(&address(L2_MESSAGE_QUEUE)?)
+        .and_then(|queue| queue.storage.get(&U256::ZERO))
+        .copied()
+        .unwrap_or_default();
+    let start_l1_queue_index = tx_traces
+        .iter()
+        .find(|tx| tx["type"] == json!(L1_MESSAGE_TX_TYPE))
+        .map_or(0, |tx| tx["nonce"].as_u64().unwrap_or_default());
+
+    let mut header = block.clone();
+    if let Some(header) = header.as_object_mut() {
+        header.remove("transactions");
+    }
+    Ok(json!({
+        "chainID": chain_id,
+        "version": format!("{EXPECTED_TRACE_VERSION}.0-assembled"),
+        "coinbase": account_wrapper(coinbase, &pre.get(&coinbase).cloned().unwrap_or_default()),
+        "header": header,
+        "transactions": tx_traces,
+        "storageTrace": storage_trace,
+        "txStorageTraces": [],
+        "executionResults": results,
+        "withdraw_trie_root": format!("0x{}", hex::encode(withdraw_trie_root.to_be_bytes::<32>())),
+        "startL1QueueIndex": start_l1_queue_index,
+    }))
+}
+
+/// Results of a file holding one tracer result or a list of them, each
+/// possibly wrapped in `{"result": ...}`.
+fn results(json: Value) -> Vec<Value> {
+    let unwrap = |mut value: Value| {
+        if let Some(result) = value.get_mut("result") {
+            value = result.take();
+        }
+        value
+    };
+    match unwrap(json) {
+        Value::Array(items) => items.into_iter().map(unwrap).collect(),
+        value => vec![value],
+    }
+}
+
+/// Accounts of a `prestateTracer` result or of its `post` in diff mode.
+fn accounts(json: &Value) -> anyhow::Result<Vec<(B160, AccountState, &Value)>> {
+    let Some(accounts) = json.as_object() else {
+        anyhow::bail!("prestate is not an object of accounts");
+    };
+    accounts
+        .iter()
+        .map(|(account_address, account)| {
+            let mut state = AccountState {
+                balance: number(&account["balance"])?,
+                nonce: small(&account["nonce"])?,
+                code: hex::decode(
+                    account["code"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .trim_start_matches("0x"),
+                )?,
+                ..Default::default()
+            };
+            if let Some(storage) = account.get("storage").and_then(Value::as_object) {
+                for (slot, value) in storage {
+                    state.storage.insert(quantity(slot)?, number(value)?);
+                }
+            }
+            Ok((address(account_address)?, state, account))
+        })
+        .collect()
+}
+
+/// Apply a diff mode prestate result to `state`. Accounts of `pre` missing
+/// from `post` were deleted and are added to `deleted`, slots of `pre`
+/// missing from `post` were cleared and are set to zero, geth leaving zero
+/// values out.
+fn apply_diff(
+    state: &mut BTreeMap<B160, AccountState>,
+    deleted: &mut BTreeSet<B160>,
+    prestate: &Value,
+    post: &Value,
+) -> anyhow::Result<()> {
+    let post = accounts(post)?;
+    if let Some(pre) = prestate.get("pre") {
+        for (address, account, _) in accounts(pre)? {
+            match post
+                .iter()
+                .find(|(post_address, ..)| *post_address == address)
+            {
+                None => {
+                    state.remove(&address);
+                    deleted.insert(address);
+                }
+                Some((_, post_account, _)) => {
+                    let storage = &mut state.entry(address).or_default().storage;
+                    for slot in account.storage.keys() {
+                        if !post_account.storage.contains_key(slot) {
+                            storage.insert(*slot, U256::ZERO);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    for (address, account, json) in post {
+        overlay(state.entry(address).or_default(), json, account);
+    }
+    Ok(())
+}
+
+/// Apply the fields a diff mode `post` account sets.
+fn overlay(account: &mut AccountState, json: &Value, post: AccountState) {
+    if json.get("balance").is_some() {
+        account.balance = post.balance;
+    }
+    if json.get("nonce").is_some() {
+        account.nonce = post.nonce;
+    }
+    if json.get("code").is_some() {
+        account.code = post.code;
+    }
+    account.storage.extend(post.storage);
+}
+
+/// Transaction in the block trace format.
+fn transaction(tx: &Value, chain_id: u64) -> anyhow::Result<Value> {
+    let tx_type = small(&tx["type"])?;
+    let mut trace = json!({
+        "type": tx_type,
+        "nonce": small(&tx["nonce"])?,
+        "txHash": tx["hash"],
+        "gas": small(&tx["gas"])?,
+        "gasPrice": tx["gasPrice"],
+        "from": tx["from"],
+        "to": tx["to"],
+        "chainId": tx.get("chainId").cloned().unwrap_or_else(|| json!(format!("{chain_id:#x}"))),
+        "value": tx["value"],
+        "data": tx["input"],
+        "isCreate": tx["to"].is_null(),
+        "v": tx["v"],
+        "r": tx["r"],
+        "s": tx["s"],
+    });
+    for (field, key) in [
+        ("gasTipCap", "maxPriorityFeePerGas"),
+        ("gasFeeCap", "maxFeePerGas"),
+        ("accessList", "accessList"),
+    ] {
+        if let Some(value) = tx.get(key) {
+            trace[field] = value.clone();
+        }
+    }
+    if tx_type == L1_MESSAGE_TX_TYPE {
+        // The queue index of an L1 message is its nonce.
+        if let Some(index) = tx.get("queueIndex") {
+            trace["nonce"] = json!(small(index)?);
+        }
+    }
+    Ok(trace)
+}
+
+/// Struct logs, with the `extraData` l2geth attaches to calls and code
+/// reads: the code and account touched, as before the transaction.
+fn struct_logs(
+    trace: &Value,
+    to: Option<B160>,
+    state: &BTreeMap<B160, AccountState>,
+) -> anyhow::Result<Vec<Value>> {
+    let mut logs = trace["structLogs"]
+        .as_array()
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("trace without structLogs"))?;
+    let account = |address: B160| {
+        let account = state.get(&address).cloned().unwrap_or_default();
+        (account_wrapper(address, &account), account.code)
+    };
+    let contexts = storage_contexts(&logs, to)?;
+    for (log, caller) in logs.iter_mut().zip(contexts) {
+        let target = match log["op"].as_str().unwrap_or_default() {
+            "CALL" | "CALLCODE" | "DELEGATECALL" | "STATICCALL" => stack_address(log, 1)?,
+            "EXTCODESIZE" | "EXTCODEHASH" | "EXTCODECOPY" => stack_address(log, 0)?,
+            _ => continue,
+        };
+        let (wrapper, code) = account(target);
+        let mut extra = json!({
+            "codeList": [format!("0x{}", hex::encode(code))],
+            "proofList": [wrapper],
+        });
+        if let Some(caller) = caller {
+            extra["caller"] = json!([account(caller).0]);
+        }
+        log["extraData"] = extra;
+    }
+    Ok(logs)
+}
+
+/// Storage write: address, slot and value.
+type Write = (B160, U256, U256);
+
+/// `(address, slot, value)` of every `SSTORE` in `logs` that persists, in
+/// order: writes of frames ending with `REVERT` or an error are dropped.
+fn storage_writes(logs: &[Value], to: Option<B160>) -> anyhow::Result<Vec<Write>> {
+    // Writes and last step of each live frame, outermost first.
+    let mut frames = vec![(vec![], None)];
+    let mut persisted = vec![];
+    for (log, context) in logs.iter().zip(storage_contexts(logs, to)?) {
+        let depth = log["depth"].as_u64().unwrap_or(1).max(1) as usize;
+        while frames.len() > depth {
+            close_frame(&mut frames, &mut persisted);
+        }
+        while frames.len() < depth {
+            frames.push((vec![], None));
+        }
+        let (writes, last) = frames.last_mut().expect("a live frame");
+        *last = Some(log);
+        if let (Some("SSTORE"), Some(context)) = (log["op"].as_str(), context) {
+            writes.push((context, stack_item(log, 0)?, stack_item(log, 1)?));
+        }
+    }
+    while !frames.is_empty() {
+        close_frame(&mut frames, &mut persisted);
+    }
+    Ok(persisted)
+}
+
+/// Pop the innermost frame, handing its writes to its caller, or to
+/// `persisted` for the outermost one, unless its last step reverted.
+fn close_frame(frames: &mut Vec<(Vec<Write>, Option<&Value>)>, persisted: &mut Vec<Write>) {
+    let Some((writes, last)) = frames.pop() else {
+        return;
+    };
+    let reverted = last.map_or(false, |last| {
+        last["op"] == "REVERT" || last.get("error").map_or(false, |e| !e.is_null())
+    });
+    if reverted {
+        return;
+    }
+    match frames.last_mut() {
+        Some((caller, _)) => caller.extend(writes),
+        None => persisted.extend(writes),
+    }
+}
+
+/// Account whose storage each step works on, `None` in code being deployed.
+fn storage_contexts(logs: &[Value], to: Option<B160>) -> anyhow::Result<Vec<Option<B160>>> {
+    let mut frames = vec![to];
+    let mut contexts = Vec::with_capacity(logs.len());
+    for log in logs {
+        let depth = log["depth"].as_u64().unwrap_or(1).max(1) as usize;
+        // Frames entered by calls that ran no code never see a step.
+        frames.truncate(depth);
+        let context = frames.last().copied().flatten();
+        contexts.push(context);
+        match log["op"].as_str().unwrap_or_default() {
+            "CALL" | "STATICCALL" => frames.push(Some(stack_address(log, 1)?)),
+            "DELEGATECALL" | "CALLCODE" => frames.push(context),
+            "CREATE" | "CREATE2" => frames.push(None),
+            _ => {}
+        }
+    }
+    Ok(contexts)
+}
+
+/// Stack item `n` of a step, counted from the top.
+fn stack_item(log: &Value, n: usize) -> anyhow::Result<U256> {
+    let stack = log["stack"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let value = stack
+        .len()
+        .checked_sub(n + 1)
+        .map(|i| &stack[i])
+        .ok_or_else(|| anyhow::anyhow!("stack underflow at {log}"))?;
+    number(value)
+}
+
+fn stack_address(log: &Value, n: usize) -> anyhow::Result<B160> {
+    Ok(B160::from_slice(
+        &stack_item(log, n)?.to_be_bytes::<32>()[12..],
+    ))
+}
+
+/// Fee for posting the signed transaction `rlp` to L1, from the gas price
+/// oracle's base fee, overhead and scalar.
+fn l1_fee(state: &BTreeMap<B160, AccountState>, rlp: &[u8]) -> anyhow::Result<U256> {
+    let oracle = state.get(&address(L1_GAS_PRICE_ORACLE)?);
+    let slot = |index: u64| {
+        oracle
+            .and_then(|oracle| oracle.storage.get(&U256::from(index)))
+            .copied()
+            .unwrap_or_default()
+    };
+    let (l1_base_fee, overhead, scalar) = (slot(1), slot(2), slot(3));
+    let zeros = rlp.iter().filter(|byte| **byte == 0).count() as u64;
+    let data_gas = U256::from(zeros * 4 + (rlp.len() as u64 - zeros) * 16);
+    Ok((data_gas + overhead) * l1_base_fee * scalar / U256::from(L1_FEE_PRECISION))
+}
+
+/// EIP-2718 encoding of a signed legacy, EIP-2930 or EIP-1559 transaction.
+fn signed_rlp(tx: &Value, chain_id: u64) -> anyhow::Result<Vec<u8>> {
+    let field = |key: &str| -> anyhow::Result<Vec<u8>> { Ok(u256_bytes(&number(&tx[key])?)) };
+    let data = hex::decode(
+        tx["input"]
+            .as_str()
+            .unwrap_or_default()
+            .trim_start_matches("0x"),
+    )?;
+    let to = match tx["to"].as_str() {
+        Some(to) => address(to)?.as_bytes().to_vec(),
+        None => vec![],
+    };
+    let mut stream = RlpStream::new();
+    let prefix = match small(&tx["type"])? {
+        0 => {
+            stream.begin_list(9);
+            stream.append(&field("nonce")?).append(&field("gasPrice")?);
+            None
+        }
+        1 => {
+            stream.begin_list(11);
+            stream
+                .append(&u256_bytes(&U256::from(chain_id)))
+                .append(&field("nonce")?)
+                .append(&field("gasPrice")?);
+            Some(1u8)
+        }
+        2 => {
+            stream.begin_list(12);
+            stream
+                .append(&u256_bytes(&U256::from(chain_id)))
+                .append(&field("nonce")?)
+                .append(&field("maxPriorityFeePerGas")?)
+                .append(&field("maxFeePerGas")?);
+            Some(2u8)
+        }
+        tx_type => anyhow::bail!("unsupported transaction type {tx_type}"),
+    };
+    stream
+        .append(&field("gas")?)
+        .append(&to)
+        .append(&field("value")?)
+        .append(&data);
+    if prefix.is_some() {
+        let access_list = tx["accessList"].as_array().cloned().unwrap_or_default();
+        stream.begin_list(access_list.len());
+        for item in &access_list {
+            let keys = item["storageKeys"].as_array().cloned().unwrap_or_default();
+            stream.begin_list(2);
+            stream.append(
+                &address(item["address"].as_str().unwrap_or_default())?
+                    .as_bytes()
+                    .to_vec(),
+            );
+            stream.begin_list(keys.len());
+            for key in &keys {
+                stream.append(&number(key)?.to_be_bytes::<32>().to_vec());
+            }
+        }
+    }
+    stream
+        .append(&field(
+            if prefix.is_some() && tx.get("yParity").is_some() {
+                "yParity"
+            } else {
+                "v"
+            },
+        )?)
+        .append(&field("r")?)
+        .append(&field("s")?);
+    Ok(prefix.into_iter().chain(stream.out().to_vec()).collect())
+}
+
+fn account_wrapper(address: B160, account: &AccountState) -> Value {
+    json!({
+        "address": format!("{address:?}"),
+        "nonce": account.nonce,
+        "balance": format!("{:#x}", account.balance),
+        "keccakCodeHash": format!("{:?}", keccak256(&account.code)),
+        "poseidonCodeHash": format!("{:?}", hash_code_poseidon(&account.code)),
+        "codeSize": account.code.len(),
+    })
+}
+
+/// A JSON number or hex quantity, zero when absent.
+fn number(value: &Value) -> anyhow::Result<U256> {
+    match value {
+        Value::Null => Ok(U256::ZERO),
+        Value::Number(number) => number
+            .as_u64()
+            .map(U256::from)
+            .ok_or_else(|| anyhow::anyhow!("not a quantity: {number}")),
+        Value::String(hex) => quantity(hex),
+        value => anyhow::bail!("not a quantity: {value}"),
+    }
+}
+
+fn small(value: &Value) -> anyhow::Result<u64> {
+    Ok(u64::try_from(number(value)?)?)
+}
+
+fn address(address: &str) -> anyhow::Result<B160> {
+    Ok(B160::from_str(address)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::{validate_trace, Expectations};
+
+    #[test]
+    fn assemble_calculation_block() {
+        let trace = assemble_trace_files(
+            "data/geth/block.json",
+            "data/geth/traces.json",
+            "data/geth/prestate.json",
+            None,
+        )
+        .unwrap();
+
+        let problems = validate_trace(&trace, &Expectations::default());
+        assert!(problems.is_empty(), "{problems:?}");
+        let trace = &trace["result"];
+        assert_eq!(trace["chainID"], json!(1337));
+        assert_eq!(trace["transactions"][0]["gas"], json!(100_000));
+        // The sender's nonce and the contract's storage are the only changes.
+        assert_eq!(
+            trace["executionResults"][0]["accountAfter"][0]["nonce"],
+            json!(2)
+        );
+        assert_ne!(
+            trace["storageTrace"]["rootBefore"],
+            trace["storageTrace"]["rootAfter"]
+        );
+    }
+
+    #[test]
+    fn diff_mode_clears_slots_and_deletes_accounts() {
+        let (token, destroyed) = (B160::from_low_u64_be(0x100), B160::from_low_u64_be(0x200));
+        let account = |slots: &[(u64, u64)]| AccountState {
+            nonce: 1,
+            storage: slots
+                .iter()
+                .map(|(slot, value)| (U256::from(*slot), U256::from(*value)))
+                .collect(),
+            ..Default::default()
+        };
+        let pre = BTreeMap::from([
+            (token, account(&[(0, 5), (1, 6), (2, 7)])),
+            (destroyed, account(&[(3, 9)])),
+        ]);
+        let mut state = pre.clone();
+        let mut deleted = BTreeSet::new();
+        let prestate = json!({
+            "pre": {
+                "0x0000000000000000000000000000000000000100": {
+                    "nonce": 1,
+                    "storage": { "0x0": "0x5", "0x1": "0x6" },
+                },
+                "0x0000000000000000000000000000000000000200": {
+                    "nonce": 1,
+                    "storage": { "0x3": "0x9" },
+                },
+            },
+            "post": {
+                "0x0000000000000000000000000000000000000100": {
+                    "storage": { "0x0": "0x8" },
+                },
+            },
+        });
+
+        apply_diff(&mut state, &mut deleted, &prestate, &prestate["post"]).unwrap();
+        assert_eq!(
+            state,
+            BTreeMap::from([(token, account(&[(0, 8), (1, 0), (2, 7)]))])
+        );
+        assert_eq!(deleted, BTreeSet::from([destroyed]));
+
+        let storage_trace = ZkState::from_accounts(&pre)
+            .unwrap()
+            .storage_trace(&BTreeMap::new(), &deleted, &state)
+            .unwrap();
+        let expected = BTreeMap::from([(token, account(&[(0, 8), (2, 7)]))]);
+        assert_eq!(
+            storage_trace["rootAfter"],
+            json!(format!(
+                "{:?}",
+                ZkState::from_accounts(&expected).unwrap().root()
+            ))
+        );
+    }
+
+    #[test]
+    fn reverted_frames_write_nothing() {
+        let step = |op: &str, depth: u64, stack: &[&str]| json!({ "op": op, "depth": depth, "stack": stack });
+        let to = B160::from_low_u64_be(0x100);
+        let callee = B160::from_low_u64_be(0x200);
+        let mut logs = vec![
+            // SSTORE pops the slot first, then the value.
+            step("SSTORE", 1, &["0x5", "0x1"]),
+            step(
+                "CALL",
+                1,
+                &["0x0", "0x0", "0x0", "0x0", "0x0", "0x200", "0xffff"],
+            ),
+            step("SSTORE", 2, &["0x6", "0x1"]),
+            step("REVERT", 2, &["0x0", "0x0"]),
+            step(
+                "CALL",
+                1,
+                &["0x0", "0x0", "0x0", "0x0", "0x0", "0x200", "0xffff"],
+            ),
+            step("SSTORE", 2, &["0x7", "0x2"]),
+            step("STOP", 2, &[]),
+            step("SSTORE", 1, &["0x8", "0x3"]),
+            step("STOP", 1, &[]),
+        ];
+        assert_eq!(
+            storage_writes(&logs, Some(to)).unwrap(),
+            [
+                (to, U256::from(1), U256::from(5)),
+                (callee, U256::from(2), U256::from(7)),
+                (to, U256::from(3), U256::from(8)),
+            ]
+        );
+
+        logs.last_mut().unwrap()["error"] = json!("out of gas");
+        assert_eq!(
+            storage_writes(&logs, Some(to)).unwrap(),
+            Vec::<Write>::new()
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! Loading of the block traces of a batch directory, checked to form one
+//! contiguous chain.
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use glob::{glob, Pattern};
+use prover::BlockTrace;
+use thiserror::Error;
+
+use super::trace_file::{load_block_trace, COMPACT_TRACE_EXTENSION};
+
+#[derive(Debug, Error)]
+pub enum BatchError {
+    #[error("Invalid pattern {0}: {1}")]
+    Pattern(String, glob::PatternError),
+    #[error("Listing {0}: {1}")]
+    Listing(String, glob::GlobError),
+    #[error("No block traces in {0}")]
+    Empty(String),
+    #[error("Listed trace {0} does not exist")]
+    MissingListed(String),
+    #[error("Loading {0}: {1}")]
+    Load(String, anyhow::Error),
+    #[error("{0} has no block number")]
+    MissingNumber(String),
+    #[error("Traces do not form a chain:\n{}", display_list(.0))]
+    Discontinuous(Vec<Discontinuity>),
+}
+
+/// Two consecutive traces of a batch that do not follow each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Discontinuity {
+    /// Trace the check failed on.
+    pub path: String,
+    /// Trace before it in the batch.
+    pub previous: String,
+    pub kind: DiscontinuityKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscontinuityKind {
+    /// Same block number as the previous trace.
+    Duplicate(u64),
+    /// Blocks between the previous trace's and this one's are missing.
+    Gap { previous: u64, number: u64 },
+    /// Lower block number than the previous trace, in an explicit order.
+    OutOfOrder { previous: u64, number: u64 },
+    /// `parentHash` is not the previous block's hash.
+    ParentHash { expected: String, found: String },
+    /// Different `chainID` from the first trace.
+    ChainId { expected: u64, found: u64 },
+    /// `storageTrace.rootBefore` is not the previous block's `rootAfter`.
+    StateRoot { expected: String, found: String },
+}
+
+impl fmt::Display for Discontinuity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} after {}: ", self.path, self.previous)?;
+        match &self.kind {
+            DiscontinuityKind::Duplicate(number) => write!(f, "block {number} again"),
+            DiscontinuityKind::Gap { previous, number } => {
+                write!(f, "blocks {} to {} missing", previous + 1, number - 1)
+            }
+            DiscontinuityKind::OutOfOrder { previous, number } => {
+                write!(f, "block {number} comes after block {previous}")
+            }
+            DiscontinuityKind::ParentHash { expected, found } => {
+                write!(f, "parentHash {found}, expected {expected}")
+            }
+            DiscontinuityKind::ChainId { expected, found } => {
+                write!(f, "chainID {found}, expected {expected}")
+            }
+            DiscontinuityKind::StateRoot { expected, found } => {
+                write!(f, "rootBefore {found}, expected rootAfter {expected}")
+            }
+        }
+    }
+}
+
+fn display_list(discontinuities: &[Discontinuity]) -> String {
+    discontinuities
+        .iter()
+        .map(|d| format!("  {d}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// How to pick and order the traces of a batch directory.
+#[derive(Clone, Debug, Default)]
+pub struct BatchOptions {
+    /// Glob patterns, relative to the batch directory, of files to leave out.
+    pub exclude: Vec<String>,
+    /// Files, relative to the batch directory, to load in this order instead
+    /// of every trace sorted by block number.
+    pub order: Option<Vec<String>>,
+}
+
+/// Trace files of `batch_dir`, JSON or compact, in the listed order or
+/// sorted by path.
+pub fn batch_files(batch_dir: &str, options: &BatchOptions) -> Result<Vec<PathBuf>, BatchError> {
+    let exclude = options
+        .exclude
+        .iter()
+        .map(|pattern| Pattern::new(pattern).map_err(|e| BatchError::Pattern(pattern.clone(), e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let excluded = |path: &Path| {
+        let relative = path.strip_prefix(batch_dir).unwrap_or(path);
+        exclude.iter().any(|pattern| pattern.matches_path(relative))
+    };
+
+    let files = match &options.order {
+        Some(order) => order
+            .iter()
+            .map(|name| {
+                let path = Path::new(batch_dir).join(name);
+                if path.is_file() {
+                    Ok(path)
+                } else {
+                    Err(BatchError::MissingListed(path.display().to_string()))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => {
+            let mut files = vec![];
+            for extension in ["json", COMPACT_TRACE_EXTENSION] {
+                let pattern = format!("{batch_dir}/**/*.{extension}");
+                let paths = glob(&pattern).map_err(|e| BatchError::Pattern(pattern, e))?;
+                for path in paths {
+                    files.push(path.map_err(|e| BatchError::Listing(batch_dir.to_string(), e))?);
+                }
+            }
+            files.sort();
+            files
+        }
+    };
+    Ok(files.into_iter().filter(|path| !excluded(path)).collect())
+}
+
+/// Load the traces of `batch_dir`, sorted by block number unless ordered
+/// explicitly, and check that each one continues the chain of the previous.
+pub fn load_batch_traces(
+    batch_dir: &str,
+    options: &BatchOptions,
+) -> Result<(Vec<String>, Vec<BlockTrace>), BatchError> {
+    let files = batch_files(batch_dir, options)?;
+    if files.is_empty() {
+        return Err(BatchError::Empty(batch_dir.to_string()));
+    }
+    log::info!("test batch with {:?}", files);
+    load_trace_chain(files, options.order.is_none())
+}
+
+/// Load the traces of `files`, sorted by block number if `sort`, and check
+/// that each one continues the chain of the previous.
+pub fn load_trace_chain(
+    files: Vec<PathBuf>,
+    sort: bool,
+) -> Result<(Vec<String>, Vec<BlockTrace>), BatchError> {
+    let mut names_and_traces = files
+        .into_iter()
+        .map(|path| {
+            let name = path.display().to_string();
+            let trace = load_block_trace(&path).map_err(|e| BatchError::Load(name.clone(), e))?;
+            let number = trace
+                .header
+                .number
+                .ok_or_else(|| BatchError::MissingNumber(name.clone()))?
+                .as_u64();
+            Ok((name, trace, number))
+        })
+        .collect::<Result<Vec<_>, BatchError>>()?;
+    if sort {
+        names_and_traces.sort_by_key(|(_, _, number)| *number);
+    }
+    log::info!(
+        "sorted: {:?}",
+        names_and_traces
+            .iter()
+            .map(|(f, _, _)| f.clone())
+            .collect::<Vec<String>>()
+    );
+
+    let discontinuities = check_chain(&names_and_traces);
+    if !discontinuities.is_empty() {
+        return Err(BatchError::Discontinuous(discontinuities));
+    }
+    Ok(names_and_traces.into_iter().map(|(f, t, _)| (f, t)).unzip())
+}
+
+fn check_chain(traces: &[(String, BlockTrace, u64)]) -> Vec<Discontinuity> {
+    let Some((_, first, _)) = traces.first() else {
+        return vec![];
+    };
+    let mut discontinuities = vec![];
+    for pair in traces.windows(2) {
+        let [(previous, previous_trace, previous_number), (path, trace, number)] = pair else {
+            unreachable!()
+        };
+        let mut report = |kind| {
+            discontinuities.push(Discontinuity {
+                path: path.clone(),
+                previous: previous.clone(),
+                kind,
+            })
+        };
+        if number == previous_number {
+            report(DiscontinuityKind::Duplicate(*number));
+            continue;
+        }
+        if number < previous_number {
+            report(DiscontinuityKind::OutOfOrder {
+                previous: *previous_number,
+                number: *number,
+            });
+            continue;
+        }
+        if *number != previous_number + 1 {
+            report(DiscontinuityKind::Gap {
+                previous: *previous_number,
+                number: *number,
+            });
+            continue;
+        }
+        if let Some(hash) = previous_trace.header.hash {
+            if trace.header.parent_hash != hash {
+                report(DiscontinuityKind::ParentHash {
+                    expected: format!("{hash:?}"),
+                    found: format!("{:?}", trace.header.parent_hash),
+                });
+            }
+        }
+        if trace.chain_id != first.chain_id {
+            report(DiscontinuityKind::ChainId {
+                expected: first.chain_id,
+                found: trace.chain_id,
+            });
+        }
+        let expected = previous_trace.storage_trace.root_after;
+        if trace.storage_trace.root_before != expected {
+            report(DiscontinuityKind::StateRoot {
+                expected: format!("{expected:?}"),
+                found: format!("{:?}", trace.storage_trace.root_before),
+            });
+        }
+    }
+    discontinuities
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_traces_are_not_a_chain() {
+        let Err(BatchError::Discontinuous(discontinuities)) =
+            load_batch_traces("traces", &BatchOptions::default())
+        else {
+            panic!("blocks 3 and 30 loaded as a chain");
+        };
+        assert_eq!(
+            discontinuities[0].kind,
+            DiscontinuityKind::Gap {
+                previous: 3,
+                number: 30
+            }
+        );
+
+        let options = BatchOptions {
+            exclude: vec!["groth16*".to_string()],
+            ..Default::default()
+        };
+        let (names, traces) = load_batch_traces("traces", &options).unwrap();
+        assert_eq!(names, ["traces/calculation.json"]);
+        assert_eq!(traces.len(), 1);
+    }
+
+    #[test]
+    fn malformed_trace_is_a_load_error() {
+        let dir = std::env::temp_dir().join(format!("malformed-batch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let bare = dir.join("bare.json");
+        let trace = crate::run::read_trace_json("traces/calculation.json").unwrap();
+        std::fs::write(&bare, trace.to_string()).unwrap();
+        let malformed = dir.join("malformed.json");
+        std::fs::write(
+            &malformed,
+            r#"{"jsonrpc": "2.0", "id": 1, "result": {"coinbase": 1}}"#,
+        )
+        .unwrap();
+
+        let (names, _) = load_trace_chain(vec![bare.clone()], true).unwrap();
+        assert_eq!(names, [bare.display().to_string()]);
+        let Err(BatchError::Load(name, _)) = load_trace_chain(vec![bare, malformed.clone()], true)
+        else {
+            panic!("malformed trace loaded");
+        };
+        assert_eq!(name, malformed.display().to_string());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+//! Splitting of a batch into the largest chunks that fit the chunk circuit.
+use std::{fs::File, io::BufWriter, ops::Range, path::Path};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::dry_run::row_usage::SubCircuitRows;
+
+/// File the chunk index is written to in the output directory.
+pub const CHUNK_INDEX_FILE: &str = "chunks.json";
+
+#[derive(Debug, Error)]
+pub enum ChunkError {
+    #[error("Row usage of the chunk ending with {0}: {1}")]
+    RowUsage(String, anyhow::Error),
+    #[error("{block} alone needs {rows} {circuit} rows, over the chunk capacity of {capacity}")]
+    Overflow {
+        block: String,
+        circuit: String,
+        rows: usize,
+        capacity: usize,
+    },
+}
+
+/// One proved chunk of the chunk index.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkEntry {
+    pub index: usize,
+    pub first_block: Option<u64>,
+    pub last_block: Option<u64>,
+    pub traces: Vec<String>,
+    /// Chunk proof, relative to the output directory. None for mock proofs.
+    pub proof: Option<String>,
+}
+
+/// Split `items`, named by `names`, into consecutive ranges as long as
+/// `measure` finds every sub-circuit of the range within `capacity` rows.
+///
+/// Row usage is not additive across blocks (bytecodes and keccak inputs are
+/// shared), so each candidate range is measured as a whole. It only grows
+/// with the range though: each chunk's length doubles until it overflows and
+/// is then binary searched, measuring O(log n) ranges per chunk.
+pub fn split_chunks<T>(
+    names: &[String],
+    items: &[T],
+    capacity: usize,
+    mut measure: impl FnMut(&[T]) -> anyhow::Result<Vec<SubCircuitRows>>,
+) -> Result<Vec<Range<usize>>, ChunkError> {
+    // First sub-circuit over the capacity with the blocks of `range`.
+    let mut overflow = |range: Range<usize>| {
+        let last = names[range.end - 1].clone();
+        let usage = measure(&items[range]).map_err(|e| ChunkError::RowUsage(last, e))?;
+        Ok::<_, ChunkError>(usage.into_iter().find(|u| u.rows > capacity))
+    };
+
+    let mut chunks = vec![];
+    let mut start = 0;
+    while start < items.len() {
+        if let Some(usage) = overflow(start..start + 1)? {
+            return Err(ChunkError::Overflow {
+                block: names[start].clone(),
+                circuit: usage.name,
+                rows: usage.rows,
+                capacity,
+            });
+        }
+        // Lengths known to fit and to overflow.
+        let remaining = items.len() - start;
+        let (mut fits, mut overflows) = (1, remaining + 1);
+        while fits < remaining {
+            let len = (fits * 2).min(remaining);
+            if overflow(start..start + len)?.is_some() {
+                overflows = len;
+                break;
+            }
+            fits = len;
+        }
+        while overflows - fits > 1 {
+            let len = (fits + overflows) / 2;
+            if overflow(start..start + len)?.is_some() {
+                overflows = len;
+            } else {
+                fits = len;
+            }
+        }
+        chunks.push(start..start + fits);
+        start += fits;
+    }
+    Ok(chunks)
+}
+
+/// Write the chunk index to `CHUNK_INDEX_FILE` in `output_dir`.
+pub fn write_chunk_index(
+    output_dir: impl AsRef<Path>,
+    chunks: &[ChunkEntry],
+) -> anyhow::Result<()> {
+    let path = output_dir.as_ref().join(CHUNK_INDEX_FILE);
+    serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), chunks)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evm_rows(items: &[usize]) -> anyhow::Result<Vec<SubCircuitRows>> {
+        Ok(vec![SubCircuitRows {
+            name: "evm".to_string(),
+            rows: items.iter().sum(),
+        }])
+    }
+
+    #[test]
+    fn splits_into_maximal_chunks() {
+        let names: Vec<String> = (0..5).map(|i| format!("block{i}")).collect();
+        let chunks = split_chunks(&names, &[4, 5, 3, 8, 2], 10, evm_rows).unwrap();
+        assert_eq!(chunks, [0..2, 2..3, 3..5]);
+
+        let Err(ChunkError::Overflow { block, rows, .. }) =
+            split_chunks(&names[..2], &[4, 11], 10, evm_rows)
+        else {
+            panic!("block over the capacity was chunked");
+        };
+        assert_eq!((block.as_str(), rows), ("block1", 11));
+    }
+
+    #[test]
+    fn measures_logarithmically_many_ranges() {
+        let names: Vec<String> = (0..256).map(|i| format!("block{i}")).collect();
+        let mut measured = 0;
+        let chunks = split_chunks(&names, &[1; 256], 100, |items| {
+            measured += items.len();
+            evm_rows(items)
+        })
+        .unwrap();
+        assert_eq!(chunks, [0..100, 100..200, 200..256]);
+        // Measuring every prefix of the chunks would take 11898 blocks.
+        assert_eq!(measured, 1859);
+    }
+}
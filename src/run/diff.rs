@@ -0,0 +1,571 @@
+//! Semantic comparison of two block traces, such as one regenerated from a
+//! dry run against the one l2geth produced for the same block.
+//!
+//! Quantities compare by value whether written as JSON numbers or hex
+//! strings, other hex strings byte for byte ignoring case, and objects
+//! compare by key, so only differences the prover would see are reported.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Write},
+};
+
+use revm_primitives::U256;
+use serde_json::Value;
+
+use crate::dry_run::{replay::zktrie_leaf, session::quantity};
+
+/// A value differing between the two traces.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Difference {
+    /// JSON path of the value, such as `$.header.gasLimit`.
+    pub path: String,
+    /// Value in the left trace, `missing` when absent.
+    pub left: String,
+    pub right: String,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\n  - {}\n  + {}",
+            self.path,
+            shorten(&self.left),
+            shorten(&self.right)
+        )
+    }
+}
+
+/// First step of a transaction where the `structLogs` of the two traces
+/// disagree.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepDivergence {
+    pub tx: usize,
+    /// Index in `structLogs`.
+    pub step: usize,
+    pub pc: Option<u64>,
+    pub op: Option<String>,
+    /// Fields of the step that differ, or `structLogs.length` when one
+    /// trace has more steps.
+    pub differences: Vec<Difference>,
+}
+
+impl fmt::Display for StepDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "tx {} step {}", self.tx, self.step)?;
+        if let (Some(pc), Some(op)) = (self.pc, &self.op) {
+            write!(f, " (pc {pc}, {op})")?;
+        }
+        for difference in &self.differences {
+            write!(f, "\n  {difference}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Differences between two block traces, by part of the trace.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceDiff {
+    /// Header fields and the top level fields besides the header.
+    pub header: Vec<Difference>,
+    /// Transactions and the execution results outside their steps.
+    pub transactions: Vec<Difference>,
+    pub steps: Vec<StepDivergence>,
+    /// `storageTrace` roots and proofs.
+    pub storage: Vec<Difference>,
+}
+
+impl TraceDiff {
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty()
+            && self.transactions.is_empty()
+            && self.steps.is_empty()
+            && self.storage.is_empty()
+    }
+}
+
+/// Fields of execution results compared as a whole rather than per step.
+const RESULT_FIELDS: &[&str] = &[
+    "l1DataFee",
+    "gas",
+    "failed",
+    "returnValue",
+    "from",
+    "to",
+    "accountAfter",
+    "poseidonCodeHash",
+    "byteCode",
+];
+/// Fields of `structLogs` entries, the ones the circuits read.
+const STEP_FIELDS: &[&str] = &[
+    "pc",
+    "op",
+    "gas",
+    "gasCost",
+    "depth",
+    "stack",
+    "memory",
+    "storage",
+    "extraData",
+];
+
+/// Fields holding quantities, compared by value. The `stack` words and the
+/// slots and values of `storage` are quantities too.
+const QUANTITY_FIELDS: &[&str] = &[
+    "number",
+    "gasLimit",
+    "gasUsed",
+    "timestamp",
+    "baseFeePerGas",
+    "difficulty",
+    "chainID",
+    "chainId",
+    "type",
+    "nonce",
+    "gas",
+    "gasPrice",
+    "gasTipCap",
+    "gasFeeCap",
+    "value",
+    "balance",
+    "l1DataFee",
+    "startL1QueueIndex",
+    "pc",
+    "gasCost",
+    "depth",
+    "refund",
+    "stack",
+    "storage",
+];
+
+/// Compare two block traces without their JSON-RPC wrapping.
+pub fn diff_traces(left: &Value, right: &Value) -> TraceDiff {
+    let mut diff = TraceDiff::default();
+
+    for key in keys(left, right) {
+        match key.as_str() {
+            "header" => compare(
+                &mut diff.header,
+                "$.header",
+                "header",
+                &left[&key],
+                &right[&key],
+            ),
+            "transactions" | "executionResults" | "storageTrace" | "txStorageTraces" => {}
+            _ => compare(
+                &mut diff.header,
+                &format!("$.{key}"),
+                &key,
+                &left[&key],
+                &right[&key],
+            ),
+        }
+    }
+
+    compare(
+        &mut diff.transactions,
+        "$.transactions",
+        "transactions",
+        &left["transactions"],
+        &right["transactions"],
+    );
+    let empty = vec![];
+    let left_results = left["executionResults"].as_array().unwrap_or(&empty);
+    let right_results = right["executionResults"].as_array().unwrap_or(&empty);
+    if left_results.len() != right_results.len() {
+        diff.transactions.push(Difference {
+            path: "$.executionResults.length".to_string(),
+            left: left_results.len().to_string(),
+            right: right_results.len().to_string(),
+        });
+    }
+    for (tx, (left, right)) in left_results.iter().zip(right_results).enumerate() {
+        for field in RESULT_FIELDS {
+            compare(
+                &mut diff.transactions,
+                &format!("$.executionResults[{tx}].{field}"),
+                field,
+                &left[field],
+                &right[field],
+            );
+        }
+        if let Some(divergence) = diverge(tx, &left["structLogs"], &right["structLogs"]) {
+            diff.steps.push(divergence);
+        }
+    }
+
+    diff_storage(
+        &mut diff.storage,
+        &left["storageTrace"],
+        &right["storageTrace"],
+    );
+    diff
+}
+
+/// Report of `diff`, empty when the traces match.
+pub fn diff_report(diff: &TraceDiff) -> String {
+    let mut out = String::new();
+    let mut section = |title: &str, lines: Vec<String>| {
+        if lines.is_empty() {
+            return;
+        }
+        writeln!(out, "{title} ({}):", lines.len()).unwrap();
+        for line in lines {
+            writeln!(out, "{line}").unwrap();
+        }
+    };
+    let lines = |differences: &[Difference]| -> Vec<String> {
+        differences.iter().map(ToString::to_string).collect()
+    };
+    section("Header", lines(&diff.header));
+    section("Transactions", lines(&diff.transactions));
+    section(
+        "Steps",
+        diff.steps.iter().map(ToString::to_string).collect(),
+    );
+    section("Storage", lines(&diff.storage));
+    out
+}
+
+/// First step where `left` and `right` struct logs differ.
+fn diverge(tx: usize, left: &Value, right: &Value) -> Option<StepDivergence> {
+    let empty = vec![];
+    let left = left.as_array().unwrap_or(&empty);
+    let right = right.as_array().unwrap_or(&empty);
+    for (step, (left_log, right_log)) in left.iter().zip(right).enumerate() {
+        let mut differences = vec![];
+        for field in STEP_FIELDS {
+            compare(
+                &mut differences,
+                field,
+                field,
+                &left_log[field],
+                &right_log[field],
+            );
+        }
+        if !differences.is_empty() {
+            return Some(StepDivergence {
+                tx,
+                step,
+                pc: left_log["pc"].as_u64(),
+                op: left_log["op"].as_str().map(String::from),
+                differences,
+            });
+        }
+    }
+    if left.len() == right.len() {
+        return None;
+    }
+    let step = left.len().min(right.len());
+    let extra = left.get(step).or_else(|| right.get(step))?;
+    Some(StepDivergence {
+        tx,
+        step,
+        pc: extra["pc"].as_u64(),
+        op: extra["op"].as_str().map(String::from),
+        differences: vec![Difference {
+            path: "structLogs.length".to_string(),
+            left: left.len().to_string(),
+            right: right.len().to_string(),
+        }],
+    })
+}
+
+/// Roots, then account and storage proofs, each reported with the leaf it
+/// proves.
+fn diff_storage(out: &mut Vec<Difference>, left: &Value, right: &Value) {
+    for root in ["rootBefore", "rootAfter"] {
+        compare(
+            out,
+            &format!("$.storageTrace.{root}"),
+            root,
+            &left[root],
+            &right[root],
+        );
+    }
+
+    let left_proofs = by_lowercase_key(&left["proofs"]);
+    let right_proofs = by_lowercase_key(&right["proofs"]);
+    for address in union(&left_proofs, &right_proofs) {
+        let key = hex::decode(address.trim_start_matches("0x")).unwrap_or_default();
+        compare_proofs(
+            out,
+            &format!("$.storageTrace.proofs.{address}"),
+            left_proofs.get(&address).copied(),
+            right_proofs.get(&address).copied(),
+            &key,
+        );
+    }
+
+    let left_storage = by_lowercase_key(&left["storageProofs"]);
+    let right_storage = by_lowercase_key(&right["storageProofs"]);
+    for address in union(&left_storage, &right_storage) {
+        let left_slots = left_storage
+            .get(&address)
+            .copied()
+            .map(by_slot)
+            .unwrap_or_default();
+        let right_slots = right_storage
+            .get(&address)
+            .copied()
+            .map(by_slot)
+            .unwrap_or_default();
+        for slot in union(&left_slots, &right_slots) {
+            let key = quantity(&slot)
+                .map(|slot| slot.to_be_bytes::<32>().to_vec())
+                .unwrap_or_default();
+            compare_proofs(
+                out,
+                &format!("$.storageTrace.storageProofs.{address}.{slot}"),
+                left_slots.get(&slot).copied(),
+                right_slots.get(&slot).copied(),
+                &key,
+            );
+        }
+    }
+}
+
+fn compare_proofs(
+    out: &mut Vec<Difference>,
+    path: &str,
+    left: Option<&Value>,
+    right: Option<&Value>,
+    key: &[u8],
+) {
+    let nodes = |proof: Option<&Value>| -> Option<Vec<String>> {
+        proof.map(|proof| {
+            proof
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(|node| node.trim_start_matches("0x").to_lowercase())
+                .collect()
+        })
+    };
+    let (left, right) = (nodes(left), nodes(right));
+    if left == right {
+        return;
+    }
+    let describe = |nodes: &Option<Vec<String>>| match nodes {
+        None => "missing".to_string(),
+        Some(nodes) => match zktrie_leaf(nodes, key) {
+            Ok(Some(leaf)) => format!(
+                "leaf [{}] ({} nodes)",
+                leaf.iter()
+                    .map(|word| format!("0x{}", hex::encode(word)))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                nodes.len()
+            ),
+            Ok(None) => format!("absent ({} nodes)", nodes.len()),
+            Err(e) => format!("undecodable: {e}"),
+        },
+    };
+    out.push(Difference {
+        path: path.to_string(),
+        left: describe(&left),
+        right: describe(&right),
+    });
+}
+
+/// Compare `left` and `right`, values of `field`, at `path`, descending
+/// into objects and arrays so that the innermost differing values are
+/// reported.
+fn compare(out: &mut Vec<Difference>, path: &str, field: &str, left: &Value, right: &Value) {
+    if same(field, left, right) {
+        return;
+    }
+    match (left, right) {
+        (Value::Object(_), Value::Object(_)) => {
+            for key in keys(left, right) {
+                compare(
+                    out,
+                    &format!("{path}.{key}"),
+                    child_field(field, &key),
+                    &left[&key],
+                    &right[&key],
+                );
+            }
+        }
+        (Value::Array(l), Value::Array(r)) if l.len() == r.len() => {
+            for (i, (left, right)) in l.iter().zip(r).enumerate() {
+                compare(out, &format!("{path}[{i}]"), field, left, right);
+            }
+        }
+        (Value::Array(l), Value::Array(r)) => out.push(Difference {
+            path: format!("{path}.length"),
+            left: l.len().to_string(),
+            right: r.len().to_string(),
+        }),
+        _ => out.push(Difference {
+            path: path.to_string(),
+            left: render(left),
+            right: render(right),
+        }),
+    }
+}
+
+/// Whether two values of `field` are the same to the prover.
+fn same(field: &str, left: &Value, right: &Value) -> bool {
+    let quantities = QUANTITY_FIELDS.contains(&field);
+    match (left, right) {
+        (Value::String(l), Value::String(r)) if quantities => match (quantity(l), quantity(r)) {
+            (Ok(l), Ok(r)) => l == r,
+            _ => l.eq_ignore_ascii_case(r),
+        },
+        (Value::String(l), Value::String(r)) => l.eq_ignore_ascii_case(r),
+        (Value::Number(_), Value::String(s)) | (Value::String(s), Value::Number(_)) => {
+            let number = if left.is_number() { left } else { right };
+            match (number.as_u64(), quantity(s)) {
+                (Some(n), Ok(q)) => q == U256::from(n),
+                _ => false,
+            }
+        }
+        (Value::Object(l), Value::Object(r)) => {
+            // Storage keyed by slot, however the slots are written.
+            if field == "storage" {
+                if let (Some(l), Some(r)) = (slots(l), slots(r)) {
+                    return l.len() == r.len()
+                        && l.iter()
+                            .zip(&r)
+                            .all(|((ls, lv), (rs, rv))| ls == rs && same(field, lv, rv));
+                }
+            }
+            keys(left, right).iter().all(|key| {
+                same(
+                    child_field(field, key),
+                    l.get(key).unwrap_or(&Value::Null),
+                    r.get(key).unwrap_or(&Value::Null),
+                )
+            })
+        }
+        (Value::Array(l), Value::Array(r)) => {
+            l.len() == r.len() && l.iter().zip(r).all(|(l, r)| same(field, l, r))
+        }
+        _ => left == right,
+    }
+}
+
+/// Values of a storage object by slot, `None` when a key is not a slot.
+fn slots(object: &serde_json::Map<String, Value>) -> Option<BTreeMap<U256, &Value>> {
+    object
+        .iter()
+        .map(|(slot, value)| Some((quantity(slot).ok()?, value)))
+        .collect()
+}
+
+/// Field of the value at `key` of an object of `field`: the storage slots
+/// keep being storage.
+fn child_field<'a>(field: &'a str, key: &'a str) -> &'a str {
+    if field == "storage" {
+        field
+    } else {
+        key
+    }
+}
+
+fn keys(left: &Value, right: &Value) -> BTreeSet<String> {
+    left.as_object()
+        .into_iter()
+        .chain(right.as_object())
+        .flat_map(|object| object.keys().cloned())
+        .collect()
+}
+
+fn by_lowercase_key(value: &Value) -> BTreeMap<String, &Value> {
+    value
+        .as_object()
+        .into_iter()
+        .flatten()
+        .map(|(key, value)| (key.to_lowercase(), value))
+        .collect()
+}
+
+/// Slots by canonical hex, as l2geth pads them and other tools may not.
+fn by_slot(value: &Value) -> BTreeMap<String, &Value> {
+    by_lowercase_key(value)
+        .into_iter()
+        .map(|(slot, value)| match quantity(&slot) {
+            Ok(slot) => (
+                format!("0x{}", hex::encode(slot.to_be_bytes::<32>())),
+                value,
+            ),
+            Err(_) => (slot, value),
+        })
+        .collect()
+}
+
+fn union<V>(left: &BTreeMap<String, V>, right: &BTreeMap<String, V>) -> BTreeSet<String> {
+    left.keys().chain(right.keys()).cloned().collect()
+}
+
+fn render(value: &Value) -> String {
+    match value {
+        Value::Null => "missing".to_string(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// `value` cut to a line, long bytecodes and proofs being common.
+fn shorten(value: &str) -> String {
+    const MAX: usize = 140;
+    let length = value.chars().count();
+    if length <= MAX {
+        return value.to_string();
+    }
+    let start: String = value.chars().take(MAX).collect();
+    format!("{start}... ({length} chars)")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::run::read_trace_json;
+
+    #[test]
+    fn reports_each_part() {
+        let trace = read_trace_json("traces/calculation.json").unwrap();
+        assert!(diff_traces(&trace, &trace).is_empty());
+
+        let mut other = trace.clone();
+        // Same values written differently.
+        other["chainID"] = json!("0x539");
+        other["header"]["gasLimit"] = json!(other["header"]["gasLimit"]
+            .as_str()
+            .unwrap()
+            .to_uppercase()
+            .replace("0X", "0x"));
+        other["header"]["number"] = json!("0x0003");
+        assert!(diff_traces(&trace, &other).is_empty());
+
+        // Calldata with a leading zero byte is other calldata.
+        let mut padded = trace.clone();
+        let data = trace["transactions"][0]["data"].as_str().unwrap();
+        padded["transactions"][0]["data"] = json!(data.replace("0x", "0x00"));
+        assert_eq!(
+            diff_traces(&trace, &padded).transactions[0].path,
+            "$.transactions[0].data"
+        );
+
+        other["header"]["timestamp"] = json!("0x1");
+        other["executionResults"][0]["gas"] = json!(1);
+        other["executionResults"][0]["structLogs"][5]["gas"] = json!(7);
+        other["executionResults"][0]["structLogs"][9]["gas"] = json!(7);
+        other["storageTrace"]["rootAfter"] = json!(format!("0x{}", "00".repeat(32)));
+        let diff = diff_traces(&trace, &other);
+
+        assert_eq!(diff.header.len(), 1);
+        assert_eq!(diff.header[0].path, "$.header.timestamp");
+        assert_eq!(diff.transactions[0].path, "$.executionResults[0].gas");
+        // Only the first divergence of a transaction.
+        assert_eq!(diff.steps.len(), 1);
+        assert_eq!(diff.steps[0].step, 5);
+        assert_eq!(diff.steps[0].differences[0].path, "gas");
+        assert_eq!(diff.storage[0].path, "$.storageTrace.rootAfter");
+        assert!(diff_report(&diff).contains("Steps (1):"));
+    }
+}
@@ -0,0 +1,365 @@
+//! Block traces fetched from a Scroll-compatible JSON-RPC endpoint into a
+//! local cache keyed by block hash.
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+/// Default cache directory of fetched traces.
+pub const DEFAULT_TRACE_CACHE: &str = ".trace-cache";
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("HTTP status {0}")]
+    Status(u16),
+    #[error("RPC error {code}: {message}")]
+    Rpc { code: i64, message: String },
+    #[error("Invalid response: {0}")]
+    InvalidResponse(String),
+    #[error("Block {0} not found")]
+    NotFound(u64),
+    #[error("Writing the trace cache: {0}")]
+    Cache(#[from] std::io::Error),
+}
+
+impl FetchError {
+    /// Whether the request may succeed when sent again.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Http(_) => true,
+            Self::Status(status) => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FetchOptions {
+    /// Directory fetched traces are cached in, one `<block hash>.json` each.
+    pub cache_dir: PathBuf,
+    /// Retries of a request failing on the transport or with a 429 or 5xx
+    /// status.
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every next one.
+    pub retry_delay: Duration,
+    /// Look the block hash up with `eth_getBlockByNumber` first, so that
+    /// cached traces are not fetched again.
+    pub block_lookup: bool,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            cache_dir: PathBuf::from(DEFAULT_TRACE_CACHE),
+            retries: 3,
+            retry_delay: Duration::from_millis(500),
+            block_lookup: true,
+        }
+    }
+}
+
+/// Client of `scroll_getBlockTraceByNumberOrHash`.
+pub struct TraceFetcher {
+    client: reqwest::blocking::Client,
+    url: String,
+    options: FetchOptions,
+}
+
+impl TraceFetcher {
+    pub fn new(url: &str, options: FetchOptions) -> Result<Self, FetchError> {
+        Ok(Self {
+            client: reqwest::blocking::Client::builder()
+                .timeout(Duration::from_secs(120))
+                .build()?,
+            url: url.to_string(),
+            options,
+        })
+    }
+
+    /// Cached trace files of the `blocks`, in block order.
+    pub fn fetch_blocks(&self, blocks: RangeInclusive<u64>) -> Result<Vec<PathBuf>, FetchError> {
+        blocks.map(|number| self.fetch_block(number)).collect()
+    }
+
+    /// Cached trace file of block `number`, fetched unless already cached.
+    pub fn fetch_block(&self, number: u64) -> Result<PathBuf, FetchError> {
+        let mut block_id = format!("{number:#x}");
+        if self.options.block_lookup {
+            let block = self.call("eth_getBlockByNumber", json!([block_id, false]))?;
+            if block.is_null() {
+                return Err(FetchError::NotFound(number));
+            }
+            let hash = block_hash(&block)?;
+            let path = self.cache_path(hash);
+            if path.is_file() {
+                log::info!("block {number} cached at {}", path.display());
+                return Ok(path);
+            }
+            block_id = hash.to_string();
+        }
+
+        let trace = self.call("scroll_getBlockTraceByNumberOrHash", json!([block_id]))?;
+        if trace.is_null() {
+            return Err(FetchError::NotFound(number));
+        }
+        let path = self.cache_path(block_hash(&trace["header"])?);
+        write_cached(&path, &trace)?;
+        log::info!("fetched block {number} to {}", path.display());
+        Ok(path)
+    }
+
+    fn cache_path(&self, hash: &str) -> PathBuf {
+        self.options.cache_dir.join(format!("{hash}.json"))
+    }
+
+    /// Result of the JSON-RPC `method` call, retried on transient failures.
+    fn call(&self, method: &str, params: Value) -> Result<Value, FetchError> {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let mut delay = self.options.retry_delay;
+        let mut attempt = 0;
+        loop {
+            match self.send(&request) {
+                Err(e) if e.is_transient() && attempt < self.options.retries => {
+                    log::warn!("{method} failed, retrying in {delay:?}: {e}");
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn send(&self, request: &Value) -> Result<Value, FetchError> {
+        let response = self
+            .client
+            .post(&self.url)
+            .header("content-type", "application/json")
+            .body(request.to_string())
+            .send()?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(FetchError::Status(status.as_u16()));
+        }
+        let mut response: Value = serde_json::from_str(&response.text()?)
+            .map_err(|e| FetchError::InvalidResponse(e.to_string()))?;
+        if let Some(error) = response.get("error") {
+            return Err(FetchError::Rpc {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+        match response.get_mut("result") {
+            Some(result) => Ok(result.take()),
+            None => Err(FetchError::InvalidResponse("no result".to_string())),
+        }
+    }
+}
+
+/// Hash of `block`, checked to be `0x` and 64 hex digits as it names the
+/// cache file.
+fn block_hash(block: &Value) -> Result<&str, FetchError> {
+    let hash = block["hash"]
+        .as_str()
+        .ok_or_else(|| FetchError::InvalidResponse("block without hash".to_string()))?;
+    match hash.strip_prefix("0x") {
+        Some(digits) if digits.len() == 64 && digits.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            Ok(hash)
+        }
+        _ => Err(FetchError::InvalidResponse(format!(
+            "invalid block hash {hash}"
+        ))),
+    }
+}
+
+/// Write `trace` as a JSON-RPC response, as the bundled traces are, through
+/// a temporary file so that an interrupted write leaves no cache entry.
+fn write_cached(path: &Path, trace: &Value) -> Result<(), FetchError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let partial = path.with_extension("json.partial");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    serde_json::to_writer(
+        &mut writer,
+        &json!({ "jsonrpc": "2.0", "id": 1, "result": trace }),
+    )
+    .map_err(std::io::Error::from)?;
+    writer.flush()?;
+    fs::rename(partial, path)?;
+    Ok(())
+}
+
+/// Parse `<from>..<to>`, both included, or a single block number.
+pub fn parse_block_range(range: &str) -> Result<RangeInclusive<u64>, String> {
+    let number = |n: &str| {
+        let n = n.trim();
+        match n.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => n.parse(),
+        }
+        .map_err(|e| format!("invalid block number {n:?}: {e}"))
+    };
+    let (from, to) = match range.split_once("..") {
+        Some((from, to)) => (number(from)?, number(to.trim_start_matches('='))?),
+        None => (number(range)?, number(range)?),
+    };
+    if from > to {
+        return Err(format!("empty block range {range}"));
+    }
+    Ok(from..=to)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        convert::Infallible,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Request, Response, Server, StatusCode,
+    };
+
+    use super::*;
+    use crate::run::load_block_trace;
+
+    /// Scroll node serving the bundled block 3, failing every first request
+    /// with a 503.
+    struct MockNode {
+        trace: Value,
+        requests: AtomicUsize,
+        trace_requests: AtomicUsize,
+    }
+
+    impl MockNode {
+        fn respond(&self, request: &Value) -> Response<Body> {
+            if self.requests.fetch_add(1, Ordering::SeqCst) == 0 {
+                let mut response = Response::new(Body::empty());
+                *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                return response;
+            }
+            let known = |id: &Value| id == "0x3" || *id == self.trace["header"]["hash"];
+            let result = match request["method"].as_str() {
+                Some("eth_getBlockByNumber") if known(&request["params"][0]) => {
+                    self.trace["header"].clone()
+                }
+                Some("scroll_getBlockTraceByNumberOrHash") if known(&request["params"][0]) => {
+                    self.trace_requests.fetch_add(1, Ordering::SeqCst);
+                    self.trace.clone()
+                }
+                _ => Value::Null,
+            };
+            Response::new(Body::from(
+                json!({ "jsonrpc": "2.0", "id": 1, "result": result }).to_string(),
+            ))
+        }
+    }
+
+    fn serve(node: Arc<MockNode>) -> (tokio::runtime::Runtime, SocketAddr) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let addr = {
+            let _guard = runtime.enter();
+            let make_service = make_service_fn(move |_| {
+                let node = node.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                        let node = node.clone();
+                        async move {
+                            let body = hyper::body::to_bytes(request.into_body()).await?;
+                            let request: Value = serde_json::from_slice(&body).unwrap();
+                            Ok::<_, hyper::Error>(node.respond(&request))
+                        }
+                    }))
+                }
+            });
+            let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+            let addr = server.local_addr();
+            runtime.spawn(server);
+            addr
+        };
+        (runtime, addr)
+    }
+
+    #[test]
+    fn fetches_into_cache_with_retries() {
+        let trace: Value =
+            serde_json::from_str(&fs::read_to_string("traces/calculation.json").unwrap()).unwrap();
+        let node = Arc::new(MockNode {
+            trace: trace["result"].clone(),
+            requests: AtomicUsize::new(0),
+            trace_requests: AtomicUsize::new(0),
+        });
+        let (_runtime, addr) = serve(node.clone());
+
+        let cache_dir = std::env::temp_dir().join(format!("trace-cache-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&cache_dir);
+        let fetcher = TraceFetcher::new(
+            &format!("http://{addr}"),
+            FetchOptions {
+                cache_dir: cache_dir.clone(),
+                retry_delay: Duration::from_millis(10),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let paths = fetcher
+            .fetch_blocks(parse_block_range("3..3").unwrap())
+            .unwrap();
+        assert_eq!(
+            paths,
+            [cache_dir
+                .join("0x9a26ba7f89bc89285e45386eb745d62ac424aab13b8a13d40c2e4d5262f1138a.json")]
+        );
+        let block_trace = load_block_trace(&paths[0]).unwrap();
+        assert_eq!(block_trace.header.number.unwrap().as_u64(), 3);
+
+        // Cached now: only the block lookup goes to the node.
+        assert_eq!(fetcher.fetch_block(3).unwrap(), paths[0]);
+        assert_eq!(node.trace_requests.load(Ordering::SeqCst), 1);
+        assert!(matches!(
+            fetcher.fetch_block(4),
+            Err(FetchError::NotFound(4))
+        ));
+        fs::remove_dir_all(cache_dir).unwrap();
+    }
+
+    #[test]
+    fn block_ranges() {
+        assert_eq!(parse_block_range("3..5"), Ok(3..=5));
+        assert_eq!(parse_block_range("0x1e..=0x20"), Ok(30..=32));
+        assert_eq!(parse_block_range("7"), Ok(7..=7));
+        assert!(parse_block_range("5..3").is_err());
+    }
+
+    #[test]
+    fn block_hashes_are_checked() {
+        let hash = format!("0x{}", "ab".repeat(32));
+        assert_eq!(block_hash(&json!({ "hash": hash })).unwrap(), hash);
+        for invalid in [
+            json!({}),
+            json!({ "hash": "0x12" }),
+            json!({ "hash": "../../etc/passwd" }),
+        ] {
+            assert!(matches!(
+                block_hash(&invalid),
+                Err(FetchError::InvalidResponse(_))
+            ));
+        }
+    }
+}
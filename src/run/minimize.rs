@@ -0,0 +1,426 @@
+//! Shrinking of a block trace that fails mock proving into a smaller one
+//! failing the same way, to attach to bug reports against the circuits.
+//!
+//! Transactions are dropped and calldata truncated for as long as the
+//! trace keeps failing. Reduced traces are regenerated by re-executing the
+//! remaining transactions in the dry run and assembling the trace again, so
+//! that execution results and state roots stay consistent. When the dry run
+//! can't reproduce the trace (contract creations, or a regenerated trace no
+//! longer failing), transactions are dropped from the trace as is and
+//! calldata is left alone. Truncating the calldata of a signed transaction
+//! invalidates its signature, such a candidate is only kept when it still
+//! fails the same way.
+use std::collections::BTreeMap;
+
+use revm_primitives::B160;
+use serde_json::{json, Value};
+
+use super::assemble::assemble_block_trace;
+use crate::dry_run::{
+    bytecode_run::RunConfig, replay::rerun_block, rpc::struct_logger_result, trie::AccountState,
+};
+
+#[derive(Clone, Debug, Default)]
+pub struct MinimizeOptions {
+    /// Keep traces as they are instead of regenerating them in the dry run.
+    pub no_regenerate: bool,
+    /// Keep candidates failing in any way, not only with the first line of
+    /// the original failure.
+    pub any_failure: bool,
+    /// Dry run configuration, the hardfork mostly.
+    pub config: RunConfig,
+}
+
+/// Smallest failing trace found.
+#[derive(Clone, Debug)]
+pub struct Minimized {
+    pub trace: Value,
+    pub failure: String,
+    /// Reductions kept, in order.
+    pub steps: Vec<String>,
+    /// Candidates checked, the original trace included.
+    pub attempts: usize,
+}
+
+/// Reduction of the original trace: which transactions remain and the
+/// calldata of those truncated, by index in the original trace.
+#[derive(Clone, Debug)]
+struct Case {
+    transactions: Vec<usize>,
+    calldata: BTreeMap<usize, Vec<u8>>,
+}
+
+struct Minimizer<'a, F> {
+    trace: &'a Value,
+    options: &'a MinimizeOptions,
+    regenerate: bool,
+    signature: String,
+    failing: F,
+    attempts: usize,
+}
+
+/// Minimize `trace`, a block trace without its JSON-RPC wrapping, which
+/// `failing` returns the failure of, or `None` when it proves.
+pub fn minimize_trace(
+    trace: Value,
+    options: &MinimizeOptions,
+    mut failing: impl FnMut(&Value) -> Option<String>,
+) -> anyhow::Result<Minimized> {
+    let failure = failing(&trace).ok_or_else(|| anyhow::anyhow!("the trace does not fail"))?;
+    let count = trace["transactions"].as_array().map_or(0, Vec::len);
+    let mut case = Case {
+        transactions: (0..count).collect(),
+        calldata: BTreeMap::new(),
+    };
+    let mut minimizer = Minimizer {
+        trace: &trace,
+        options,
+        regenerate: false,
+        signature: signature(&failure).to_string(),
+        failing,
+        attempts: 1,
+    };
+    let mut best = (trace.clone(), failure);
+    let mut steps = vec![];
+
+    if !options.no_regenerate {
+        match minimizer.regenerated(&case) {
+            Ok(regenerated) => {
+                if let Some(failure) = minimizer.check(&regenerated) {
+                    minimizer.regenerate = true;
+                    best = (regenerated, failure);
+                    steps.push("regenerated the trace in the dry run".to_string());
+                } else {
+                    log::warn!(
+                        "the regenerated trace fails differently, keeping traces as they are"
+                    );
+                }
+            }
+            Err(e) => log::warn!("can't regenerate the trace, keeping it as it is: {e}"),
+        }
+    }
+
+    loop {
+        let mut progress = false;
+
+        // Drop runs of transactions, halving their length down to one.
+        let mut size = (case.transactions.len() / 2).max(1);
+        loop {
+            let mut start = 0;
+            while start < case.transactions.len() && case.transactions.len() > 1 {
+                let end = (start + size).min(case.transactions.len());
+                let mut candidate = case.clone();
+                let dropped: Vec<usize> = candidate.transactions.drain(start..end).collect();
+                if let Some(kept) = minimizer.attempt(&candidate) {
+                    steps.push(format!("dropped transactions {dropped:?}"));
+                    best = kept;
+                    case = candidate;
+                    progress = true;
+                } else {
+                    start = end;
+                }
+            }
+            if size == 1 {
+                break;
+            }
+            size /= 2;
+        }
+
+        // Truncate calldata to the shortest length still failing.
+        if minimizer.regenerate {
+            for index in case.transactions.clone() {
+                let calldata = match case.calldata.get(&index) {
+                    Some(calldata) => calldata.clone(),
+                    None => {
+                        let data = trace["transactions"][index]["data"]
+                            .as_str()
+                            .unwrap_or_default();
+                        hex::decode(data.trim_start_matches("0x"))?
+                    }
+                };
+                for len in shorter_lengths(calldata.len()) {
+                    let mut candidate = case.clone();
+                    candidate.calldata.insert(index, calldata[..len].to_vec());
+                    if let Some(kept) = minimizer.attempt(&candidate) {
+                        steps.push(format!(
+                            "truncated the calldata of transaction {index} to {len} bytes"
+                        ));
+                        best = kept;
+                        case = candidate;
+                        progress = true;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if !progress {
+            break;
+        }
+    }
+
+    let (trace, failure) = best;
+    Ok(Minimized {
+        trace,
+        failure,
+        steps,
+        attempts: minimizer.attempts,
+    })
+}
+
+impl<F: FnMut(&Value) -> Option<String>> Minimizer<'_, F> {
+    /// The trace of `case` with its failure, if it fails as the original.
+    fn attempt(&mut self, case: &Case) -> Option<(Value, String)> {
+        let trace = if self.regenerate {
+            match self.regenerated(case) {
+                Ok(trace) => trace,
+                Err(e) => {
+                    log::debug!("can't regenerate {case:?}: {e}");
+                    return None;
+                }
+            }
+        } else if case.calldata.is_empty() {
+            self.dropped(case)
+        } else {
+            return None;
+        };
+        self.check(&trace).map(|failure| (trace, failure))
+    }
+
+    /// The failure of `trace`, if it fails as the original.
+    fn check(&mut self, trace: &Value) -> Option<String> {
+        self.attempts += 1;
+        let failure = (self.failing)(trace)?;
+        log::info!("attempt {}: {}", self.attempts, signature(&failure));
+        (self.options.any_failure || signature(&failure) == self.signature).then_some(failure)
+    }
+
+    /// The original trace with only the transactions of `case`.
+    fn dropped(&self, case: &Case) -> Value {
+        let mut trace = self.trace.clone();
+        for field in ["transactions", "executionResults", "txStorageTraces"] {
+            let Some(items) = trace[field].as_array() else {
+                continue;
+            };
+            if items.len() != self.trace["transactions"].as_array().map_or(0, Vec::len) {
+                continue;
+            }
+            let kept: Value = case
+                .transactions
+                .iter()
+                .map(|index| items[*index].clone())
+                .collect();
+            trace[field] = kept;
+        }
+        trace
+    }
+
+    /// The trace of `case` re-executed in the dry run and assembled again.
+    fn regenerated(&self, case: &Case) -> anyhow::Result<Value> {
+        let mut trace = self.dropped(case);
+        trace["txStorageTraces"] = json!([]);
+        let calldata: BTreeMap<usize, Vec<u8>> = case
+            .transactions
+            .iter()
+            .enumerate()
+            .filter_map(|(position, index)| {
+                case.calldata
+                    .get(index)
+                    .map(|calldata| (position, calldata.clone()))
+            })
+            .collect();
+        let (pre, runs) = rerun_block(trace.clone(), &calldata, &self.options.config)?;
+
+        let mut block = trace["header"].clone();
+        block["miner"] = trace["coinbase"]["address"].clone();
+        block["transactions"] = trace["transactions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(position, tx)| eth_transaction(tx, calldata.get(&position)))
+            .collect();
+        let mut traces = vec![];
+        let mut prestates = vec![];
+        for (position, run) in runs.iter().enumerate() {
+            traces.push(struct_logger_result(&run.output, &run.steps, run.gas_limit));
+            let mut prestate = json!({ "pre": {} });
+            if position == 0 {
+                prestate["pre"] = accounts_json(&pre);
+            }
+            if let Ok(output) = &run.output {
+                prestate["post"] = accounts_json(&output.state);
+            }
+            prestates.push(prestate);
+        }
+        let chain_id = trace["chainID"].as_u64();
+        assemble_block_trace(&block, &traces, &prestates, chain_id)
+    }
+}
+
+/// First line of a failure, what candidates must fail with too.
+fn signature(failure: &str) -> &str {
+    failure.lines().next().unwrap_or_default().trim()
+}
+
+/// Calldata lengths to try, shortest first: none, the selector, then the
+/// selector with halves of the argument words.
+fn shorter_lengths(len: usize) -> Vec<usize> {
+    let mut lengths = vec![0, 4];
+    let words = len.saturating_sub(4) / 32;
+    let mut halves = vec![];
+    let mut half = words / 2;
+    while half > 0 {
+        halves.push(4 + 32 * half);
+        half /= 2;
+    }
+    lengths.extend(halves.into_iter().rev());
+    lengths.dedup();
+    lengths.retain(|l| *l < len);
+    lengths
+}
+
+/// Transaction of the block trace in the `eth_getBlockByNumber` format,
+/// with `calldata` as input if given.
+fn eth_transaction(tx: &Value, calldata: Option<&Vec<u8>>) -> Value {
+    let mut eth = json!({
+        "type": tx["type"],
+        "nonce": tx["nonce"],
+        "hash": tx["txHash"],
+        "gas": tx["gas"],
+        "gasPrice": tx["gasPrice"],
+        "from": tx["from"],
+        "to": tx["to"],
+        "chainId": tx["chainId"],
+        "value": tx["value"],
+        "input": match calldata {
+            Some(calldata) => json!(format!("0x{}", hex::encode(calldata))),
+            None => tx["data"].clone(),
+        },
+        "v": tx["v"],
+        "r": tx["r"],
+        "s": tx["s"],
+    });
+    for (field, key) in [
+        ("gasTipCap", "maxPriorityFeePerGas"),
+        ("gasFeeCap", "maxFeePerGas"),
+        ("accessList", "accessList"),
+    ] {
+        if let Some(value) = tx.get(field).filter(|value| !value.is_null()) {
+            eth[key] = value.clone();
+        }
+    }
+    if tx["type"] == json!(super::validate::L1_MESSAGE_TX_TYPE) {
+        eth["queueIndex"] = tx["nonce"].clone();
+    }
+    eth
+}
+
+/// Accounts in the `prestateTracer` format.
+fn accounts_json(accounts: &BTreeMap<B160, AccountState>) -> Value {
+    accounts
+        .iter()
+        .map(|(address, account)| {
+            let storage: serde_json::Map<String, Value> = account
+                .storage
+                .iter()
+                .map(|(slot, value)| (format!("{slot:#x}"), json!(format!("{value:#x}"))))
+                .collect();
+            (
+                format!("{address:?}"),
+                json!({
+                    "balance": format!("{:#x}", account.balance),
+                    "nonce": account.nonce,
+                    "code": format!("0x{}", hex::encode(&account.code)),
+                    "storage": storage,
+                }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_to_the_failing_transaction() {
+        let json: Value =
+            serde_json::from_str(&std::fs::read_to_string("traces/calculation.json").unwrap())
+                .unwrap();
+        let mut trace = json["result"].clone();
+        let (tx, result) = (
+            trace["transactions"][0].clone(),
+            trace["executionResults"][0].clone(),
+        );
+        let hashes = ["0x01", "0x02", "0xbad", "0x04", "0x05"];
+        trace["transactions"] = hashes
+            .iter()
+            .map(|hash| {
+                let mut tx = tx.clone();
+                tx["txHash"] = json!(hash);
+                tx
+            })
+            .collect();
+        trace["executionResults"] = json!(vec![result; hashes.len()]);
+        trace["txStorageTraces"] = json!([]);
+
+        // Fails on the bad transaction, or in another way without it.
+        let failing = |trace: &Value| {
+            let transactions = trace["transactions"].as_array().unwrap();
+            if transactions.iter().any(|tx| tx["txHash"] == "0xbad") {
+                Some("Error: bad transaction\ndetails".to_string())
+            } else {
+                Some("Error: other".to_string())
+            }
+        };
+        let options = MinimizeOptions {
+            no_regenerate: true,
+            ..Default::default()
+        };
+        let minimized = minimize_trace(trace, &options, failing).unwrap();
+        assert_eq!(minimized.trace["transactions"].as_array().unwrap().len(), 1);
+        assert_eq!(minimized.trace["transactions"][0]["txHash"], "0xbad");
+        assert_eq!(
+            minimized.trace["executionResults"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(shorter_lengths(4 + 32 * 5), [0, 4, 36, 68]);
+    }
+
+    #[test]
+    fn regenerates_and_truncates_calldata() {
+        let json: Value =
+            serde_json::from_str(&std::fs::read_to_string("traces/calculation.json").unwrap())
+                .unwrap();
+        let trace = json["result"].clone();
+        let gas = trace["transactions"][0]["gas"].as_u64().unwrap();
+
+        // Fails as long as `add(uint256,uint256)` is called.
+        let failing = |trace: &Value| {
+            let transactions = trace["transactions"].as_array().unwrap();
+            transactions
+                .iter()
+                .any(|tx| tx["data"].as_str().unwrap().starts_with("0x771602f7"))
+                .then(|| "Error: add called".to_string())
+        };
+        let minimized = minimize_trace(trace, &MinimizeOptions::default(), failing).unwrap();
+        assert_eq!(
+            minimized.steps,
+            [
+                "regenerated the trace in the dry run",
+                "truncated the calldata of transaction 0 to 4 bytes"
+            ]
+        );
+        assert_eq!(minimized.trace["transactions"][0]["data"], "0x771602f7");
+        // Without its arguments the call reverts, having used part of its gas.
+        let result = &minimized.trace["executionResults"][0];
+        assert_eq!(result["failed"], json!(true));
+        let gas_used = result["gas"].as_u64().unwrap();
+        assert!(21_000 < gas_used && gas_used < gas, "{gas_used} gas used");
+    }
+}
@@ -1,38 +1,35 @@
-use glob::glob;
-use prover::{utils::get_block_trace_from_file, BlockTrace};
-
+mod assemble;
+mod batch;
+mod chunk;
+mod diff;
+mod fetch;
+mod minimize;
 pub mod mock_plonk;
 mod proof;
+mod row_usage;
+mod stats;
+mod trace_file;
+mod validate;
 
+pub use assemble::{assemble_block_trace, assemble_trace_files};
+pub use batch::{
+    batch_files, load_batch_traces, load_trace_chain, BatchError, BatchOptions, Discontinuity,
+    DiscontinuityKind,
+};
+pub use chunk::{split_chunks, write_chunk_index, ChunkEntry, ChunkError, CHUNK_INDEX_FILE};
+pub use diff::{diff_report, diff_traces, Difference, StepDivergence, TraceDiff};
+pub use fetch::{parse_block_range, FetchError, FetchOptions, TraceFetcher, DEFAULT_TRACE_CACHE};
+pub use minimize::{minimize_trace, MinimizeOptions, Minimized};
 pub use proof::{
     gen_and_verify_batch_proofs, gen_and_verify_chunk_proofs, gen_and_verify_normal_and_evm_proofs,
     gen_and_verify_normal_proof,
 };
-
-pub fn load_batch_traces(batch_dir: &str) -> (Vec<String>, Vec<BlockTrace>) {
-    let file_names: Vec<String> = glob(&format!("{batch_dir}/**/*.json"))
-        .unwrap()
-        .map(|p| p.unwrap().to_str().unwrap().to_string())
-        .collect();
-    log::info!("test batch with {:?}", file_names);
-    let mut names_and_traces = file_names
-        .into_iter()
-        .map(|trace_path| {
-            let trace: BlockTrace = get_block_trace_from_file(trace_path.clone());
-            (
-                trace_path,
-                trace.clone(),
-                trace.header.number.unwrap().as_u64(),
-            )
-        })
-        .collect::<Vec<_>>();
-    names_and_traces.sort_by(|a, b| a.2.cmp(&b.2));
-    log::info!(
-        "sorted: {:?}",
-        names_and_traces
-            .iter()
-            .map(|(f, _, _)| f.clone())
-            .collect::<Vec<String>>()
-    );
-    names_and_traces.into_iter().map(|(f, t, _)| (f, t)).unzip()
-}
+pub use row_usage::trace_row_usage;
+pub use stats::{stats_report, TraceStats};
+pub use trace_file::{
+    convert_trace, is_compact_trace, load_block_trace, read_compact_trace, read_trace_json,
+    write_compact_trace, COMPACT_TRACE_EXTENSION,
+};
+pub use validate::{
+    validate_trace, validate_trace_file, Expectations, Problem, EXPECTED_TRACE_VERSION,
+};
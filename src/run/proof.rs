@@ -27,6 +27,7 @@ pub fn gen_and_verify_chunk_proofs(
     layer1_snark: Snark,
     output_dir: &str,
     params_dir: &str,
+    name: &str,
 ) {
     let normal_proof = gen_and_verify_normal_and_evm_proofs(
         &mut zkevm_prover.inner,
@@ -35,7 +36,13 @@ pub fn gen_and_verify_chunk_proofs(
         Some(output_dir),
     )
     .0;
-    verify_chunk_proof(&zkevm_prover.inner, normal_proof, output_dir, params_dir);
+    verify_chunk_proof(
+        &zkevm_prover.inner,
+        normal_proof,
+        output_dir,
+        params_dir,
+        name,
+    );
 }
 
 pub fn gen_and_verify_normal_and_evm_proofs(
@@ -121,11 +128,12 @@ fn verify_chunk_proof(
     normal_proof: Snark,
     output_dir: &str,
     params_dir: &str,
+    name: &str,
 ) {
     let pk = prover.pk(LayerId::Layer2.id()).unwrap();
     let chunk_proof =
         ChunkProof::new(normal_proof, StorageTrace::default(), Some(pk), None).unwrap();
-    chunk_proof.dump(output_dir, "0").unwrap();
+    chunk_proof.dump(output_dir, name).unwrap();
 
     let verifier = zkevm::Verifier::from_dirs(params_dir, output_dir);
     log::info!("Constructed zkevm verifier");
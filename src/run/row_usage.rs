@@ -0,0 +1,38 @@
+use prover::{
+    utils::chunk_trace_to_witness_block, zkevm::circuit::calculate_row_usage_of_witness_block,
+    BlockTrace,
+};
+
+use crate::dry_run::row_usage::SubCircuitRows;
+
+/// Exact row usage of every sub-circuit for the chunk made of `block_traces`.
+pub fn trace_row_usage(block_traces: &[BlockTrace]) -> anyhow::Result<Vec<SubCircuitRows>> {
+    // The witness builder takes the traces by value.
+    let witness_block = chunk_trace_to_witness_block(block_traces.to_vec())?;
+    let row_usage = calculate_row_usage_of_witness_block(&witness_block)?;
+
+    Ok(row_usage
+        .into_iter()
+        .map(|usage| SubCircuitRows {
+            name: usage.name,
+            rows: usage.row_number,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dry_run::row_usage::chunk_capacity, run::load_block_trace};
+
+    #[test]
+    fn row_usage_of_bundled_trace() {
+        let trace = load_block_trace("traces/calculation.json").unwrap();
+        let usage = trace_row_usage(&[trace]).unwrap();
+
+        let rows = |name: &str| usage.iter().find(|u| u.name == name).unwrap().rows;
+        assert!(rows("evm") > 0);
+        assert!(rows("keccak") > 0);
+        assert!(usage.iter().all(|u| u.rows <= chunk_capacity(20).unwrap()));
+    }
+}
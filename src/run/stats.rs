@@ -0,0 +1,306 @@
+//! Statistics of block traces, to compare what makes traces heavy to prove
+//! and to plan chunk sizes.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write,
+};
+
+use revm_primitives::{keccak256, B256, U256};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::trace_file::read_trace_json;
+
+/// Highest precompile address on Scroll.
+const LAST_PRECOMPILE: u64 = 0x09;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockTraceJson {
+    transactions: Vec<Value>,
+    execution_results: Vec<ExecutionResultJson>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExecutionResultJson {
+    gas: u64,
+    #[serde(default)]
+    byte_code: Option<String>,
+    struct_logs: Vec<StructLog>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StructLog {
+    op: String,
+    depth: u64,
+    #[serde(default)]
+    stack: Option<Vec<String>>,
+    /// Set on the step that failed, whose effects never happened.
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    extra_data: Option<ExtraData>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExtraData {
+    #[serde(default)]
+    code_list: Option<Vec<String>>,
+}
+
+/// Statistics of one or more block traces.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceStats {
+    pub blocks: u64,
+    pub transactions: u64,
+    /// Gas used by the transactions, intrinsic gas included.
+    pub gas_used: u64,
+    pub steps: u64,
+    /// Executed steps per opcode name.
+    pub opcodes: BTreeMap<String, u64>,
+    /// Calls per precompile address.
+    pub precompile_calls: BTreeMap<u64, u64>,
+    /// Bytes hashed by `KECCAK256`.
+    pub keccak_bytes: u64,
+    pub storage_reads: u64,
+    pub storage_writes: u64,
+    /// Deepest call depth reached, 1 for the transaction's own frame.
+    pub max_depth: u64,
+    /// Size of every distinct bytecode executed, by keccak hash.
+    pub bytecodes: BTreeMap<B256, usize>,
+}
+
+impl TraceStats {
+    /// Statistics of the block trace at `path`, compact or JSON, wrapped in a
+    /// JSON-RPC response or not.
+    pub fn from_file(path: &str) -> anyhow::Result<Self> {
+        let trace: BlockTraceJson = serde_json::from_value(read_trace_json(path)?)?;
+
+        let mut stats = Self {
+            blocks: 1,
+            transactions: trace.transactions.len() as u64,
+            ..Default::default()
+        };
+        for result in &trace.execution_results {
+            stats.gas_used += result.gas;
+            stats.add_code(result.byte_code.as_deref());
+            for log in &result.struct_logs {
+                stats.add_step(log);
+            }
+        }
+        Ok(stats)
+    }
+
+    fn add_step(&mut self, log: &StructLog) {
+        self.steps += 1;
+        self.max_depth = self.max_depth.max(log.depth);
+        *self.opcodes.entry(log.op.clone()).or_default() += 1;
+        if log.error.is_some() {
+            return;
+        }
+        let stack = log.stack.as_deref().unwrap_or_default();
+        // Top of the stack is last, the second item being a call's address
+        // and the size of the hashed memory.
+        let second = || {
+            stack
+                .len()
+                .checked_sub(2)
+                .and_then(|i| parse_word(&stack[i]))
+        };
+        match log.op.as_str() {
+            "SLOAD" => self.storage_reads += 1,
+            "SSTORE" => self.storage_writes += 1,
+            "SHA3" | "KECCAK256" => {
+                if let Some(size) = second() {
+                    self.keccak_bytes = self
+                        .keccak_bytes
+                        .saturating_add(u64::try_from(size).unwrap_or(u64::MAX));
+                }
+            }
+            "CALL" | "CALLCODE" | "DELEGATECALL" | "STATICCALL" => {
+                if let Some(address) = second().and_then(|a| u64::try_from(a).ok()) {
+                    if (1..=LAST_PRECOMPILE).contains(&address) {
+                        *self.precompile_calls.entry(address).or_default() += 1;
+                    }
+                }
+            }
+            _ => {}
+        }
+        if let Some(codes) = log.extra_data.as_ref().and_then(|e| e.code_list.as_ref()) {
+            for code in codes {
+                self.add_code(Some(code));
+            }
+        }
+    }
+
+    fn add_code(&mut self, code: Option<&str>) {
+        let Some(code) = code.and_then(|code| hex::decode(code.trim_start_matches("0x")).ok())
+        else {
+            return;
+        };
+        if !code.is_empty() {
+            self.bytecodes.insert(keccak256(&code), code.len());
+        }
+    }
+
+    /// Add the statistics of other traces.
+    pub fn merge(&mut self, other: &Self) {
+        self.blocks += other.blocks;
+        self.transactions += other.transactions;
+        self.gas_used += other.gas_used;
+        self.steps += other.steps;
+        for (op, count) in &other.opcodes {
+            *self.opcodes.entry(op.clone()).or_default() += count;
+        }
+        for (address, count) in &other.precompile_calls {
+            *self.precompile_calls.entry(*address).or_default() += count;
+        }
+        self.keccak_bytes = self.keccak_bytes.saturating_add(other.keccak_bytes);
+        self.storage_reads += other.storage_reads;
+        self.storage_writes += other.storage_writes;
+        self.max_depth = self.max_depth.max(other.max_depth);
+        self.bytecodes.extend(&other.bytecodes);
+    }
+}
+
+/// Side by side report of named statistics, with the `top` most executed
+/// opcodes of any of them.
+pub fn stats_report(stats: &[(String, TraceStats)], top: usize) -> String {
+    let mut out = String::new();
+    let mut row = |label: &str, values: Vec<String>| {
+        write!(out, "{label:<22}").unwrap();
+        for value in values {
+            write!(out, " {value:>24}").unwrap();
+        }
+        out.push('\n');
+    };
+    let column = |f: &dyn Fn(&TraceStats) -> String| -> Vec<String> {
+        stats.iter().map(|(_, stats)| f(stats)).collect()
+    };
+
+    row(
+        "",
+        stats.iter().map(|(name, _)| shorten(name, 24)).collect(),
+    );
+    row("blocks", column(&|s| s.blocks.to_string()));
+    row("transactions", column(&|s| s.transactions.to_string()));
+    row("gas used", column(&|s| s.gas_used.to_string()));
+    row("steps", column(&|s| s.steps.to_string()));
+    row("max call depth", column(&|s| s.max_depth.to_string()));
+    row("storage reads", column(&|s| s.storage_reads.to_string()));
+    row("storage writes", column(&|s| s.storage_writes.to_string()));
+    row("keccak bytes", column(&|s| s.keccak_bytes.to_string()));
+    row("bytecodes", column(&|s| s.bytecodes.len().to_string()));
+    row(
+        "bytecode bytes",
+        column(&|s| s.bytecodes.values().sum::<usize>().to_string()),
+    );
+    row(
+        "largest bytecode",
+        column(&|s| s.bytecodes.values().max().copied().unwrap_or(0).to_string()),
+    );
+
+    let precompiles: BTreeSet<u64> = stats
+        .iter()
+        .flat_map(|(_, s)| s.precompile_calls.keys().copied())
+        .collect();
+    for address in precompiles {
+        row(
+            &format!("precompile {address:#x} calls"),
+            column(&|s| {
+                s.precompile_calls
+                    .get(&address)
+                    .copied()
+                    .unwrap_or(0)
+                    .to_string()
+            }),
+        );
+    }
+
+    // Most executed opcodes across all the statistics.
+    let mut totals: BTreeMap<&str, u64> = BTreeMap::new();
+    for (_, s) in stats {
+        for (op, count) in &s.opcodes {
+            *totals.entry(op).or_default() += count;
+        }
+    }
+    let mut ops: Vec<_> = totals.into_iter().collect();
+    ops.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (op, _) in ops.into_iter().take(top) {
+        row(
+            &format!("  {op}"),
+            column(&|s| {
+                let count = s.opcodes.get(op).copied().unwrap_or(0);
+                format!(
+                    "{count} ({:.1}%)",
+                    count as f64 * 100.0 / s.steps.max(1) as f64
+                )
+            }),
+        );
+    }
+    out
+}
+
+fn shorten(name: &str, width: usize) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    if chars.len() <= width {
+        name.to_string()
+    } else {
+        let tail: String = chars[chars.len() - (width - 3)..].iter().collect();
+        format!("...{tail}")
+    }
+}
+
+fn parse_word(word: &str) -> Option<U256> {
+    U256::from_str_radix(word.trim_start_matches("0x"), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groth16_is_heavier() {
+        let calculation = TraceStats::from_file("traces/calculation.json").unwrap();
+        let groth16 = TraceStats::from_file("traces/groth16_verification.json").unwrap();
+
+        assert_eq!(calculation.transactions, 1);
+        assert!(calculation.precompile_calls.is_empty());
+        // ecAdd, ecMul and ecPairing of the verifier
+        assert_eq!(
+            groth16.precompile_calls,
+            BTreeMap::from([(6, 1), (7, 1), (8, 1)])
+        );
+        assert!(groth16.steps > calculation.steps);
+        assert!(groth16.gas_used > calculation.gas_used);
+
+        let mut total = calculation.clone();
+        total.merge(&groth16);
+        assert_eq!(total.steps, calculation.steps + groth16.steps);
+        assert_eq!(total.blocks, 2);
+    }
+
+    #[test]
+    fn failed_steps_have_no_effects() {
+        let log = |op: &str, stack: &[&str], error: Option<&str>| StructLog {
+            op: op.into(),
+            depth: 1,
+            stack: Some(stack.iter().map(|item| item.to_string()).collect()),
+            error: error.map(str::to_string),
+            extra_data: None,
+        };
+        let mut stats = TraceStats::default();
+        stats.add_step(&log("SSTORE", &["0x1", "0x0"], Some("out of gas")));
+        stats.add_step(&log("SHA3", &["0xffffffffffffffffff", "0x0"], None));
+        stats.add_step(&log("SHA3", &["0x20", "0x0"], None));
+        stats.add_step(&log("SLOAD", &["0x0"], None));
+
+        assert_eq!(stats.steps, 4);
+        assert_eq!(stats.storage_writes, 0);
+        assert_eq!(stats.storage_reads, 1);
+        assert_eq!(stats.keccak_bytes, u64::MAX);
+    }
+}
@@ -0,0 +1,487 @@
+//! Compact binary block traces.
+//!
+//! The file is `MAGIC`, a flags byte and the bincode encoding of the trace's
+//! JSON tree, zstd-compressed when flagged. Object keys are stored once,
+//! hex strings as bytes. The tree keeps every value of the JSON trace and is
+//! itself a serde `Deserializer`, so `BlockTrace` deserializes from it
+//! exactly as from the JSON file. Loading is neither streaming nor zero-copy:
+//! the whole tree is decoded into memory first, and hex strings are encoded
+//! back to text for the prover's deserializers. What it saves is tokenizing
+//! JSON text.
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use prover::BlockTrace;
+use serde::{
+    de::{
+        self, value::BorrowedStrDeserializer, DeserializeOwned, DeserializeSeed, IntoDeserializer,
+        Visitor,
+    },
+    forward_to_deserialize_any, Deserialize, Serialize,
+};
+use serde_json::{json, Value};
+
+/// Extension of compact trace files.
+pub const COMPACT_TRACE_EXTENSION: &str = "trace";
+const MAGIC: &[u8; 4] = b"LZT1";
+const FLAG_ZSTD: u8 = 1;
+
+/// JSON value with interned object keys and binary hex strings.
+#[derive(Debug, Serialize, Deserialize)]
+enum Node {
+    Null,
+    Bool(bool),
+    U64(u64),
+    I64(i64),
+    F64(f64),
+    Str(String),
+    /// `0x` prefixed lowercase hex.
+    Hex(Vec<u8>),
+    /// Lowercase hex without prefix, as `returnValue`.
+    RawHex(Vec<u8>),
+    Array(Vec<Node>),
+    Object(Vec<(u32, Node)>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CompactTrace {
+    keys: Vec<String>,
+    root: Node,
+}
+
+/// Load a block trace from a JSON or compact file, told apart by content.
+/// JSON traces may be wrapped in a JSON-RPC response or not.
+pub fn load_block_trace(path: impl AsRef<Path>) -> anyhow::Result<BlockTrace> {
+    let path = path.as_ref();
+    if is_compact_trace(path)? {
+        return read_compact(path);
+    }
+
+    #[derive(Deserialize)]
+    struct Response {
+        #[serde(default)]
+        result: Option<BlockTrace>,
+    }
+    let open = || -> anyhow::Result<_> { Ok(BufReader::new(File::open(path)?)) };
+    match serde_json::from_reader::<_, Response>(open()?)?.result {
+        Some(trace) => Ok(trace),
+        // Not wrapped, read it again as a trace.
+        None => Ok(serde_json::from_reader(open()?)?),
+    }
+}
+
+/// The JSON tree of the block trace at `path`, compact or JSON, without
+/// its JSON-RPC wrapping.
+pub fn read_trace_json(path: impl AsRef<Path>) -> anyhow::Result<Value> {
+    let path = path.as_ref();
+    let mut json: Value = if is_compact_trace(path)? {
+        read_compact_trace(path)?
+    } else {
+        serde_json::from_reader(BufReader::new(File::open(path)?))?
+    };
+    if let Some(result) = json.get_mut("result") {
+        json = result.take();
+    }
+    Ok(json)
+}
+
+/// Whether the file at `path` is a compact trace.
+pub fn is_compact_trace(path: impl AsRef<Path>) -> anyhow::Result<bool> {
+    let mut magic = [0; 4];
+    let mut file = File::open(path)?;
+    Ok(file.read_exact(&mut magic).is_ok() && &magic == MAGIC)
+}
+
+/// The JSON tree of the block trace in a compact file.
+pub fn read_compact_trace(path: impl AsRef<Path>) -> anyhow::Result<Value> {
+    read_compact(path)
+}
+
+/// Deserialize the block trace in a compact file as `T`.
+fn read_compact<T: DeserializeOwned>(path: impl AsRef<Path>) -> anyhow::Result<T> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    anyhow::ensure!(&header[..4] == MAGIC, "not a compact block trace");
+    let trace: CompactTrace = if header[4] & FLAG_ZSTD != 0 {
+        bincode::deserialize_from(zstd::Decoder::with_buffer(reader)?)?
+    } else {
+        bincode::deserialize_from(reader)?
+    };
+    Ok(T::deserialize(NodeDeserializer {
+        node: trace.root,
+        keys: &trace.keys,
+    })?)
+}
+
+/// Write the block trace `json`, wrapped in a JSON-RPC response or not, to
+/// a compact file, compressed at zstd `level` unless it is `None`.
+pub fn write_compact_trace(
+    path: impl AsRef<Path>,
+    mut json: Value,
+    level: Option<i32>,
+) -> anyhow::Result<()> {
+    if let Some(result) = json.get_mut("result") {
+        json = result.take();
+    }
+    let mut keys = Keys::default();
+    let root = keys.encode(json);
+    let trace = CompactTrace {
+        keys: keys.names,
+        root,
+    };
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    match level {
+        Some(level) => {
+            writer.write_all(&[FLAG_ZSTD])?;
+            let mut encoder = zstd::Encoder::new(writer, level)?;
+            bincode::serialize_into(&mut encoder, &trace)?;
+            encoder.finish()?.flush()?;
+        }
+        None => {
+            writer.write_all(&[0])?;
+            bincode::serialize_into(&mut writer, &trace)?;
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Convert the trace at `input` to `output`: JSON when `output` ends in
+/// `.json`, compact otherwise.
+pub fn convert_trace(input: &str, output: &str, level: Option<i32>) -> anyhow::Result<()> {
+    let json = read_trace_json(input)?;
+    if output.ends_with(".json") {
+        let json = json!({ "jsonrpc": "2.0", "id": 1, "result": json });
+        serde_json::to_writer(BufWriter::new(File::create(output)?), &json)?;
+        return Ok(());
+    }
+    write_compact_trace(output, json, level)
+}
+
+#[derive(Default)]
+struct Keys {
+    names: Vec<String>,
+    index: HashMap<String, u32>,
+}
+
+impl Keys {
+    fn encode(&mut self, value: Value) -> Node {
+        match value {
+            Value::Null => Node::Null,
+            Value::Bool(b) => Node::Bool(b),
+            Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+                (Some(n), _) => Node::U64(n),
+                (_, Some(n)) => Node::I64(n),
+                _ => Node::F64(n.as_f64().unwrap_or_default()),
+            },
+            Value::String(s) => match s.strip_prefix("0x") {
+                Some(digits) => hex_bytes(digits).map_or(Node::Str(s), Node::Hex),
+                None => hex_bytes(&s).map_or(Node::Str(s), Node::RawHex),
+            },
+            Value::Array(items) => Node::Array(items.into_iter().map(|v| self.encode(v)).collect()),
+            Value::Object(map) => Node::Object(
+                map.into_iter()
+                    .map(|(key, v)| (self.intern(key), self.encode(v)))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn intern(&mut self, key: String) -> u32 {
+        if let Some(index) = self.index.get(&key) {
+            return *index;
+        }
+        let index = self.names.len() as u32;
+        self.names.push(key.clone());
+        self.index.insert(key, index);
+        index
+    }
+}
+
+/// Deserializer of a `Node` as of the JSON value it encodes.
+struct NodeDeserializer<'de> {
+    node: Node,
+    keys: &'de [String],
+}
+
+fn key_name(keys: &[String], index: u32) -> Result<&str, de::value::Error> {
+    keys.get(index as usize)
+        .map(String::as_str)
+        .ok_or_else(|| de::Error::custom(format!("unknown key {index}")))
+}
+
+impl<'de> de::Deserializer<'de> for NodeDeserializer<'de> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let keys = self.keys;
+        match self.node {
+            Node::Null => visitor.visit_unit(),
+            Node::Bool(b) => visitor.visit_bool(b),
+            Node::U64(n) => visitor.visit_u64(n),
+            Node::I64(n) => visitor.visit_i64(n),
+            Node::F64(n) => visitor.visit_f64(n),
+            Node::Str(s) => visitor.visit_string(s),
+            Node::Hex(bytes) => visitor.visit_string(format!("0x{}", hex::encode(bytes))),
+            Node::RawHex(bytes) => visitor.visit_string(hex::encode(bytes)),
+            Node::Array(items) => visitor.visit_seq(Nodes {
+                items: items.into_iter(),
+                keys,
+            }),
+            Node::Object(fields) => visitor.visit_map(Fields {
+                fields: fields.into_iter(),
+                value: None,
+                keys,
+            }),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.node {
+            Node::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// Enums as serde_json writes them: a unit variant's name, or an object
+    /// of one field naming the variant.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        match self.node {
+            Node::Str(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Node::Object(mut fields) if fields.len() == 1 => {
+                let (key, node) = fields.pop().unwrap();
+                visitor.visit_enum(Variant {
+                    name: key_name(self.keys, key)?,
+                    content: NodeDeserializer {
+                        node,
+                        keys: self.keys,
+                    },
+                })
+            }
+            _ => Err(de::Error::custom("expected an enum variant")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct Nodes<'de> {
+    items: std::vec::IntoIter<Node>,
+    keys: &'de [String],
+}
+
+impl<'de> de::SeqAccess<'de> for Nodes<'de> {
+    type Error = de::value::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        self.items
+            .next()
+            .map(|node| {
+                seed.deserialize(NodeDeserializer {
+                    node,
+                    keys: self.keys,
+                })
+            })
+            .transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct Fields<'de> {
+    fields: std::vec::IntoIter<(u32, Node)>,
+    value: Option<Node>,
+    keys: &'de [String],
+}
+
+impl<'de> de::MapAccess<'de> for Fields<'de> {
+    type Error = de::value::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        let Some((key, node)) = self.fields.next() else {
+            return Ok(None);
+        };
+        self.value = Some(node);
+        seed.deserialize(BorrowedStrDeserializer::new(key_name(self.keys, key)?))
+            .map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let node = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("value without a key"))?;
+        seed.deserialize(NodeDeserializer {
+            node,
+            keys: self.keys,
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.fields.len())
+    }
+}
+
+struct Variant<'de> {
+    name: &'de str,
+    content: NodeDeserializer<'de>,
+}
+
+impl<'de> de::EnumAccess<'de> for Variant<'de> {
+    type Error = de::value::Error;
+    type Variant = NodeDeserializer<'de>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Self::Error> {
+        let name = seed.deserialize(BorrowedStrDeserializer::new(self.name))?;
+        Ok((name, self.content))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for NodeDeserializer<'de> {
+    type Error = de::value::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        <()>::deserialize(self)
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_seq(self, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        de::Deserializer::deserialize_map(self, visitor)
+    }
+}
+
+/// Bytes of lowercase hex `digits` that encode back to them.
+fn hex_bytes(digits: &str) -> Option<Vec<u8>> {
+    let lowercase_hex = digits
+        .bytes()
+        .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    if digits.len() < 2 || digits.len() % 2 != 0 || !lowercase_hex {
+        return None;
+    }
+    hex::decode(digits).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_round_trip() {
+        let path = std::env::temp_dir().join("groth16_verification.trace");
+        let json: Value = serde_json::from_reader(BufReader::new(
+            File::open("traces/groth16_verification.json").unwrap(),
+        ))
+        .unwrap();
+        write_compact_trace(&path, json.clone(), Some(3)).unwrap();
+
+        assert!(is_compact_trace(&path).unwrap());
+        assert_eq!(read_compact_trace(&path).unwrap(), json["result"]);
+        let compact_size = std::fs::metadata(&path).unwrap().len();
+        let json_size = std::fs::metadata("traces/groth16_verification.json")
+            .unwrap()
+            .len();
+        assert!(compact_size * 10 < json_size);
+        assert_eq!(
+            serde_json::to_value(load_block_trace(&path).unwrap()).unwrap(),
+            serde_json::to_value(load_block_trace("traces/groth16_verification.json").unwrap())
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn json_conversion_keeps_one_wrapper() {
+        let path = std::env::temp_dir().join(format!("calculation-{}.json", std::process::id()));
+        convert_trace("traces/calculation.json", path.to_str().unwrap(), None).unwrap();
+
+        let json: Value = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert!(json["result"].get("result").is_none());
+        assert_eq!(
+            json["result"],
+            read_trace_json("traces/calculation.json").unwrap()
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Sizes and load times of the same trace as JSON and compact, printed with
+    /// `cargo test --release compact_load_time -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn compact_load_time() {
+        let json_path = "traces/groth16_verification.json";
+        let compact_path = std::env::temp_dir().join("groth16_verification_timing.trace");
+        convert_trace(json_path, compact_path.to_str().unwrap(), Some(3)).unwrap();
+
+        let time = |load: &dyn Fn() -> BlockTrace| {
+            let start = std::time::Instant::now();
+            for _ in 0..10 {
+                load();
+            }
+            start.elapsed() / 10
+        };
+        let json = time(&|| load_block_trace(json_path).unwrap());
+        let compact = time(&|| load_block_trace(&compact_path).unwrap());
+        let size = |path: &Path| std::fs::metadata(path).unwrap().len();
+        println!(
+            "json: {} bytes, {json:?}; compact: {} bytes, {compact:?}",
+            size(Path::new(json_path)),
+            size(&compact_path)
+        );
+        std::fs::remove_file(compact_path).unwrap();
+    }
+}
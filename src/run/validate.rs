@@ -0,0 +1,531 @@
+//! Checks of a block trace before it is handed to the prover, reporting
+//! every problem with its JSON path instead of failing deep in witness
+//! generation.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+
+use revm_interpreter::opcode::{self, OPCODE_JUMPMAP};
+use revm_primitives::{B160, U256};
+use serde_json::Value;
+
+use super::trace_file::read_trace_json;
+use crate::dry_run::{replay::op_name, row_usage::stack_io, state_test::is_supported};
+
+/// l2geth release line the linked prover reads the traces of.
+pub const EXPECTED_TRACE_VERSION: &str = "4.4";
+/// Transaction type of L1 messages, which carry no chain id.
+pub(super) const L1_MESSAGE_TX_TYPE: u64 = 0x7e;
+
+/// A problem found in a trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// JSON path of the offending value, such as
+    /// `$.result.executionResults[0].structLogs[3].gas`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// What to hold the trace against.
+#[derive(Clone, Debug)]
+pub struct Expectations {
+    /// Prefix the trace `version` must start with.
+    pub version: String,
+    /// Chain id the trace must be for, any when `None`.
+    pub chain_id: Option<u64>,
+}
+
+impl Default for Expectations {
+    fn default() -> Self {
+        Self {
+            version: EXPECTED_TRACE_VERSION.to_string(),
+            chain_id: None,
+        }
+    }
+}
+
+/// Validate the block trace at `path`, compact or JSON, wrapped in a JSON-RPC
+/// response or not. Paths are relative to the unwrapped trace.
+pub fn validate_trace_file(path: &str, expect: &Expectations) -> anyhow::Result<Vec<Problem>> {
+    Ok(validate_trace(&read_trace_json(path)?, expect))
+}
+
+/// Validate a parsed block trace.
+pub fn validate_trace(json: &Value, expect: &Expectations) -> Vec<Problem> {
+    let mut validator = Validator::default();
+    match json.get("result") {
+        Some(trace) => validator.trace(trace, "$.result", expect),
+        None => validator.trace(json, "$", expect),
+    }
+    validator.problems
+}
+
+#[derive(Default)]
+struct Validator {
+    problems: Vec<Problem>,
+}
+
+impl Validator {
+    fn report(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.problems.push(Problem {
+            path: path.into(),
+            message: message.into(),
+        });
+    }
+
+    /// `json[field]`, reporting it missing or null.
+    fn field<'a>(&mut self, json: &'a Value, path: &str, field: &str) -> Option<&'a Value> {
+        match json.get(field) {
+            None | Some(Value::Null) => {
+                self.report(format!("{path}.{field}"), "missing");
+                None
+            }
+            value => value,
+        }
+    }
+
+    fn array<'a>(&mut self, json: &'a Value, path: &str, field: &str) -> &'a [Value] {
+        match self.field(json, path, field) {
+            Some(Value::Array(items)) => items,
+            Some(_) => {
+                self.report(format!("{path}.{field}"), "not an array");
+                &[]
+            }
+            None => &[],
+        }
+    }
+
+    /// A number, either a JSON number or a hex quantity.
+    fn number(&mut self, json: &Value, path: &str, field: &str) -> Option<u64> {
+        let value = self.field(json, path, field)?;
+        let number = match value {
+            Value::Number(number) => number.as_u64(),
+            Value::String(hex) => u64::from_str_radix(hex.trim_start_matches("0x"), 16).ok(),
+            _ => None,
+        };
+        if number.is_none() {
+            self.report(format!("{path}.{field}"), format!("not a number: {value}"));
+        }
+        number
+    }
+
+    fn address(&mut self, json: &Value, path: &str, field: &str) -> Option<B160> {
+        let value = self.field(json, path, field)?;
+        let address = value.as_str().and_then(parse_address);
+        if address.is_none() {
+            self.report(
+                format!("{path}.{field}"),
+                format!("not an address: {value}"),
+            );
+        }
+        address
+    }
+
+    fn trace(&mut self, trace: &Value, path: &str, expect: &Expectations) {
+        match self.field(trace, path, "version").map(Value::as_str) {
+            Some(Some(version)) if version_matches(version, &expect.version) => {}
+            Some(Some(version)) => self.report(
+                format!("{path}.version"),
+                format!(
+                    "{version} is not a {} trace the prover reads",
+                    expect.version
+                ),
+            ),
+            Some(None) => self.report(format!("{path}.version"), "not a string"),
+            None => {}
+        }
+        let chain_id = self.number(trace, path, "chainID");
+        if let (Some(chain_id), Some(expected)) = (chain_id, expect.chain_id) {
+            if chain_id != expected {
+                self.report(
+                    format!("{path}.chainID"),
+                    format!("{chain_id}, expected {expected}"),
+                );
+            }
+        }
+        if let Some(coinbase) = self.field(trace, path, "coinbase") {
+            self.address(coinbase, &format!("{path}.coinbase"), "address");
+        }
+        if let Some(header) = self.field(trace, path, "header") {
+            let header_path = format!("{path}.header");
+            for field in ["number", "timestamp", "gasLimit"] {
+                self.number(header, &header_path, field);
+            }
+            for field in ["hash", "parentHash", "stateRoot"] {
+                self.field(header, &header_path, field);
+            }
+        }
+
+        let transactions = self.array(trace, path, "transactions");
+        let results = self.array(trace, path, "executionResults");
+        if transactions.len() != results.len() {
+            self.report(
+                format!("{path}.executionResults"),
+                format!(
+                    "{} execution results for {} transactions",
+                    results.len(),
+                    transactions.len()
+                ),
+            );
+        }
+        for (index, tx) in transactions.iter().enumerate() {
+            self.transaction(tx, &format!("{path}.transactions[{index}]"), chain_id);
+        }
+
+        let proofs = Proofs::new(self, trace, path);
+        for (index, result) in results.iter().enumerate() {
+            let tx = transactions.get(index);
+            self.execution_result(
+                result,
+                &format!("{path}.executionResults[{index}]"),
+                tx,
+                &proofs,
+            );
+        }
+    }
+
+    fn transaction(&mut self, tx: &Value, path: &str, chain_id: Option<u64>) {
+        let tx_type = self.number(tx, path, "type");
+        for field in ["nonce", "gas"] {
+            self.number(tx, path, field);
+        }
+        for field in ["txHash", "gasPrice", "value", "data", "isCreate"] {
+            self.field(tx, path, field);
+        }
+        self.address(tx, path, "from");
+        if tx_type != Some(L1_MESSAGE_TX_TYPE) {
+            let tx_chain_id = self.number(tx, path, "chainId");
+            if let (Some(tx_chain_id), Some(chain_id)) = (tx_chain_id, chain_id) {
+                if tx_chain_id != chain_id {
+                    self.report(
+                        format!("{path}.chainId"),
+                        format!("{tx_chain_id} differs from the trace chainID {chain_id}"),
+                    );
+                }
+            }
+        }
+    }
+
+    fn execution_result(
+        &mut self,
+        result: &Value,
+        path: &str,
+        tx: Option<&Value>,
+        proofs: &Proofs,
+    ) {
+        self.number(result, path, "gas");
+        self.field(result, path, "failed");
+        self.field(result, path, "returnValue");
+        let logs = self.array(result, path, "structLogs");
+        let steps: Vec<_> = logs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, log)| self.step(log, &format!("{path}.structLogs[{index}]")))
+            .collect();
+        if steps.len() != logs.len() {
+            // Consistency can't be checked across malformed steps.
+            return;
+        }
+
+        let to = tx
+            .and_then(|tx| tx.get("to"))
+            .and_then(Value::as_str)
+            .and_then(parse_address);
+        let from = tx
+            .and_then(|tx| tx.get("from"))
+            .and_then(Value::as_str)
+            .and_then(parse_address);
+        for address in from.iter().chain(&to) {
+            proofs.check_account(self, *address, path);
+        }
+
+        let mut unsupported: BTreeMap<u8, (usize, usize)> = BTreeMap::new();
+        // Storage context of each frame, `None` for code being deployed.
+        let mut frames = vec![to];
+        for (index, step) in steps.iter().enumerate() {
+            let step_path = format!("{path}.structLogs[{index}]");
+            if index == 0 && step.depth != 1 {
+                self.report(format!("{step_path}.depth"), "first step is not at depth 1");
+            }
+            if !is_supported(step.op) {
+                let entry = unsupported.entry(step.op).or_insert((index, 0));
+                entry.1 += 1;
+            }
+            let storage = frames.last().copied().flatten();
+            if matches!(step.op, opcode::SLOAD | opcode::SSTORE) {
+                match (storage, step.stack.last()) {
+                    (Some(address), Some(Some(slot))) => {
+                        proofs.check_slot(self, address, *slot, &step_path)
+                    }
+                    (_, None) => self.report(format!("{step_path}.stack"), "stack underflow"),
+                    _ => {}
+                }
+            }
+
+            let Some(next) = steps.get(index + 1) else {
+                continue;
+            };
+            let next_path = format!("{path}.structLogs[{}]", index + 1);
+            if next.depth > step.depth {
+                if next.depth != step.depth + 1 || !is_frame_entry(step.op) {
+                    self.report(
+                        format!("{next_path}.depth"),
+                        format!(
+                            "depth {} after {} at depth {}",
+                            next.depth, step.name, step.depth
+                        ),
+                    );
+                }
+                let target = step
+                    .stack
+                    .len()
+                    .checked_sub(2)
+                    .and_then(|i| step.stack[i])
+                    .map(|address| B160::from_slice(&address.to_be_bytes::<32>()[12..]));
+                let context = match step.op {
+                    opcode::CALL | opcode::STATICCALL => target,
+                    opcode::DELEGATECALL | opcode::CALLCODE => storage,
+                    _ => None,
+                };
+                if let Some(target) = target {
+                    proofs.check_account(self, target, &step_path);
+                }
+                frames.push(context);
+                continue;
+            }
+            if next.depth < step.depth {
+                frames.truncate(next.depth.max(1) as usize);
+                continue;
+            }
+            if is_frame_entry(step.op) || step.error {
+                // Calls into precompiles or code-less accounts stay at the
+                // same depth, with gas and stack depending on the callee.
+                continue;
+            }
+            if next.gas != step.gas.wrapping_sub(step.gas_cost) {
+                self.report(
+                    format!("{next_path}.gas"),
+                    format!(
+                        "{} after {} with gas {} and gasCost {}",
+                        next.gas, step.name, step.gas, step.gas_cost
+                    ),
+                );
+            }
+            let (inputs, outputs) = stack_io(step.op);
+            let expected = step.stack.len().saturating_sub(inputs) + outputs;
+            if next.stack.len() != expected {
+                self.report(
+                    format!("{next_path}.stack"),
+                    format!(
+                        "{} items after {} on {}, expected {expected}",
+                        next.stack.len(),
+                        step.name,
+                        step.stack.len()
+                    ),
+                );
+            }
+        }
+
+        for (op, (first, count)) in unsupported {
+            self.report(
+                format!("{path}.structLogs[{first}].op"),
+                format!(
+                    "{} is outside the supported instruction subset ({count} steps)",
+                    op_name(op)
+                ),
+            );
+        }
+    }
+
+    /// The fields of a struct log the checks use.
+    fn step(&mut self, log: &Value, path: &str) -> Option<Step> {
+        let pc = self.number(log, path, "pc");
+        let gas = self.number(log, path, "gas");
+        let gas_cost = self.number(log, path, "gasCost");
+        let depth = self.number(log, path, "depth");
+        let name = self.field(log, path, "op").and_then(Value::as_str);
+        let op = name.and_then(op_code);
+        if let (Some(name), None) = (name, op) {
+            self.report(format!("{path}.op"), format!("unknown opcode {name}"));
+        }
+        let stack = match log.get("stack") {
+            None | Some(Value::Null) => vec![],
+            Some(Value::Array(stack)) => stack
+                .iter()
+                .map(|value| value.as_str().and_then(parse_word))
+                .collect(),
+            Some(_) => {
+                self.report(format!("{path}.stack"), "not an array");
+                return None;
+            }
+        };
+        pc?;
+        Some(Step {
+            op: op?,
+            name: name?.to_string(),
+            gas: gas?,
+            gas_cost: gas_cost?,
+            depth: depth?,
+            stack,
+            error: log.get("error").map_or(false, |error| !error.is_null()),
+        })
+    }
+}
+
+struct Step {
+    op: u8,
+    name: String,
+    gas: u64,
+    gas_cost: u64,
+    depth: u64,
+    /// Bottom first, `None` for values that aren't hex words.
+    stack: Vec<Option<U256>>,
+    error: bool,
+}
+
+/// Accounts and slots `storageTrace` has proofs for.
+struct Proofs {
+    accounts: BTreeSet<B160>,
+    slots: HashMap<B160, BTreeSet<U256>>,
+}
+
+impl Proofs {
+    fn new(validator: &mut Validator, trace: &Value, path: &str) -> Self {
+        let mut proofs = Self {
+            accounts: BTreeSet::new(),
+            slots: HashMap::new(),
+        };
+        let Some(storage_trace) = validator.field(trace, path, "storageTrace") else {
+            return proofs;
+        };
+        let path = format!("{path}.storageTrace");
+        for field in ["rootBefore", "rootAfter"] {
+            validator.field(storage_trace, &path, field);
+        }
+        if let Some(Value::Object(accounts)) = validator.field(storage_trace, &path, "proofs") {
+            proofs.accounts = accounts
+                .keys()
+                .filter_map(|address| parse_address(address))
+                .collect();
+        }
+        if let Some(Value::Object(accounts)) = storage_trace.get("storageProofs") {
+            for (address, slots) in accounts {
+                let (Some(address), Some(slots)) = (parse_address(address), slots.as_object())
+                else {
+                    continue;
+                };
+                proofs
+                    .slots
+                    .entry(address)
+                    .or_default()
+                    .extend(slots.keys().filter_map(|slot| parse_word(slot)));
+            }
+        }
+        proofs
+    }
+
+    fn check_account(&self, validator: &mut Validator, address: B160, path: &str) {
+        if !self.accounts.contains(&address) {
+            validator.report(
+                path,
+                format!("no storageTrace.proofs entry for touched account {address:?}"),
+            );
+        }
+    }
+
+    fn check_slot(&self, validator: &mut Validator, address: B160, slot: U256, path: &str) {
+        let proven = self
+            .slots
+            .get(&address)
+            .map_or(false, |slots| slots.contains(&slot));
+        if !proven {
+            validator.report(
+                path,
+                format!("no storageTrace.storageProofs entry for slot {slot:#x} of {address:?}"),
+            );
+        }
+    }
+}
+
+fn version_matches(version: &str, expected: &str) -> bool {
+    version == expected
+        || version
+            .strip_prefix(expected)
+            .map_or(false, |rest| rest.starts_with(['.', '-']))
+}
+
+/// Ops after which execution may continue one frame deeper.
+fn is_frame_entry(op: u8) -> bool {
+    matches!(
+        op,
+        opcode::CALL
+            | opcode::CALLCODE
+            | opcode::DELEGATECALL
+            | opcode::STATICCALL
+            | opcode::CREATE
+            | opcode::CREATE2
+    )
+}
+
+fn op_code(name: &str) -> Option<u8> {
+    let name = if name == "KECCAK256" { "SHA3" } else { name };
+    OPCODE_JUMPMAP
+        .iter()
+        .position(|known| *known == Some(name))
+        .map(|op| op as u8)
+}
+
+fn parse_address(address: &str) -> Option<B160> {
+    let bytes = hex::decode(address.trim_start_matches("0x")).ok()?;
+    (bytes.len() == 20).then(|| B160::from_slice(&bytes))
+}
+
+fn parse_word(word: &str) -> Option<U256> {
+    let digits = word.trim_start_matches("0x");
+    if digits.is_empty() {
+        return Some(U256::ZERO);
+    }
+    U256::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn bundled_traces_are_valid() {
+        for path in [
+            "traces/calculation.json",
+            "traces/groth16_verification.json",
+        ] {
+            let problems = validate_trace_file(path, &Expectations::default()).unwrap();
+            assert!(problems.is_empty(), "{path}: {problems:?}");
+        }
+    }
+
+    #[test]
+    fn reports_paths() {
+        let mut json: Value =
+            serde_json::from_str(&fs::read_to_string("traces/calculation.json").unwrap()).unwrap();
+        json["result"]["header"]["number"] = Value::Null;
+        json["result"]["executionResults"][0]["structLogs"][3]["gas"] = serde_json::json!(1);
+        json["result"]["version"] = serde_json::json!("4.3.1-1d824f42");
+
+        let paths: Vec<_> = validate_trace(&json, &Expectations::default())
+            .into_iter()
+            .map(|problem| problem.path)
+            .collect();
+        assert!(paths.contains(&"$.result.header.number".to_string()));
+        assert!(paths.contains(&"$.result.executionResults[0].structLogs[3].gas".to_string()));
+        assert!(paths.contains(&"$.result.version".to_string()));
+    }
+}